@@ -0,0 +1,204 @@
+/// Named things the user can do, listed in the command palette (`Ctrl+P`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Run,
+    Cancel,
+    Reset,
+    ClearInput,
+    CompletePath,
+    InsertLastArg,
+    ToggleLiteralEdit,
+    CopyPlain,
+    CopyRaw,
+    CopyForSharing,
+    CopySnippet,
+    CopyShellLine,
+    SelectLines,
+    OpenPager,
+    OpenEditor,
+    ToggleDryRun,
+    ToggleExecMode,
+    ToggleAppendOutput,
+    ToggleTableView,
+    CycleTimestamps,
+    ToggleFoldRepeats,
+    ToggleScratchRuns,
+    ToggleWatch,
+    ToggleMultiline,
+    ToggleWrap,
+    ZoomStdout,
+    ZoomStderr,
+    StderrTop,
+    StderrEnd,
+    RunLog,
+    SearchHistory,
+    ReloadHistory,
+    ClearHistory,
+    Stats,
+    Pin,
+    Unpin,
+    CaptureStdin,
+    ToggleSplit,
+    ToggleNotes,
+    FocusNext,
+    Quit,
+}
+
+impl Action {
+    /// Every action, in palette order.
+    pub const ALL: [Action; 41] = [
+        Action::Run,
+        Action::Cancel,
+        Action::Reset,
+        Action::ClearInput,
+        Action::CompletePath,
+        Action::InsertLastArg,
+        Action::ToggleLiteralEdit,
+        Action::CopyPlain,
+        Action::CopyRaw,
+        Action::CopyForSharing,
+        Action::CopySnippet,
+        Action::CopyShellLine,
+        Action::SelectLines,
+        Action::OpenPager,
+        Action::OpenEditor,
+        Action::ToggleDryRun,
+        Action::ToggleExecMode,
+        Action::ToggleAppendOutput,
+        Action::ToggleTableView,
+        Action::CycleTimestamps,
+        Action::ToggleFoldRepeats,
+        Action::ToggleScratchRuns,
+        Action::ToggleWatch,
+        Action::ToggleMultiline,
+        Action::ToggleWrap,
+        Action::ZoomStdout,
+        Action::ZoomStderr,
+        Action::StderrTop,
+        Action::StderrEnd,
+        Action::RunLog,
+        Action::SearchHistory,
+        Action::ReloadHistory,
+        Action::ClearHistory,
+        Action::Stats,
+        Action::Pin,
+        Action::Unpin,
+        Action::CaptureStdin,
+        Action::ToggleSplit,
+        Action::ToggleNotes,
+        Action::FocusNext,
+        Action::Quit,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Run => "run command",
+            Action::Cancel => "cancel running command",
+            Action::Reset => "reset: kill, clear output and redraw the screen",
+            Action::ClearInput => "clear input",
+            Action::CompletePath => "complete file name",
+            Action::InsertLastArg => "insert last argument of previous command",
+            Action::ToggleLiteralEdit => "toggle literal edit",
+            Action::CopyPlain => "copy output (plain)",
+            Action::CopyRaw => "copy output (raw ANSI)",
+            Action::CopyForSharing => "copy output for sharing (sanitized)",
+            Action::CopySnippet => "copy command, output and exit status as a snippet",
+            Action::CopyShellLine => "copy command as a shell line with its cwd and env",
+            Action::SelectLines => "select output lines to copy",
+            Action::OpenPager => "open output in pager",
+            Action::OpenEditor => "open output in editor",
+            Action::ToggleDryRun => "toggle dry run",
+            Action::ToggleExecMode => "toggle shell/direct mode",
+            Action::ToggleAppendOutput => "toggle append/replace output",
+            Action::ToggleTableView => "toggle table view of tabular output",
+            Action::CycleTimestamps => "cycle timestamp gutter (off/relative/clock)",
+            Action::ToggleFoldRepeats => "fold repeated lines into one with a count",
+            Action::ToggleScratchRuns => "toggle scratch runs (fresh temporary directory)",
+            Action::ToggleWatch => "toggle watch (re-run on an interval)",
+            Action::ToggleMultiline => "toggle multi-line input",
+            Action::ToggleWrap => "toggle line wrap (focused pane, else stdout)",
+            Action::ZoomStdout => "zoom stdout",
+            Action::ZoomStderr => "zoom stderr",
+            Action::StderrTop => "jump to first stderr line",
+            Action::StderrEnd => "follow end of stderr",
+            Action::RunLog => "show run log",
+            Action::SearchHistory => "search history",
+            Action::ReloadHistory => "reload history saved by other instances",
+            Action::ClearHistory => "clear saved history",
+            Action::Stats => "show output stats",
+            Action::Pin => "pin output",
+            Action::Unpin => "unpin output",
+            Action::CaptureStdin => "capture output as stdin (toggle)",
+            Action::ToggleSplit => "toggle split view",
+            Action::ToggleNotes => "toggle notes pane",
+            Action::FocusNext => "focus next pane (input, stdout, stderr, notes)",
+            Action::Quit => "quit",
+        }
+    }
+
+    /// Key that triggers the action directly, shown next to its name.
+    pub fn key_hint(self) -> &'static str {
+        match self {
+            Action::Run => "Enter",
+            Action::Cancel => "Ctrl+K",
+            Action::Reset => "Ctrl+G",
+            Action::ClearInput => "Ctrl+U",
+            Action::CompletePath => "Alt+/",
+            Action::InsertLastArg => "Alt+.",
+            Action::ToggleLiteralEdit => "Alt+R",
+            Action::CopyPlain => "Alt+Y",
+            Action::CopyRaw => "Alt+Shift+Y",
+            Action::CopyForSharing => "Ctrl+Alt+Y",
+            Action::CopySnippet => "",
+            Action::CopyShellLine => "",
+            Action::SelectLines => "Alt+V",
+            Action::OpenPager => "Alt+O",
+            Action::OpenEditor => "Alt+E",
+            Action::ToggleDryRun => "Ctrl+D",
+            Action::ToggleExecMode => "Ctrl+X",
+            Action::ToggleAppendOutput => "Alt+A",
+            Action::ToggleTableView => "Alt+T",
+            Action::CycleTimestamps => "Alt+G",
+            Action::ToggleFoldRepeats => "Alt+X",
+            Action::ToggleScratchRuns => "",
+            Action::ToggleWatch => "Alt+W",
+            Action::ToggleMultiline => "Alt+M",
+            Action::ToggleWrap => "w (pane focused)",
+            Action::ZoomStdout => "Ctrl+Z",
+            Action::ZoomStderr => "Alt+Z",
+            Action::StderrTop => "Alt+Home",
+            Action::StderrEnd => "Alt+End",
+            Action::RunLog => "Ctrl+L",
+            Action::SearchHistory => "Ctrl+R",
+            Action::ReloadHistory => "Alt+H",
+            Action::ClearHistory => ":clear-history",
+            Action::Stats => "Alt+S",
+            Action::Pin => "Alt+P",
+            Action::Unpin => "Alt+Shift+P",
+            Action::CaptureStdin => "Ctrl+O",
+            Action::ToggleSplit => "Ctrl+W",
+            Action::ToggleNotes => "Alt+N",
+            Action::FocusNext => "Tab",
+            Action::Quit => "Esc",
+        }
+    }
+}
+
+/// Actions whose name fuzzily matches `query`, in palette order.
+pub fn filter(query: &str) -> Vec<Action> {
+    Action::ALL
+        .into_iter()
+        .filter(|action| fuzzy_match(query, action.name()))
+        .collect()
+}
+
+/// Whether the characters of `query` appear in `text` in order, ignoring
+/// case and whitespace in the query (`cpr` matches "copy output (raw ANSI)").
+fn fuzzy_match(query: &str, text: &str) -> bool {
+    let mut text = text.chars().flat_map(char::to_lowercase);
+    query
+        .chars()
+        .filter(|ch| !ch.is_whitespace())
+        .flat_map(char::to_lowercase)
+        .all(|wanted| text.any(|ch| ch == wanted))
+}
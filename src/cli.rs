@@ -1,142 +1,1274 @@
-use crossterm::event::{Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
 use std::time::Duration;
-use crate::execution::WorkerMsg;
-use crate::history::App;
 use crossbeam_channel::Sender;
+use crate::action::{self, Action};
+use crate::error::Result;
+use crate::execution::{ExecMode, WorkerMsg};
+use crate::history::{App, EditMode, Focus, Overlay, Pane, PaneView, PinComparison, Viewer};
+use crate::parser;
+use crate::table::Tabular;
+use crate::utility;
+use crate::workspace::Workspace;
+use crate::Error;
 
-pub fn handle_input(app: &mut App, tx_worker: &Sender<WorkerMsg>) -> anyhow::Result<bool> {
-    if crossterm::event::poll(Duration::from_millis(100))? {
-        if let Event::Key(key) = crossterm::event::read()? {
-            // ignore repeats
-            if key.kind == KeyEventKind::Repeat {
-                return Ok(true);
+/// What waiting for one terminal event came to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InputOutcome {
+    /// Nothing arrived within the timeout.
+    Idle,
+    /// An event was handled; the screen may have changed.
+    Handled,
+    Quit,
+}
+
+/// Wait up to `timeout` for a terminal event and handle it.
+pub fn handle_input(workspace: &mut Workspace, timeout: Duration) -> Result<InputOutcome> {
+    if !crossterm::event::poll(timeout).map_err(Error::Terminal)? {
+        return Ok(InputOutcome::Idle);
+    }
+    let event = crossterm::event::read().map_err(Error::Terminal)?;
+    Ok(handle_event(workspace, event))
+}
+
+/// Handle one terminal event, real or synthetic.
+pub fn handle_event(workspace: &mut Workspace, event: Event) -> InputOutcome {
+    match event {
+        Event::Key(key) if !handle_key(workspace, key) => InputOutcome::Quit,
+        // anything else, like a resize, just needs a redraw
+        _ => InputOutcome::Handled,
+    }
+}
+
+/// Handle one key press. Returns false to quit.
+fn handle_key(workspace: &mut Workspace, key: KeyEvent) -> bool {
+    // workspace-level keys, handled before the focused pane sees them
+    if key.kind != KeyEventKind::Repeat && workspace.active().app.overlay.is_none() {
+        match key.code {
+            KeyCode::Char('w') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                workspace.toggle_split();
+                return true;
             }
-            match key.code {
-                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                    return Ok(false);
-                }
-                KeyCode::Char('a') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                    app.move_cursor_home();
-                }
-                KeyCode::Char('e') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                    app.move_cursor_end();
-                }
-                KeyCode::Esc => return Ok(false),
-                KeyCode::Enter => {
-                    let cmd = app.input.clone();
-                    if app.prepare_run(&cmd, true) {
-                        tx_worker.send(WorkerMsg::Run(cmd)).ok();
-                    }
-                }
-                KeyCode::Char('u') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                    app.clear_input();
-                }
-                KeyCode::Backspace => {
-                    app.delete_backward();
-                }
-                KeyCode::Delete => {
-                    app.delete_forward();
-                }
-                KeyCode::Left => {
-                    app.move_cursor_left();
-                }
-                KeyCode::Right => {
-                    app.move_cursor_right();
-                }
-                KeyCode::Home => {
-                    app.move_cursor_home();
-                }
-                KeyCode::End => {
-                    app.move_cursor_end();
-                }
-                KeyCode::Up => {
-                    app.history_prev();
-                }
-                KeyCode::Down => {
-                    app.history_next();
-                }
-                KeyCode::Char(ch) => {
-                    if key.modifiers.contains(KeyModifiers::ALT)
-                        || key.modifiers.contains(KeyModifiers::CONTROL)
-                    {
-                        return Ok(true);
-                    }
-                    app.insert_char(ch);
-                }
-                _ => {}
+            KeyCode::Tab => {
+                workspace.focus_next();
+                return true;
             }
+            _ => {}
+        }
+    }
+    let app = &mut workspace.active_mut().app;
+    // held keys only repeat for motion/deletion, never for e.g. Enter
+    if key.kind == KeyEventKind::Repeat && !(app.config.key_repeat && repeatable(key.code)) {
+        return true;
+    }
+    if app.selection.is_some() {
+        handle_selection_key(app, key.code);
+        return true;
+    }
+    if app.overlay.is_some() {
+        if let Some(action) = handle_overlay_key(app, key.code) {
+            return apply_action(workspace, action);
+        }
+        return true;
+    }
+    if app.focus == Focus::Notes {
+        return handle_notes_key(app, key);
+    }
+    if let Some(pane) = app.focused_pane() {
+        if key
+            .modifiers
+            .intersection(KeyModifiers::CONTROL | KeyModifiers::ALT)
+            .is_empty()
+            && handle_scroll_key(app, pane, key.code)
+        {
+            return true;
+        }
+    }
+    if app.config.vi_mode
+        && app.edit_mode == EditMode::Normal
+        && key
+            .modifiers
+            .intersection(KeyModifiers::CONTROL | KeyModifiers::ALT)
+            .is_empty()
+        && handle_normal_mode_key(app, key.code)
+    {
+        return true;
+    }
+    match map_key(&key, app) {
+        Some(action) => apply_action(workspace, action),
+        None => {
+            edit_input(app, key);
+            true
+        }
+    }
+}
+
+/// The named action a key press in the input triggers, if any. Pure, so
+/// key bindings can be checked without a terminal; keys that edit the
+/// input are left to `edit_input`.
+pub fn map_key(key: &KeyEvent, app: &App) -> Option<Action> {
+    let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+    let alt = key.modifiers.contains(KeyModifiers::ALT);
+    let action = match key.code {
+        KeyCode::Char('c') if ctrl => Action::Quit,
+        KeyCode::Char('d') if ctrl => Action::ToggleDryRun,
+        KeyCode::Char('g') if ctrl => Action::Reset,
+        KeyCode::Char('z') if ctrl => Action::ZoomStdout,
+        KeyCode::Char('k') if ctrl => Action::Cancel,
+        KeyCode::Char('o') if ctrl => Action::CaptureStdin,
+        KeyCode::Char('l') if ctrl => Action::RunLog,
+        KeyCode::Char('r') if ctrl => Action::SearchHistory,
+        KeyCode::Char('x') if ctrl => Action::ToggleExecMode,
+        KeyCode::Char('u') if ctrl => Action::ClearInput,
+        KeyCode::Char('y') if ctrl && alt => Action::CopyForSharing,
+        KeyCode::Char('p') if alt => Action::Pin,
+        KeyCode::Char('P') if alt => Action::Unpin,
+        KeyCode::Char('y') if alt => Action::CopyPlain,
+        KeyCode::Char('Y') if alt => Action::CopyRaw,
+        KeyCode::Char('h') if alt => Action::ReloadHistory,
+        KeyCode::Char('n') if alt => Action::ToggleNotes,
+        KeyCode::Char('v') if alt => Action::SelectLines,
+        KeyCode::Char('s') if alt => Action::Stats,
+        KeyCode::Char('z') if alt => Action::ZoomStderr,
+        KeyCode::Char('o') if alt => Action::OpenPager,
+        KeyCode::Char('e') if alt => Action::OpenEditor,
+        KeyCode::Char('m') if alt => Action::ToggleMultiline,
+        KeyCode::Char('w') if alt => Action::ToggleWatch,
+        KeyCode::Char('a') if alt => Action::ToggleAppendOutput,
+        KeyCode::Char('t') if alt => Action::ToggleTableView,
+        KeyCode::Char('g') if alt => Action::CycleTimestamps,
+        KeyCode::Char('x') if alt => Action::ToggleFoldRepeats,
+        KeyCode::Char('.') if alt => Action::InsertLastArg,
+        KeyCode::Char('r') if alt => Action::ToggleLiteralEdit,
+        KeyCode::Char('/') if alt => Action::CompletePath,
+        KeyCode::Home if alt => Action::StderrTop,
+        KeyCode::End if alt => Action::StderrEnd,
+        // in vi mode `Esc` leaves insert mode instead
+        KeyCode::Esc if !app.config.vi_mode => Action::Quit,
+        KeyCode::Enter if !app.multiline || alt => Action::Run,
+        _ => return None,
+    };
+    Some(action)
+}
+
+/// Edit the input for a key press without a named action: typing,
+/// deleting, moving the cursor and walking the history.
+fn edit_input(app: &mut App, key: KeyEvent) {
+    let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+    let alt = key.modifiers.contains(KeyModifiers::ALT);
+    match key.code {
+        KeyCode::Char('a') if ctrl => app.move_cursor_home(),
+        KeyCode::Char('e') if ctrl => app.move_cursor_end(),
+        KeyCode::Char('t') if ctrl => app.transpose_chars(),
+        KeyCode::Char('p') if ctrl => app.open_palette(),
+        KeyCode::Char('b') if alt => app.move_word_left(),
+        KeyCode::Char('f') if alt => app.move_word_right(),
+        KeyCode::Char('d') if alt => app.delete_word_forward(),
+        KeyCode::Char('u') if alt => app.upcase_word(),
+        KeyCode::Char('l') if alt => app.downcase_word(),
+        KeyCode::Char('c') if alt => app.capitalize_word(),
+        KeyCode::Esc => app.enter_normal_mode(),
+        KeyCode::Enter => app.insert_char('\n'),
+        KeyCode::Backspace => app.delete_backward(),
+        KeyCode::Delete => app.delete_forward(),
+        KeyCode::Left if ctrl => app.move_word_left(),
+        KeyCode::Right if ctrl => app.move_word_right(),
+        KeyCode::Left => app.move_cursor_left(),
+        KeyCode::Right => app.move_cursor_right(),
+        KeyCode::Home => app.move_cursor_home(),
+        KeyCode::End => app.move_cursor_end(),
+        KeyCode::Up => app.line_or_history(-1),
+        KeyCode::Down => app.line_or_history(1),
+        // unbound shortcuts type nothing
+        KeyCode::Char(_) if ctrl || alt => {}
+        KeyCode::Char(ch) => app.insert_char(ch),
+        _ => {}
+    }
+}
+
+/// Keys for the open overlay. Returns the action picked in the palette.
+fn handle_overlay_key(app: &mut App, code: KeyCode) -> Option<Action> {
+    match app.overlay {
+        Some(Overlay::RunLog { .. }) => match code {
+            KeyCode::Up => app.run_log_move(-1),
+            KeyCode::Down => app.run_log_move(1),
+            KeyCode::Enter => app.recall_run_log_entry(),
+            KeyCode::Esc | KeyCode::Char('l') => app.close_overlay(),
+            _ => {}
+        },
+        Some(Overlay::Palette { .. }) => match code {
+            KeyCode::Up => app.palette_move(-1),
+            KeyCode::Down => app.palette_move(1),
+            KeyCode::Enter => return app.take_palette_choice(),
+            KeyCode::Esc => app.close_overlay(),
+            KeyCode::Backspace => app.palette_edit(None),
+            KeyCode::Char(ch) => app.palette_edit(Some(ch)),
+            _ => {}
+        },
+        Some(Overlay::HistorySearch { .. }) => match code {
+            KeyCode::Up => app.history_search_move(-1),
+            KeyCode::Down => app.history_search_move(1),
+            KeyCode::Enter => app.accept_history_search(),
+            KeyCode::Esc => app.close_overlay(),
+            KeyCode::Backspace => app.history_search_edit(None),
+            KeyCode::Char(ch) => app.history_search_edit(Some(ch)),
+            _ => {}
+        },
+        Some(Overlay::ConfirmClearHistory) => app.resolve_clear_history(code == KeyCode::Char('y')),
+        Some(Overlay::RestoreDraft(_)) => {
+            app.resolve_draft(matches!(code, KeyCode::Char('y') | KeyCode::Enter))
+        }
+        // informational popups close on any key
+        Some(Overlay::DryRun(_) | Overlay::Stats(_)) | None => app.close_overlay(),
+    }
+    None
+}
+
+/// Keys in line-selection mode: move the cursor, copy, or cancel.
+fn handle_selection_key(app: &mut App, code: KeyCode) {
+    match code {
+        KeyCode::Up | KeyCode::Char('k') => app.move_selection(-1),
+        KeyCode::Down | KeyCode::Char('j') => app.move_selection(1),
+        KeyCode::PageUp => app.move_selection(-10),
+        KeyCode::PageDown => app.move_selection(10),
+        KeyCode::Enter | KeyCode::Char('y') => app.copy_selection(),
+        KeyCode::Esc => app.cancel_selection(),
+        _ => {}
+    }
+}
+
+/// Keys while the notes pane has focus: they edit the notes, except
+/// `Ctrl+C` (quit), `Esc` (back to the input) and `Alt+N` (hide the pane).
+/// Returns false to quit.
+fn handle_notes_key(app: &mut App, key: KeyEvent) -> bool {
+    match key.code {
+        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return false,
+        KeyCode::Char('n') if key.modifiers.contains(KeyModifiers::ALT) => app.toggle_notes(),
+        KeyCode::Char(ch)
+            if key
+                .modifiers
+                .intersection(KeyModifiers::CONTROL | KeyModifiers::ALT)
+                .is_empty() =>
+        {
+            app.notes.insert(ch)
+        }
+        KeyCode::Enter => app.notes.insert('\n'),
+        KeyCode::Backspace => app.notes.delete_backward(),
+        KeyCode::Delete => app.notes.delete_forward(),
+        KeyCode::Left => app.notes.move_left(),
+        KeyCode::Right => app.notes.move_right(),
+        KeyCode::Up => app.notes.move_line(-1),
+        KeyCode::Down => app.notes.move_line(1),
+        KeyCode::Home => app.notes.move_home(),
+        KeyCode::End => app.notes.move_end(),
+        KeyCode::Esc => app.focus = Focus::Input,
+        _ => {}
+    }
+    true
+}
+
+/// Keys in vi normal mode. Returns false for keys that work the same in
+/// both modes (`Enter`, `↑`/`↓`); everything else is a vi command or
+/// ignored.
+fn handle_normal_mode_key(app: &mut App, code: KeyCode) -> bool {
+    let pending = app.vi_pending.take();
+    match (pending, code) {
+        (Some('d'), KeyCode::Char('d')) => app.clear_input(),
+        (Some('d'), KeyCode::Char('w')) => app.delete_word_forward(),
+        (Some('d'), KeyCode::Char('$')) => app.delete_to_line_end(),
+        (Some(_), _) => {}
+        (None, KeyCode::Char('h') | KeyCode::Left) => app.move_cursor_left(),
+        (None, KeyCode::Char('l') | KeyCode::Right) => app.move_cursor_right(),
+        (None, KeyCode::Char('w')) => app.move_word_right(),
+        (None, KeyCode::Char('b')) => app.move_word_left(),
+        (None, KeyCode::Char('0') | KeyCode::Home) => app.move_cursor_home(),
+        (None, KeyCode::Char('$') | KeyCode::End) => app.move_cursor_end(),
+        (None, KeyCode::Char('j')) => app.line_or_history(1),
+        (None, KeyCode::Char('k')) => app.line_or_history(-1),
+        (None, KeyCode::Char('x') | KeyCode::Delete) => app.delete_forward(),
+        (None, KeyCode::Char('X')) => app.delete_backward(),
+        (None, KeyCode::Char('D')) => app.delete_to_line_end(),
+        (None, KeyCode::Char('d')) => app.vi_pending = Some('d'),
+        (None, KeyCode::Char('i')) => app.enter_insert_mode(),
+        (None, KeyCode::Char('a')) => {
+            app.move_cursor_right();
+            app.enter_insert_mode();
+        }
+        (None, KeyCode::Char('I')) => {
+            app.move_cursor_home();
+            app.enter_insert_mode();
+        }
+        (None, KeyCode::Char('A')) => {
+            app.move_cursor_end();
+            app.enter_insert_mode();
+        }
+        (None, KeyCode::Enter | KeyCode::Up | KeyCode::Down) => return false,
+        _ => {}
+    }
+    true
+}
+
+/// Keys while an output pane has focus: navigation scrolls it, editing
+/// keys are swallowed. Returns false for keys that work anywhere.
+fn handle_scroll_key(app: &mut App, pane: Pane, code: KeyCode) -> bool {
+    match code {
+        KeyCode::Up | KeyCode::Char('k') => app.scroll(pane, -1),
+        KeyCode::Down | KeyCode::Char('j') => app.scroll(pane, 1),
+        KeyCode::PageUp => app.scroll(pane, -10),
+        KeyCode::PageDown => app.scroll(pane, 10),
+        KeyCode::Home | KeyCode::Char('g') => app.scroll_to_top(pane),
+        KeyCode::End | KeyCode::Char('G') => app.scroll_to_end(pane),
+        KeyCode::Left | KeyCode::Char('h') => app.scroll_sideways(pane, -8),
+        KeyCode::Right | KeyCode::Char('l') => app.scroll_sideways(pane, 8),
+        KeyCode::Char('w') => app.toggle_wrap(pane),
+        KeyCode::Enter | KeyCode::Esc => return false,
+        _ => {}
+    }
+    true
+}
+
+/// Perform `action` on the focused session. Returns false to quit.
+fn apply_action(workspace: &mut Workspace, action: Action) -> bool {
+    let session = workspace.active_mut();
+    let app = &mut session.app;
+    match action {
+        Action::Run => run_input(app, &session.tx_worker),
+        Action::Cancel => cancel_run(app, &session.tx_worker),
+        Action::Reset => {
+            cancel_run(app, &session.tx_worker);
+            app.reset();
         }
+        Action::ClearInput => app.clear_input(),
+        Action::CompletePath => app.complete_path(),
+        Action::InsertLastArg => app.insert_last_arg(),
+        Action::ToggleLiteralEdit => app.toggle_literal_edit(),
+        Action::ToggleAppendOutput => app.toggle_append_output(),
+        Action::ToggleTableView => app.toggle_table_view(),
+        Action::CycleTimestamps => app.cycle_timestamps(),
+        Action::ToggleFoldRepeats => app.toggle_fold_repeats(),
+        Action::ToggleScratchRuns => app.toggle_scratch_runs(),
+        Action::ToggleWatch => app.toggle_watch(),
+        Action::ToggleMultiline => app.toggle_multiline(),
+        Action::ToggleWrap => app.toggle_wrap(app.focused_pane().unwrap_or(Pane::Stdout)),
+        Action::OpenPager => app.request_viewer(Viewer::Pager),
+        Action::OpenEditor => app.request_viewer(Viewer::Editor),
+        Action::CopyPlain => app.copy_output(false),
+        Action::CopyRaw => app.copy_output(true),
+        Action::CopyForSharing => app.copy_for_sharing(false),
+        Action::CopySnippet => app.copy_for_sharing(true),
+        Action::CopyShellLine => app.copy_shell_line(),
+        Action::SelectLines => app.start_selection(),
+        Action::ToggleDryRun => app.toggle_dry_run(),
+        Action::ToggleExecMode => app.toggle_exec_mode(),
+        Action::ZoomStdout => app.toggle_zoom(Pane::Stdout),
+        Action::ZoomStderr => app.toggle_zoom(Pane::Stderr),
+        Action::StderrTop => app.scroll_to_top(Pane::Stderr),
+        Action::StderrEnd => app.scroll_to_end(Pane::Stderr),
+        Action::RunLog => app.toggle_run_log(),
+        Action::SearchHistory => app.open_history_search(),
+        Action::ReloadHistory => app.reload_history(),
+        Action::ClearHistory => app.ask_clear_history(),
+        Action::Stats => app.show_stats(),
+        Action::Pin => app.pin_output(),
+        Action::Unpin => app.unpin_output(),
+        Action::CaptureStdin => app.toggle_captured_stdin(),
+        Action::ToggleNotes => app.toggle_notes(),
+        Action::ToggleSplit => workspace.toggle_split(),
+        Action::FocusNext => workspace.focus_next(),
+        Action::Quit => return false,
+    }
+    true
+}
+
+fn cancel_run(app: &mut App, tx_worker: &Sender<WorkerMsg>) {
+    if app.request_cancel() {
+        tx_worker.send(WorkerMsg::Cancel).ok();
     }
-    Ok(true)
 }
 
-pub fn render_ui<B: ratatui::backend::Backend>(f: &mut ratatui::Frame, app: &App) {
+/// Run the input (minus any auto-run trigger and comment) as a manual run.
+pub fn run_input(app: &mut App, tx_worker: &Sender<WorkerMsg>) {
+    let input = app.strip_trigger(&app.input).unwrap_or(&app.input);
+    let cmd = app.without_comment(input).to_string();
+    if app.run_pseudo_command(&cmd) {
+        if let Some(spec) = app.pipe_request.take() {
+            tx_worker.send(WorkerMsg::Run(spec)).ok();
+        }
+        return;
+    }
+    if app.prepare_run(&cmd, true) {
+        tx_worker.send(WorkerMsg::Run(app.run_spec(&cmd))).ok();
+    }
+}
+
+fn repeatable(code: KeyCode) -> bool {
+    matches!(
+        code,
+        KeyCode::Left
+            | KeyCode::Right
+            | KeyCode::Up
+            | KeyCode::Down
+            | KeyCode::PageUp
+            | KeyCode::PageDown
+            | KeyCode::Backspace
+            | KeyCode::Delete
+    )
+}
+
+/// Draw every session. Returns where output text ended up on screen, for
+/// `hyperlinks`.
+pub fn render_ui(f: &mut ratatui::Frame, workspace: &Workspace) -> Vec<ratatui::layout::Rect> {
     use ratatui::layout::{Constraint, Direction, Layout};
-    use ratatui::style::{Modifier, Style};
-    use ratatui::text::{Line, Span};
-    use ratatui::widgets::{Block, Borders, Paragraph, Wrap};
-
-    let chunks = Layout::default()
-        .direction(Direction::Vertical)
-        .constraints(
-            [
-                Constraint::Length(3),
-                Constraint::Min(6),
-                Constraint::Length(6),
-            ]
-            .as_ref(),
-        )
+
+    let count = workspace.sessions.len() as u32;
+    let compact_height = workspace.active().app.config.compact_height;
+    let compact = f.size().height < compact_height;
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(vec![Constraint::Ratio(1, count); count as usize])
         .split(f.size());
+    let mut output_areas = Vec::new();
+    for (idx, session) in workspace.sessions.iter().enumerate() {
+        let focused = idx == workspace.active;
+        output_areas.extend(render_pane(f, &session.app, columns[idx], focused, compact));
+    }
+    output_areas
+}
 
-    // Input
-    let input = Paragraph::new(app.input.as_str())
-        .block(Block::default().title("pipeline").borders(Borders::ALL))
-        .wrap(Wrap { trim: false });
-    f.render_widget(input, chunks[0]);
+/// Draw one session into `area`. In the `focused` session, the border of
+/// the pane holding keyboard focus is highlighted. `compact` is for short
+/// terminals: a single borderless input row, and stderr reduced to its
+/// last line above the status row, leaving the rest to stdout. Returns
+/// the areas showing output, unless an overlay covers them.
+fn render_pane(
+    f: &mut ratatui::Frame,
+    app: &App,
+    area: ratatui::layout::Rect,
+    focused: bool,
+    compact: bool,
+) -> Vec<ratatui::layout::Rect> {
+    use ratatui::layout::{Alignment, Constraint, Direction, Layout, Margin};
+    use ratatui::style::{Color, Modifier, Style};
+    use ratatui::text::{Line, Span};
+    use ratatui::widgets::block::Title;
+    use ratatui::widgets::{Block, Borders, Gauge, Paragraph, Wrap};
+    use unicode_width::UnicodeWidthStr;
 
-    // Output
-    let out_block = Block::default().title("stdout").borders(Borders::ALL);
-    let out_area = chunks[1];
-    let stdout_lines = app.stdout_view(out_area);
-    let out = if stdout_lines.is_empty() {
-        Paragraph::new(Line::from("(waiting for output...)"))
-            .block(out_block)
-            .wrap(Wrap { trim: false })
+    // a progress gauge below everything while the output shows a percentage
+    let progress = app.progress();
+    let (area, gauge_area) = match progress {
+        Some(_) => {
+            let rows = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Min(0), Constraint::Length(1)].as_ref())
+                .split(area);
+            (rows[0], Some(rows[1]))
+        }
+        None => (area, None),
+    };
+
+    // the notes pane takes the right third while shown
+    let (area, notes_area) = if app.notes.visible && app.zoom.is_none() {
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Ratio(2, 3), Constraint::Ratio(1, 3)].as_ref())
+            .split(area);
+        (columns[0], Some(columns[1]))
     } else {
-        Paragraph::new(stdout_lines)
-            .block(out_block)
-            .wrap(Wrap { trim: false })
+        (area, None)
+    };
+
+    // the input box grows with a multi-line input, up to a limit
+    let input_rows = (app.input.matches('\n').count() + 1).min(MAX_INPUT_ROWS);
+
+    // compact: the row stderr is reduced to
+    let mut err_row = None;
+    // zoomed: a single pane takes everything except the status row
+    let (input_area, out_area, err_area, status_area) = match app.zoom {
+        Some(pane) => {
+            let chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Min(3), Constraint::Length(1)].as_ref())
+                .split(area);
+            match pane {
+                Pane::Stdout => (None, Some(chunks[0]), None, chunks[1]),
+                Pane::Stderr => (None, None, Some(chunks[0]), chunks[1]),
+            }
+        }
+        None if compact => {
+            let chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints(
+                    [
+                        Constraint::Length(1),
+                        Constraint::Min(0),
+                        Constraint::Length(1),
+                        Constraint::Length(1),
+                    ]
+                    .as_ref(),
+                )
+                .split(area);
+            err_row = Some(chunks[2]);
+            (Some(chunks[0]), Some(chunks[1]), None, chunks[3])
+        }
+        None => {
+            let chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints(
+                    [
+                        Constraint::Length(input_rows as u16 + 2),
+                        Constraint::Min(6),
+                        Constraint::Length(6),
+                    ]
+                    .as_ref(),
+                )
+                .split(area);
+            let bottom_chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Min(4), Constraint::Length(1)].as_ref())
+                .split(chunks[2]);
+            (
+                Some(chunks[0]),
+                Some(chunks[1]),
+                Some(bottom_chunks[0]),
+                bottom_chunks[1],
+            )
+        }
+    };
+
+    let placeholder_style = Style::default().add_modifier(Modifier::DIM);
+    let border_style = |part: Focus| {
+        if focused && app.focus == part {
+            Style::default().fg(Color::Cyan)
+        } else {
+            Style::default()
+        }
     };
-    f.render_widget(out, out_area);
-
-    // Stderr + Status
-    let bottom_chunks = Layout::default()
-        .direction(Direction::Vertical)
-        .constraints([Constraint::Min(4), Constraint::Length(1)].as_ref())
-        .split(chunks[2]);
-
-    let err_block = Block::default().title("stderr").borders(Borders::ALL);
-    let stderr_lines = app.stderr_view(bottom_chunks[0]);
-    let err = if stderr_lines.is_empty() {
-        Paragraph::new(Line::from("<no stderr>")).block(err_block)
+
+    // Input: scrolls both ways to keep the cursor visible
+    let (cursor_row, cursor_col) = app.cursor_position();
+    let (cursor_row, cursor_col) = (cursor_row as u16, cursor_col as u16);
+    // the input's border width, none in the compact layout
+    let inset = if compact { 0 } else { 1 };
+    if let Some(area) = input_area {
+        let scroll = input_scroll(
+            cursor_col,
+            area.width.saturating_sub(2 * inset),
+            app.input_scroll.get(),
+        );
+        app.input_scroll.set(scroll);
+        let vscroll = input_scroll(
+            cursor_row,
+            area.height.saturating_sub(2 * inset),
+            app.input_vscroll.get(),
+        );
+        app.input_vscroll.set(vscroll);
+        let labels = &app.config.labels;
+        let title = match &app.rc_file {
+            Some(path) => format!("{} ({})", labels.input_title, path.display()),
+            None => labels.input_title.clone(),
+        };
+        let mut input = Paragraph::new(app.input.as_str()).scroll((vscroll, scroll));
+        if !compact {
+            input = input.block(
+                Block::default()
+                    .title(title)
+                    .borders(Borders::ALL)
+                    .border_style(border_style(Focus::Input)),
+            );
+        }
+        f.render_widget(input, area);
+    }
+
+    // the previous run's output, kept on screen until the new run writes
+    let content_style = if app.stale_output {
+        Style::default().add_modifier(Modifier::DIM)
     } else {
-        Paragraph::new(stderr_lines)
-            .block(err_block)
-            .wrap(Wrap { trim: false })
+        Style::default()
     };
-    f.render_widget(err, bottom_chunks[0]);
+
+    // Output
+    if let Some(out_area) = out_area {
+        let title = match &app.pinned {
+            Some(pin) => {
+                let verdict = match pin.comparison {
+                    None => "pinned".to_string(),
+                    Some(PinComparison::Matches) => "matches pin".to_string(),
+                    Some(PinComparison::DiffersAt(line)) => {
+                        format!("differs from pin at line {line}")
+                    }
+                };
+                format!(
+                    "{} [{verdict}: {}]",
+                    app.config.labels.stdout_title, pin.label
+                )
+            }
+            None => app.config.labels.stdout_title.clone(),
+        };
+        let tabular = app.stdout_table(out_area);
+        let title = match tabular {
+            Some(_) => title + " [table]",
+            None => {
+                title + if app.fold_repeats { " [fold]" } else { "" } + no_wrap(&app.stdout_pane)
+            }
+        };
+        // the pipeline's stages on the right, in what room the title leaves
+        let room =
+            (out_area.width as usize).saturating_sub(UnicodeWidthStr::width(title.as_str()) + 6);
+        let stages = app
+            .started_cmd
+            .as_deref()
+            .filter(|_| app.is_running())
+            .or(app.output_cmd.as_deref())
+            .map(parser::pipeline_stages)
+            .filter(|stages| stages.len() > 1 && room > 1)
+            .map(|stages| {
+                let programs: Vec<&str> = stages.into_iter().map(parser::stage_program).collect();
+                Title::from(Span::styled(
+                    format!(" {} ", utility::breadcrumb(&programs, room - 2)),
+                    placeholder_style,
+                ))
+                .alignment(Alignment::Right)
+            });
+        let mut out_block = Block::default()
+            .title(title)
+            .borders(Borders::ALL)
+            .border_style(border_style(Focus::Stdout));
+        if let Some(stages) = stages {
+            out_block = out_block.title(stages);
+        }
+        if let Some(tabular) = tabular {
+            f.render_widget(
+                table_widget(tabular, content_style).block(out_block),
+                out_area,
+            );
+        } else {
+            let stdout_lines = app.stdout_view(out_area);
+            let out = if stdout_lines.is_empty() {
+                Paragraph::new(Line::styled(app.stdout_placeholder(), placeholder_style))
+                    .block(out_block)
+                    .wrap(Wrap { trim: false })
+            } else {
+                fit_to_view(
+                    Paragraph::new(stdout_lines)
+                        .block(out_block)
+                        .style(content_style),
+                    &app.stdout_pane,
+                )
+            };
+            f.render_widget(out, out_area);
+        }
+    }
+
+    // Stderr
+    if let Some(err_area) = err_area {
+        let err_block = Block::default()
+            .title(app.config.labels.stderr_title.clone() + no_wrap(&app.stderr_pane))
+            .borders(Borders::ALL)
+            .border_style(border_style(Focus::Stderr));
+        let stderr_lines = app.stderr_view(err_area);
+        let err = if stderr_lines.is_empty() {
+            Paragraph::new(Line::styled(app.stderr_placeholder(), placeholder_style))
+                .block(err_block)
+        } else {
+            fit_to_view(
+                Paragraph::new(stderr_lines)
+                    .block(err_block)
+                    .style(content_style),
+                &app.stderr_pane,
+            )
+        };
+        f.render_widget(err, err_area);
+    }
+    if let Some(notes_area) = notes_area {
+        let (row, col) = app.notes.cursor_position();
+        let (hscroll, vscroll) = app.notes.scroll.get();
+        let hscroll = input_scroll(col as u16, notes_area.width.saturating_sub(2), hscroll);
+        let vscroll = input_scroll(row as u16, notes_area.height.saturating_sub(2), vscroll);
+        app.notes.scroll.set((hscroll, vscroll));
+        let block = Block::default()
+            .title(app.config.labels.notes_title.as_str())
+            .borders(Borders::ALL)
+            .border_style(border_style(Focus::Notes));
+        let notes = if app.notes.text.is_empty() && app.focus != Focus::Notes {
+            Paragraph::new(Line::styled("(Alt+N to write notes)", placeholder_style))
+        } else {
+            Paragraph::new(app.notes.text.as_str()).scroll((vscroll, hscroll))
+        };
+        f.render_widget(notes.block(block), notes_area);
+    }
+
+    if let Some(row) = err_row {
+        let label = format!("{}: ", app.config.labels.stderr_title);
+        let label_width = UnicodeWidthStr::width(label.as_str()) as u16;
+        let label_style = if focused && app.focus == Focus::Stderr {
+            Style::default().fg(Color::Cyan)
+        } else {
+            Style::default().add_modifier(Modifier::BOLD)
+        };
+        let mut spans = vec![Span::styled(label, label_style)];
+        match app.stderr_row(row.width.saturating_sub(label_width)) {
+            Some(line) => spans.extend(line.spans),
+            None => spans.push(Span::styled(app.stderr_placeholder(), placeholder_style)),
+        }
+        f.render_widget(Paragraph::new(Line::from(spans)).style(content_style), row);
+    }
 
     let status = Paragraph::new(Line::from(vec![
         Span::styled("Status: ", Style::default().add_modifier(Modifier::BOLD)),
-        Span::raw(&app.status_line),
+        match app.silent_run_hint().or_else(|| app.auto_run_hint()) {
+            Some(hint) => Span::styled(hint, Style::default().fg(Color::Yellow)),
+            None => Span::raw(&app.status_line),
+        },
+        Span::raw(
+            app.child_pid
+                .map(|pid| format!("  pid {pid}"))
+                .unwrap_or_default(),
+        ),
+        Span::raw(match (app.stdout_bytes, app.stderr_bytes) {
+            (0, 0) => String::new(),
+            (stdout, stderr) => format!(
+                "  stdout {}  stderr {}",
+                utility::format_bytes(stdout),
+                utility::format_bytes(stderr)
+            ),
+        }),
+        Span::raw(match app.stdout_dropped + app.stderr_dropped {
+            0 => String::new(),
+            dropped => format!("  [{} lines dropped]", utility::format_count(dropped)),
+        }),
+        Span::raw(if app.dry_run_armed { "  [dry-run]" } else { "" }),
+        Span::raw(if app.zoom.is_some() { "  [zoom]" } else { "" }),
+        Span::raw(if app.selection.is_some() {
+            "  [select: ↑/↓ extend, y copy, Esc cancel]"
+        } else {
+            ""
+        }),
+        Span::raw(if app.captured_stdin.is_some() {
+            "  [stdin]"
+        } else {
+            ""
+        }),
+        Span::raw(if app.exec_mode == ExecMode::Direct {
+            "  [direct]"
+        } else {
+            ""
+        }),
+        Span::raw(
+            app.watch
+                .map(|interval| format!("  [watch {}]", utility::format_interval(interval)))
+                .unwrap_or_default(),
+        ),
+        Span::raw(
+            app.column_cut
+                .as_ref()
+                .map(|cut| format!("  [cut {}]", cut.spec))
+                .unwrap_or_default(),
+        ),
+        Span::raw(if app.append_output {
+            "  [append]"
+        } else {
+            ""
+        }),
+        Span::raw(match &app.scratch_dir {
+            Some(dir) => format!("  [scratch: {}]", dir.display()),
+            None if app.scratch_runs => "  [scratch]".into(),
+            None => String::new(),
+        }),
+        Span::raw(if app.multiline { "  [multi-line]" } else { "" }),
+        Span::raw(match app.edit_mode {
+            EditMode::Normal if app.config.vi_mode => "  [normal]",
+            EditMode::Insert if app.config.vi_mode => "  [insert]",
+            _ => "",
+        }),
+        Span::raw(if app.literal_input() {
+            "  [literal]"
+        } else {
+            ""
+        }),
         Span::raw("   "),
-        Span::raw("Keys: Enter=run  Esc=quit  Ctrl+u=clear  ↑/↓=history  ←/→=move  Home/End"),
+        Span::raw("Keys: Enter=run  Ctrl+k=cancel  Ctrl+g=reset  Esc=quit (vi_mode: normal mode)  Ctrl+u=clear  Ctrl+d=dry-run  Ctrl+x=shell/direct  Ctrl+z/Alt+z=zoom  Ctrl+l=run log  Ctrl+r=search history  Alt+h=reload history  Ctrl+p=commands  Ctrl+o=capture stdin  Alt+s=stats  Alt+p/P=pin/unpin  Alt+y/Y=copy plain/raw  Ctrl+Alt+y=copy for sharing  Alt+v=select lines  Ctrl+w=split  Tab=focus (input/stdout/stderr/notes)  Alt+n=notes  ↑/↓ PgUp/PgDn Home/End=scroll focused output  w=wrap, ←/→=sideways  ↑/↓=history  ←/→=move  Ctrl+←/→ Alt+b/f=word  Alt+d=del word  Ctrl+t=transpose  Alt+u/l/c=case  Alt+/=complete file  Alt+.=last arg  Alt+r=literal edit  Alt+a=append output  Alt+t=table view  Alt+g=timestamps  Alt+x=fold repeats  Alt+w=watch  Alt+m=multi-line (Alt+Enter runs)  Alt+o/e=pager/editor  Home/End  Alt+Home/End=stderr top/end"),
     ]));
-    f.render_widget(status, bottom_chunks[1]);
+    f.render_widget(status, status_area);
+
+    if let (Some(percent), Some(gauge_area)) = (progress, gauge_area) {
+        let gauge = Gauge::default()
+            .gauge_style(Style::default().fg(Color::Green))
+            .ratio(percent / 100.0)
+            .label(format!("{percent:.0}%"));
+        f.render_widget(gauge, gauge_area);
+    }
+
+    if app.flash.replace(false) {
+        let buf = f.buffer_mut();
+        for y in area.top()..area.bottom() {
+            for x in area.left()..area.right() {
+                let cell = buf.get_mut(x, y);
+                cell.set_style(cell.style().add_modifier(Modifier::REVERSED));
+            }
+        }
+    }
+
+    if let Some(overlay) = &app.overlay {
+        render_overlay(f, app, overlay, area);
+        return Vec::new();
+    }
+
+    // Set cursor to input box (hidden while another pane is zoomed or focused)
+    if let Some(area) = input_area.filter(|_| focused && app.focus == Focus::Input) {
+        let cursor_x = area.x + inset + cursor_col - app.input_scroll.get();
+        let y = area.y + inset + cursor_row - app.input_vscroll.get();
+        f.set_cursor(cursor_x, y);
+    }
+    if let Some(area) = notes_area.filter(|_| focused && app.focus == Focus::Notes) {
+        let (row, col) = app.notes.cursor_position();
+        let (hscroll, vscroll) = app.notes.scroll.get();
+        f.set_cursor(
+            area.x + 1 + col as u16 - hscroll,
+            area.y + 1 + row as u16 - vscroll,
+        );
+    }
+
+    let inside_borders = Margin {
+        horizontal: 1,
+        vertical: 1,
+    };
+    out_area
+        .into_iter()
+        .chain(err_area)
+        .map(|area| area.inner(&inside_borders))
+        .chain(err_row)
+        .collect()
+}
+
+/// Rows the input box grows to before it scrolls.
+const MAX_INPUT_ROWS: usize = 8;
+
+/// Offset for a `width`-column (or row) input view: keep the previous
+/// offset unless the cursor at column `cursor_col` would fall outside it.
+fn input_scroll(cursor_col: u16, width: u16, previous: u16) -> u16 {
+    if width == 0 {
+        return cursor_col;
+    }
+    if cursor_col < previous {
+        cursor_col
+    } else if cursor_col >= previous + width {
+        cursor_col + 1 - width
+    } else {
+        previous
+    }
+}
+
+fn render_overlay(
+    f: &mut ratatui::Frame,
+    app: &App,
+    overlay: &Overlay,
+    pane: ratatui::layout::Rect,
+) {
+    use ratatui::layout::{Constraint, Direction, Layout};
+    use ratatui::style::{Modifier, Style};
+    use ratatui::text::{Line, Span};
+    use ratatui::widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph, Wrap};
+
+    match overlay {
+        Overlay::DryRun(lines) | Overlay::Stats(lines) => {
+            let title = match overlay {
+                Overlay::DryRun(_) => "dry run (any key to close)",
+                _ => "stdout stats (any key to close)",
+            };
+            let area = centered_rect(pane, 80, lines.len() as u16 + 2);
+            let popup = Paragraph::new(
+                lines
+                    .iter()
+                    .map(|l| Line::from(l.as_str()))
+                    .collect::<Vec<_>>(),
+            )
+            .block(Block::default().title(title).borders(Borders::ALL))
+            .wrap(Wrap { trim: false });
+            f.render_widget(Clear, area);
+            f.render_widget(popup, area);
+        }
+        Overlay::RunLog { selected } => {
+            let area = centered_rect(pane, 80, pane.height.saturating_sub(4));
+            let items: Vec<ListItem> = if app.run_log.is_empty() {
+                vec![ListItem::new("(no runs yet)")]
+            } else {
+                app.run_log
+                    .iter()
+                    .rev()
+                    .map(|record| {
+                        ListItem::new(format!(
+                            "{:<17} {:>8}  {}",
+                            record.status.to_string(),
+                            utility::format_duration(record.duration),
+                            record.cmd
+                        ))
+                    })
+                    .collect()
+            };
+            let list = List::new(items)
+                .block(
+                    Block::default()
+                        .title("run log (↑/↓ select, Enter recall, Esc close)")
+                        .borders(Borders::ALL),
+                )
+                .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+            let mut state = ListState::default();
+            if !app.run_log.is_empty() {
+                state.select(Some(*selected));
+            }
+            f.render_widget(Clear, area);
+            f.render_stateful_widget(list, area, &mut state);
+        }
+        Overlay::ConfirmClearHistory => {
+            let area = centered_rect(pane, 60, 5);
+            let popup = Paragraph::new(vec![
+                Line::from(format!(
+                    "{} entries will be removed, including the saved file.",
+                    app.history.len()
+                )),
+                Line::from(""),
+                Line::styled(
+                    "y clear, any other key keep",
+                    Style::default().add_modifier(Modifier::DIM),
+                ),
+            ])
+            .block(
+                Block::default()
+                    .title("clear all history?")
+                    .borders(Borders::ALL),
+            );
+            f.render_widget(Clear, area);
+            f.render_widget(popup, area);
+        }
+        Overlay::RestoreDraft(draft) => {
+            let area = centered_rect(pane, 80, 5);
+            let popup = Paragraph::new(vec![
+                Line::from(draft.as_str()),
+                Line::from(""),
+                Line::styled(
+                    "y/Enter restore, any other key discard",
+                    Style::default().add_modifier(Modifier::DIM),
+                ),
+            ])
+            .block(
+                Block::default()
+                    .title("restore unsaved input from last session?")
+                    .borders(Borders::ALL),
+            );
+            f.render_widget(Clear, area);
+            f.render_widget(popup, area);
+        }
+        Overlay::HistorySearch {
+            query,
+            matches,
+            selected,
+        } => {
+            let area = centered_rect(pane, 80, pane.height.saturating_sub(4));
+            let rows = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Length(2), Constraint::Min(1)])
+                .split(area);
+            let prompt = Paragraph::new(format!("> {query}")).block(
+                Block::default()
+                    .title(format!(
+                        "search history, {} of {} (Enter load, Esc close)",
+                        matches.len(),
+                        app.history.len()
+                    ))
+                    .borders(Borders::TOP | Borders::LEFT | Borders::RIGHT),
+            );
+            let items: Vec<ListItem> = if matches.is_empty() {
+                vec![ListItem::new("(no matching command)")]
+            } else {
+                matches
+                    .iter()
+                    .filter_map(|found| {
+                        let cmd = app.history.get(found.entry)?;
+                        let mut line = highlight_positions(cmd, &found.positions);
+                        if app.never_ran.contains(cmd) {
+                            line.spans.push(Span::styled(
+                                "  (never ran)",
+                                Style::default().add_modifier(Modifier::DIM),
+                            ));
+                        }
+                        Some(ListItem::new(line))
+                    })
+                    .collect()
+            };
+            let list = List::new(items)
+                .block(Block::default().borders(Borders::BOTTOM | Borders::LEFT | Borders::RIGHT))
+                .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+            let mut state = ListState::default();
+            if !matches.is_empty() {
+                state.select(Some(*selected));
+            }
+            f.render_widget(Clear, area);
+            f.render_widget(prompt, rows[0]);
+            f.render_stateful_widget(list, rows[1], &mut state);
+        }
+        Overlay::Palette { query, selected } => {
+            let matches = action::filter(query);
+            let area = centered_rect(pane, 60, matches.len().max(1) as u16 + 3);
+            let rows = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Length(2), Constraint::Min(1)])
+                .split(area);
+            let prompt = Paragraph::new(format!("> {query}")).block(
+                Block::default()
+                    .title("commands (Enter run, Esc close)")
+                    .borders(Borders::TOP | Borders::LEFT | Borders::RIGHT),
+            );
+            let items: Vec<ListItem> = if matches.is_empty() {
+                vec![ListItem::new("(no matching command)")]
+            } else {
+                let width = rows[1].width.saturating_sub(2) as usize;
+                matches
+                    .iter()
+                    .map(|action| {
+                        let hint = action.key_hint();
+                        let pad = width.saturating_sub(action.name().len() + hint.len());
+                        ListItem::new(format!("{}{}{hint}", action.name(), " ".repeat(pad)))
+                    })
+                    .collect()
+            };
+            let list = List::new(items)
+                .block(Block::default().borders(Borders::BOTTOM | Borders::LEFT | Borders::RIGHT))
+                .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+            let mut state = ListState::default();
+            if !matches.is_empty() {
+                state.select(Some(*selected));
+            }
+            f.render_widget(Clear, area);
+            f.render_widget(prompt, rows[0]);
+            f.render_stateful_widget(list, rows[1], &mut state);
+        }
+    }
+}
+
+/// Title suffix for a pane that cuts long lines off.
+fn no_wrap(view: &PaneView) -> &'static str {
+    if view.wrap {
+        ""
+    } else {
+        " [no wrap]"
+    }
+}
+
+/// `tabular` as a table of aligned columns, numbers on the right, under
+/// a bold row of column names when it has them.
+fn table_widget(
+    tabular: Tabular,
+    style: ratatui::style::Style,
+) -> ratatui::widgets::Table<'static> {
+    use ratatui::layout::{Alignment, Constraint};
+    use ratatui::style::{Modifier, Style};
+    use ratatui::text::Line;
+    use ratatui::widgets::{Row, Table};
+
+    let numeric = tabular.numeric;
+    let row = |cells: Vec<String>| {
+        Row::new(cells.into_iter().zip(&numeric).map(|(cell, &numeric)| {
+            let line = Line::from(cell);
+            if numeric {
+                line.alignment(Alignment::Right)
+            } else {
+                line
+            }
+        }))
+    };
+    let rows: Vec<Row> = tabular
+        .rows
+        .into_iter()
+        .map(|(_, cells)| row(cells))
+        .collect();
+    let widths = tabular.widths.into_iter().map(Constraint::Length);
+    let mut table = Table::new(rows, widths).style(style).column_spacing(2);
+    if let Some(header) = tabular.header {
+        table = table.header(row(header).style(Style::default().add_modifier(Modifier::BOLD)));
+    }
+    table
+}
 
-    // Set cursor to input box
-    let cursor_x =
-        chunks[0].x + 1 + unicode_width::UnicodeWidthStr::width(&app.input[..app.cursor]) as u16;
-    let y = chunks[0].y + 1;
-    f.set_cursor(cursor_x, y);
+/// Wrap `paragraph` or scroll it sideways, as `view` says.
+fn fit_to_view<'a>(
+    paragraph: ratatui::widgets::Paragraph<'a>,
+    view: &PaneView,
+) -> ratatui::widgets::Paragraph<'a> {
+    if view.wrap {
+        paragraph.wrap(ratatui::widgets::Wrap { trim: false })
+    } else {
+        paragraph.scroll((0, view.left.min(u16::MAX as usize) as u16))
+    }
+}
+
+/// `cmd` with the characters at byte offsets `positions` highlighted and
+/// newlines drawn as `⏎`, so multi-line entries stay on one row.
+fn highlight_positions<'a>(cmd: &'a str, positions: &[usize]) -> ratatui::text::Line<'a> {
+    use ratatui::style::{Color, Modifier, Style};
+    use ratatui::text::{Line, Span};
+
+    let hit = Style::default()
+        .fg(Color::Yellow)
+        .add_modifier(Modifier::BOLD);
+    let mut spans = Vec::new();
+    let mut start = 0;
+    let mut positions = positions.iter().peekable();
+    for (idx, ch) in cmd.char_indices() {
+        let matched = positions.next_if_eq(&&idx).is_some();
+        if !matched && ch != '\n' {
+            continue;
+        }
+        if start < idx {
+            spans.push(Span::raw(&cmd[start..idx]));
+        }
+        let text = if ch == '\n' {
+            "⏎"
+        } else {
+            &cmd[idx..idx + ch.len_utf8()]
+        };
+        spans.push(if matched {
+            Span::styled(text, hit)
+        } else {
+            Span::raw(text)
+        });
+        start = idx + ch.len_utf8();
+    }
+    if start < cmd.len() {
+        spans.push(Span::raw(&cmd[start..]));
+    }
+    Line::from(spans)
+}
+
+/// A rect of `percent_x` of the width and `height` rows centered in `area`.
+fn centered_rect(
+    area: ratatui::layout::Rect,
+    percent_x: u16,
+    height: u16,
+) -> ratatui::layout::Rect {
+    let width = (area.width as u32 * percent_x as u32 / 100) as u16;
+    let height = height.min(area.height);
+    ratatui::layout::Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One session, its history in memory only and set to `history`.
+    fn workspace(history: &[&str]) -> Workspace {
+        crate::history::tests::app();
+        let mut workspace = Workspace::new();
+        let app = &mut workspace.active_mut().app;
+        app.history_path = None;
+        app.history = history.iter().map(|cmd| cmd.to_string()).collect();
+        workspace
+    }
+
+    fn press(workspace: &mut Workspace, code: KeyCode, modifiers: KeyModifiers) -> InputOutcome {
+        handle_event(workspace, Event::Key(KeyEvent::new(code, modifiers)))
+    }
+
+    fn type_keys(workspace: &mut Workspace, text: &str) {
+        for ch in text.chars() {
+            press(workspace, KeyCode::Char(ch), KeyModifiers::NONE);
+        }
+    }
+
+    fn input(workspace: &Workspace) -> &str {
+        &workspace.active().app.input
+    }
+
+    #[test]
+    fn typing_and_backspace_edit_the_input() {
+        let mut workspace = workspace(&[]);
+        type_keys(&mut workspace, "lsx");
+        press(&mut workspace, KeyCode::Backspace, KeyModifiers::NONE);
+        type_keys(&mut workspace, " -l");
+        assert_eq!(input(&workspace), "ls -l");
+        press(&mut workspace, KeyCode::Left, KeyModifiers::CONTROL);
+        press(&mut workspace, KeyCode::Backspace, KeyModifiers::NONE);
+        assert_eq!(input(&workspace), "ls l");
+        // unbound shortcuts type nothing
+        press(&mut workspace, KeyCode::Char('q'), KeyModifiers::CONTROL);
+        assert_eq!(input(&workspace), "ls l");
+    }
+
+    #[test]
+    fn up_and_down_walk_the_history() {
+        let mut workspace = workspace(&["ls", "git log", "git status"]);
+        type_keys(&mut workspace, "git");
+        press(&mut workspace, KeyCode::Up, KeyModifiers::NONE);
+        assert_eq!(input(&workspace), "git status");
+        press(&mut workspace, KeyCode::Up, KeyModifiers::NONE);
+        assert_eq!(input(&workspace), "git log");
+        press(&mut workspace, KeyCode::Up, KeyModifiers::NONE);
+        assert_eq!(input(&workspace), "git log", "no older match");
+        press(&mut workspace, KeyCode::Down, KeyModifiers::NONE);
+        press(&mut workspace, KeyCode::Down, KeyModifiers::NONE);
+        assert_eq!(input(&workspace), "git");
+    }
+
+    #[test]
+    fn alt_dot_inserts_the_last_argument() {
+        let mut workspace = workspace(&["cat notes.txt", "ls /tmp"]);
+        type_keys(&mut workspace, "cd ");
+        let key = KeyEvent::new(KeyCode::Char('.'), KeyModifiers::ALT);
+        assert_eq!(
+            map_key(&key, &workspace.active().app),
+            Some(Action::InsertLastArg)
+        );
+        press(&mut workspace, KeyCode::Char('.'), KeyModifiers::ALT);
+        assert_eq!(input(&workspace), "cd /tmp");
+        press(&mut workspace, KeyCode::Char('.'), KeyModifiers::ALT);
+        assert_eq!(input(&workspace), "cd notes.txt");
+    }
+
+    #[test]
+    fn esc_quits_unless_in_vi_mode() {
+        let mut workspace = workspace(&[]);
+        let esc = KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE);
+        assert_eq!(map_key(&esc, &workspace.active().app), Some(Action::Quit));
+        assert_eq!(
+            press(&mut workspace, KeyCode::Esc, KeyModifiers::NONE),
+            InputOutcome::Quit
+        );
+
+        let mut workspace = self::workspace(&[]);
+        workspace.active_mut().app.config.vi_mode = true;
+        assert_eq!(map_key(&esc, &workspace.active().app), None);
+        type_keys(&mut workspace, "echo hi");
+        assert_eq!(
+            press(&mut workspace, KeyCode::Esc, KeyModifiers::NONE),
+            InputOutcome::Handled
+        );
+        assert_eq!(workspace.active().app.edit_mode, EditMode::Normal);
+        // normal mode: `0` goes home and `x` deletes instead of typing
+        type_keys(&mut workspace, "0x");
+        assert_eq!(input(&workspace), "cho hi");
+        type_keys(&mut workspace, "i!");
+        assert_eq!(input(&workspace), "!cho hi");
+    }
+
+    #[test]
+    fn enter_adds_a_line_in_multiline_input() {
+        let mut workspace = workspace(&[]);
+        let enter = KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE);
+        assert_eq!(map_key(&enter, &workspace.active().app), Some(Action::Run));
+        workspace.active_mut().app.multiline = true;
+        assert_eq!(map_key(&enter, &workspace.active().app), None);
+        type_keys(&mut workspace, "echo a |");
+        press(&mut workspace, KeyCode::Enter, KeyModifiers::NONE);
+        type_keys(&mut workspace, "cat");
+        assert_eq!(input(&workspace), "echo a |\ncat");
+        let alt_enter = KeyEvent::new(KeyCode::Enter, KeyModifiers::ALT);
+        assert_eq!(
+            map_key(&alt_enter, &workspace.active().app),
+            Some(Action::Run)
+        );
+    }
 }
@@ -0,0 +1,90 @@
+use std::borrow::Cow;
+
+/// A `cut`/`awk`-style column selection applied to the stdout pane as it
+/// is drawn (`:cut 1,3`, `:cut -d, 2-`). The lines themselves are left
+/// alone, so dropping the cut shows them whole again.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ColumnCut {
+    /// Field separator; `None` splits on runs of whitespace like awk.
+    delim: Option<String>,
+    /// 1-based inclusive field ranges, an open end running to the last field.
+    fields: Vec<(usize, Option<usize>)>,
+    /// The spec as entered, for the status line.
+    pub spec: String,
+}
+
+impl ColumnCut {
+    /// Parse the arguments of `:cut`: an optional `-d DELIM` (or `-dDELIM`,
+    /// `\t` for a tab) followed by a field list such as `1,3` or `2-4,6-`.
+    pub fn parse(args: &str) -> Result<Self, String> {
+        let mut words = args.split_whitespace();
+        let mut delim = None;
+        let mut list = None;
+        while let Some(word) = words.next() {
+            if let Some(rest) = word.strip_prefix("-d") {
+                let value = if rest.is_empty() {
+                    words.next().ok_or("-d needs a delimiter")?
+                } else {
+                    rest
+                };
+                let value = value.trim_matches(|c| c == '\'' || c == '"');
+                let value = if value == "\\t" { "\t" } else { value };
+                if value.is_empty() {
+                    return Err("empty delimiter".into());
+                }
+                delim = Some(value.to_string());
+            } else if list.is_none() {
+                list = Some(word);
+            } else {
+                return Err(format!("unexpected '{word}'"));
+            }
+        }
+        let list = list.ok_or("no fields given, e.g. ':cut 1,3'")?;
+        let mut fields = Vec::new();
+        for part in list.split(',') {
+            let field = |n: &str| match n.parse::<usize>() {
+                Ok(n) if n > 0 => Ok(n),
+                _ => Err(format!("invalid field '{part}'")),
+            };
+            let range = match part.split_once('-') {
+                Some((from, "")) => (field(from)?, None),
+                Some(("", to)) => (1, Some(field(to)?)),
+                Some((from, to)) => (field(from)?, Some(field(to)?)),
+                None => (field(part)?, Some(field(part)?)),
+            };
+            if range.1.is_some_and(|to| to < range.0) {
+                return Err(format!("decreasing range '{part}'"));
+            }
+            fields.push(range);
+        }
+        let spec = match &delim {
+            Some(d) => format!("-d{} {list}", d.escape_default()),
+            None => list.to_string(),
+        };
+        Ok(ColumnCut {
+            delim,
+            fields,
+            spec,
+        })
+    }
+
+    /// The selected fields of `line`, joined by the delimiter (a space when
+    /// splitting on whitespace). Fields a short line doesn't have are
+    /// skipped, so it shows what it has, or nothing.
+    pub fn apply<'a>(&self, line: &'a str) -> Cow<'a, str> {
+        let parts: Vec<&str> = match &self.delim {
+            Some(delim) => line.split(delim.as_str()).collect(),
+            None => line.split_whitespace().collect(),
+        };
+        let picked: Vec<&str> = self
+            .fields
+            .iter()
+            .flat_map(|&(from, to)| {
+                let to = to.unwrap_or(parts.len()).min(parts.len());
+                parts.get(from - 1..to).unwrap_or_default()
+            })
+            .copied()
+            .collect();
+        Cow::Owned(picked.join(self.delim.as_deref().unwrap_or(" ")))
+    }
+}
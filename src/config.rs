@@ -0,0 +1,336 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use serde::Deserialize;
+use crate::execution::ExecMode;
+
+/// How control characters in output (other than tab) are drawn.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ControlChars {
+    /// Passed to the terminal as they are.
+    #[default]
+    Raw,
+    /// Caret notation: `^@` for NUL, `^[` for ESC, `^?` for DEL.
+    Caret,
+    /// Unicode control pictures: `␀`, `␛`, `␡`.
+    Pictures,
+}
+
+/// What the gutter beside the output shows of when each line arrived.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Timestamps {
+    /// No gutter.
+    #[default]
+    Off,
+    /// Seconds since the run started.
+    Relative,
+    /// Wall-clock time.
+    Clock,
+}
+
+/// What to do when a command exits nonzero.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FailureAlert {
+    #[default]
+    Off,
+    /// Ring the terminal bell (`\x07`).
+    Bell,
+    /// Invert the whole screen for one frame.
+    Flash,
+}
+
+/// Colorize every match of `pattern` in the output panes.
+/// Colors are ratatui color names (`red`, `lightcyan`) or `#rrggbb`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct HighlightRule {
+    pub pattern: String,
+    #[serde(default)]
+    pub fg: Option<String>,
+    #[serde(default)]
+    pub bg: Option<String>,
+    #[serde(default)]
+    pub bold: bool,
+}
+
+/// Pane titles and empty-pane placeholders, e.g. to shorten or localize
+/// them. Missing keys keep the built-in English text.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct Labels {
+    pub input_title: String,
+    pub stdout_title: String,
+    pub stderr_title: String,
+    pub notes_title: String,
+    /// Stdout before anything has run.
+    pub idle: String,
+    /// Stdout while a run hasn't printed yet.
+    pub waiting: String,
+    /// Stdout after a run that printed nothing.
+    pub no_stdout: String,
+    /// Stderr when there is none.
+    pub no_stderr: String,
+}
+
+impl Default for Labels {
+    fn default() -> Self {
+        Self {
+            input_title: "pipeline".into(),
+            stdout_title: "stdout".into(),
+            stderr_title: "stderr".into(),
+            notes_title: "notes".into(),
+            idle: "(output will appear here)".into(),
+            waiting: "(waiting for output...)".into(),
+            no_stdout: "<no stdout>".into(),
+            no_stderr: "<no stderr>".into(),
+        }
+    }
+}
+
+/// User settings read from `config.json` in the pipetui config dir.
+/// Every field is optional in the file; missing ones keep their defaults.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Honor held-key repeat events for navigation and deletion keys.
+    /// Turn off for terminals that report spurious repeats.
+    pub key_repeat: bool,
+    pub failure_alert: FailureAlert,
+    /// Draw in the alternate screen. When off the UI renders inline and
+    /// its last frame stays in the terminal after quitting.
+    pub alternate_screen: bool,
+    /// After quitting, print the last run's stdout to the real stdout.
+    pub print_output_on_exit: bool,
+    /// Shell used in shell mode instead of `sh`/`cmd`. `PIPETUI_SHELL`
+    /// still takes precedence.
+    pub shell: Option<String>,
+    /// Working directory for commands; defaults to where pipetui started.
+    pub cwd: Option<PathBuf>,
+    /// Environment overrides applied to every command.
+    pub env: BTreeMap<String, String>,
+    pub highlights: Vec<HighlightRule>,
+    /// Strip trailing whitespace from each output line as it arrives.
+    /// Off by default to keep output byte-faithful.
+    pub trim_trailing_whitespace: bool,
+    /// Keep showing (dimmed) the previous output when a run starts until
+    /// the new run produces its first output, instead of clearing at once.
+    pub keep_output_until_new: bool,
+    /// Only auto-run once the input ends with this suffix, which is
+    /// stripped before running. `None` auto-runs on every edit.
+    pub auto_run_trigger: Option<String>,
+    /// Drop trailing blank lines from both panes when a run finishes.
+    pub strip_trailing_blank_lines: bool,
+    /// Columns between tab stops when expanding tabs in output; `0` leaves
+    /// tabs to the terminal.
+    pub tab_width: usize,
+    /// Run commands under a pseudo-terminal so they behave as if attached
+    /// to one (colors, progress bars). Stderr is merged into stdout.
+    pub pty: bool,
+    /// Lines kept per output pane; older ones are dropped as new ones
+    /// arrive. `0` keeps everything.
+    pub max_output_lines: usize,
+    /// Prefilled into the input on startup, without running it. `--file`
+    /// takes precedence.
+    pub startup_command: Option<String>,
+    /// Drop a trailing `# comment` from the input before running it.
+    pub strip_comments: bool,
+    pub labels: Labels,
+    /// Start in this mode instead of the one saved from the last session.
+    pub exec_mode: Option<ExecMode>,
+    /// Regex whose first capture group is a percentage in the latest
+    /// output line, shown as a gauge while the command runs. `None`
+    /// disables the gauge.
+    pub progress_pattern: Option<String>,
+    /// Show the command above its output as a dimmed `$ cmd` row.
+    pub echo_command: bool,
+    /// Input length in bytes from which editing steps by `char` instead of
+    /// grapheme cluster and the caret column skips cluster segmentation.
+    /// `0` never switches.
+    pub literal_input_threshold: usize,
+    /// Longest input in bytes; typing or pasting past it is refused so a
+    /// runaway paste can't bog down editing. `0` allows any length.
+    pub max_input_bytes: usize,
+    /// Hold back the output of auto-runs and only show it once the command
+    /// exits 0; failed auto-runs leave the previous output on screen.
+    pub quiet_auto_run: bool,
+    /// Make control characters in the output panes visible instead of
+    /// letting them reach the terminal.
+    pub control_chars: ControlChars,
+    /// Seconds between runs in watch mode when started with `Alt+W` or a
+    /// bare `:watch`.
+    pub watch_interval: f64,
+    /// Also show each stderr line inline in the stdout pane, where it was
+    /// emitted relative to stdout.
+    pub annotate_stderr: bool,
+    /// Shell command run after each completed run, with `{cmd}`,
+    /// `{status}` and `{duration}` expanding to their values.
+    pub on_finish: Option<String>,
+    /// Initial wrapping of each output pane; long lines are cut off (and
+    /// scroll sideways) when false.
+    pub wrap_stdout: bool,
+    pub wrap_stderr: bool,
+    /// vi-style modal editing: `Esc` enters normal mode instead of quitting.
+    pub vi_mode: bool,
+    /// After editing an entry recalled from history, `↑` continues from
+    /// that entry instead of starting again from the newest.
+    pub history_resume: bool,
+    /// Terminal height below which the compact layout is used; 0 never.
+    pub compact_height: u16,
+    /// Output copied for sharing comes in a Markdown code block.
+    pub share_fenced: bool,
+    /// Start with scratch runs on: each run in a fresh temporary directory.
+    pub scratch_runs: bool,
+    /// Make URLs and existing file paths in the output clickable (OSC 8).
+    pub hyperlinks: bool,
+    /// Only auto-run commands all of whose parts start with one of these
+    /// (`ls`, `git log`); others wait for `Enter`. Empty auto-runs all.
+    pub auto_run_allow: Vec<String>,
+    /// Never auto-run commands any part of which starts with one of these
+    /// (`rm`, `git push`), allowlisted or not.
+    pub auto_run_block: Vec<String>,
+    /// Start with a gutter showing when each output line arrived.
+    pub timestamps: Timestamps,
+    /// Keep commands that couldn't be started (a mistyped program in
+    /// direct mode, a missing shell) in the history, marked as never run
+    /// in the history search.
+    pub history_spawn_failures: bool,
+    /// Environment variables a `.pipetuirc` may set; a trailing `*`
+    /// allows every name starting with what precedes it (`MYAPP_*`).
+    pub rc_env_allow: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            key_repeat: true,
+            failure_alert: FailureAlert::Off,
+            alternate_screen: true,
+            print_output_on_exit: false,
+            shell: None,
+            cwd: None,
+            env: BTreeMap::new(),
+            highlights: Vec::new(),
+            trim_trailing_whitespace: false,
+            keep_output_until_new: false,
+            auto_run_trigger: None,
+            strip_trailing_blank_lines: false,
+            tab_width: 8,
+            pty: false,
+            max_output_lines: 100_000,
+            startup_command: None,
+            strip_comments: false,
+            labels: Labels::default(),
+            exec_mode: None,
+            progress_pattern: Some(r"(\d{1,3}(?:\.\d+)?)%".into()),
+            echo_command: false,
+            literal_input_threshold: 4096,
+            max_input_bytes: 64 * 1024,
+            quiet_auto_run: false,
+            control_chars: ControlChars::Raw,
+            watch_interval: 2.0,
+            annotate_stderr: false,
+            on_finish: None,
+            wrap_stdout: true,
+            wrap_stderr: true,
+            vi_mode: false,
+            history_resume: false,
+            compact_height: 16,
+            share_fenced: true,
+            scratch_runs: false,
+            hyperlinks: false,
+            auto_run_allow: Vec::new(),
+            auto_run_block: Vec::new(),
+            timestamps: Timestamps::Off,
+            history_spawn_failures: true,
+            rc_env_allow: [
+                "RUST_LOG",
+                "RUST_BACKTRACE",
+                "NODE_ENV",
+                "TZ",
+                "NO_COLOR",
+                "CLICOLOR_FORCE",
+            ]
+            .map(String::from)
+            .to_vec(),
+        }
+    }
+}
+
+/// Whether `allow` (`rc_env_allow`) lets a `.pipetuirc` set `name`.
+fn rc_env_allowed(allow: &[String], name: &str) -> bool {
+    allow.iter().any(|allowed| match allowed.strip_suffix('*') {
+        Some(prefix) => name.starts_with(prefix),
+        None => name == allowed,
+    })
+}
+
+/// Per-project settings from a `.pipetuirc` (JSON) in the working
+/// directory or one of its parents, layered over the global config. Any
+/// checkout can carry one, so it can't pick the shell and only sets the
+/// variables `rc_env_allow` lists: `PATH`, `LD_PRELOAD`, `BASH_ENV` and
+/// the like would make the next auto-run execute code from the checkout
+/// as soon as pipetui opens there.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ProjectRc {
+    /// Relative paths are resolved against the rc file's directory.
+    pub cwd: Option<PathBuf>,
+    pub env: BTreeMap<String, String>,
+}
+
+impl ProjectRc {
+    /// Override `config` with everything this rc file may set. `rc_dir`
+    /// is the directory containing the rc file. Returns the names of the
+    /// variables left out because `rc_env_allow` doesn't list them.
+    pub fn apply(self, config: &mut Config, rc_dir: &Path) -> Vec<String> {
+        if let Some(cwd) = self.cwd {
+            config.cwd = Some(rc_dir.join(cwd));
+        }
+        let mut ignored = Vec::new();
+        for (key, value) in self.env {
+            if rc_env_allowed(&config.rc_env_allow, &key) {
+                config.env.insert(key, value);
+            } else {
+                ignored.push(key);
+            }
+        }
+        ignored
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rc_cannot_pick_the_shell_or_its_startup_file() {
+        let rc: ProjectRc = serde_json::from_str(
+            r#"{"shell": "/tmp/evil", "cwd": "sub", "env": {"BASH_ENV": "x", "ENV": "y", "TZ": "UTC"}}"#,
+        )
+        .unwrap();
+        let mut config = Config::default();
+        let ignored = rc.apply(&mut config, Path::new("/project"));
+        assert_eq!(config.shell, None);
+        assert_eq!(config.cwd, Some(PathBuf::from("/project/sub")));
+        assert_eq!(config.env.keys().collect::<Vec<_>>(), ["TZ"]);
+        assert_eq!(ignored, ["BASH_ENV", "ENV"]);
+    }
+
+    #[test]
+    fn rc_only_sets_allowed_variables() {
+        let rc: ProjectRc = serde_json::from_str(
+            r#"{"env": {"PATH": "./bin", "LD_PRELOAD": "./x.so", "IFS": "/", "MYAPP_MODE": "dev", "RUST_LOG": "debug"}}"#,
+        )
+        .unwrap();
+        let mut config = Config::default();
+        config.rc_env_allow.push("MYAPP_*".into());
+        config.env.insert("PATH".into(), "/usr/bin".into());
+        let ignored = rc.apply(&mut config, Path::new("/project"));
+        assert_eq!(ignored, ["IFS", "LD_PRELOAD", "PATH"]);
+        assert_eq!(config.env["PATH"], "/usr/bin");
+        assert!(!config.env.contains_key("LD_PRELOAD"));
+        assert_eq!(config.env["MYAPP_MODE"], "dev");
+        assert_eq!(config.env["RUST_LOG"], "debug");
+    }
+}
@@ -0,0 +1,93 @@
+use std::io;
+use std::path::PathBuf;
+use std::process::ExitStatus;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// What can go wrong outside pipetui's own state: the terminal, the files
+/// it keeps, and the programs it starts. Messages are written to be shown
+/// as-is in the status line or the stderr pane.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// Raw mode, the alternate screen, drawing or reading keys failed.
+    #[error("terminal: {0}")]
+    Terminal(#[source] io::Error),
+    /// The platform has no cache/data or config directory.
+    #[error("no {0} dir")]
+    NoDir(&'static str),
+    /// Reading or writing history, state, config or a temporary file.
+    #[error("{}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    /// `config.json`, `.pipetuirc` or the history isn't valid JSON of the
+    /// expected shape.
+    #[error("{}: {source}", path.display())]
+    Parse {
+        path: PathBuf,
+        #[source]
+        source: serde_json::Error,
+    },
+    /// Direct mode couldn't split the input into argv.
+    #[error("argv parse error: {0}")]
+    Argv(String),
+    /// The shell for shell mode doesn't exist.
+    #[error("shell '{0}' not found — set PIPETUI_SHELL")]
+    ShellNotFound(String),
+    /// The program for direct mode doesn't exist.
+    #[error("command '{0}' not found")]
+    CommandNotFound(String),
+    #[error("Failed to spawn: {source}")]
+    Spawn {
+        program: String,
+        #[source]
+        source: io::Error,
+    },
+    /// Opening the pseudo-terminal or starting the command in it failed.
+    #[error("Failed to spawn under a pty: {0}")]
+    Pty(String),
+    /// The pager or editor couldn't be started.
+    #[error("cannot start {kind} '{program}': {source}")]
+    ViewerStart {
+        kind: &'static str,
+        program: String,
+        #[source]
+        source: io::Error,
+    },
+    /// The pager or editor exited unsuccessfully.
+    #[error("{kind} '{program}' failed ({status})")]
+    ViewerFailed {
+        kind: &'static str,
+        program: String,
+        status: ExitStatus,
+    },
+    /// Headless mode couldn't write the command's output.
+    #[error("cannot write output: {0}")]
+    Output(#[source] io::Error),
+    /// The worker thread went away before a run could be sent to it.
+    #[error("worker thread exited")]
+    WorkerGone,
+}
+
+impl Error {
+    pub(crate) fn io(path: impl Into<PathBuf>) -> impl FnOnce(io::Error) -> Self {
+        let path = path.into();
+        move |source| Error::Io { path, source }
+    }
+
+    /// The cause without the path, for a short note in the status line
+    /// ("permission denied").
+    pub fn reason(&self) -> String {
+        match self {
+            Error::Io { source, .. } => source.kind().to_string(),
+            other => other.to_string(),
+        }
+    }
+
+    pub(crate) fn parse(path: impl Into<PathBuf>) -> impl FnOnce(serde_json::Error) -> Self {
+        let path = path.into();
+        move |source| Error::Parse { path, source }
+    }
+}
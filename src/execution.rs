@@ -1,146 +1,957 @@
-use std::io::{BufRead, BufReader, Read};
-use std::process::{Command, Stdio};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
-use std::thread;
-use std::time::Duration;
-use crossbeam_channel::{unbounded, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use crossbeam_channel::{unbounded, Receiver, RecvTimeoutError, Sender, TryRecvError};
+use portable_pty::{native_pty_system, CommandBuilder, MasterPty, PtySize};
+use serde::{Deserialize, Serialize};
+use crate::error::Result;
+use crate::parser::{quote_word, shell_quote, split_argv};
+use crate::Error;
 
 #[derive(Clone, Debug)]
 pub struct ExecResult {
     pub cmd: String,
-    pub status: i32,
+    pub status: Exit,
     pub stdout: String,
     pub stderr: String,
+    pub duration: Duration,
+    pub usage: Option<ResourceUsage>,
+    /// Killed by `WorkerMsg::Cancel` rather than exiting on its own.
+    pub cancelled: bool,
+    /// The command couldn't be started at all; `stderr` says why.
+    pub spawn_failed: bool,
+}
+
+/// How a run ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Exit {
+    /// Exited on its own with this code.
+    Code(i32),
+    /// Terminated by this signal (Unix only).
+    Signal(i32),
+    /// No status: it couldn't be spawned or waited for.
+    Unknown,
+}
+
+impl Exit {
+    pub fn success(self) -> bool {
+        self == Exit::Code(0)
+    }
+
+    /// The status as a shell reports it in `$?`: the exit code, `128 + n`
+    /// for signal `n`, and `-1` when unknown.
+    pub fn code(self) -> i32 {
+        match self {
+            Exit::Code(code) => code,
+            Exit::Signal(signal) => 128 + signal,
+            Exit::Unknown => -1,
+        }
+    }
+}
+
+impl From<std::process::ExitStatus> for Exit {
+    fn from(status: std::process::ExitStatus) -> Self {
+        if let Some(code) = status.code() {
+            return Exit::Code(code);
+        }
+        #[cfg(unix)]
+        if let Some(signal) = std::os::unix::process::ExitStatusExt::signal(&status) {
+            return Exit::Signal(signal);
+        }
+        Exit::Unknown
+    }
+}
+
+impl From<portable_pty::ExitStatus> for Exit {
+    /// portable-pty only keeps the signal's description (`strsignal`), so
+    /// find the signal with that description.
+    fn from(status: portable_pty::ExitStatus) -> Self {
+        let Some(description) = status.signal() else {
+            return Exit::Code(status.exit_code() as i32);
+        };
+        #[cfg(unix)]
+        for signal in 1..65 {
+            // SAFETY: strsignal returns a pointer to a NUL-terminated string
+            // (or null), read before any other strsignal call
+            let text = unsafe { libc::strsignal(signal) };
+            if !text.is_null()
+                && unsafe { std::ffi::CStr::from_ptr(text) }.to_str() == Ok(description)
+            {
+                return Exit::Signal(signal);
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = description;
+        Exit::Unknown
+    }
+}
+
+impl std::fmt::Display for Exit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Exit::Code(code) => write!(f, "exit {code}"),
+            Exit::Signal(signal) => write!(f, "killed by {}", signal_name(signal)),
+            Exit::Unknown => f.write_str("no exit status"),
+        }
+    }
+}
+
+/// `SIGTERM` for 15, else `signal N`.
+fn signal_name(signal: i32) -> String {
+    #[cfg(unix)]
+    if let Ok(known) = nix::sys::signal::Signal::try_from(signal) {
+        return known.as_str().to_string();
+    }
+    format!("signal {signal}")
+}
+
+/// CPU time and peak memory of a finished run (Unix only), as `wait4`
+/// reports them when reaping its process: the shell and every stage of a
+/// pipeline it waited for, nothing else pipetui started.
+#[derive(Clone, Copy, Debug)]
+pub struct ResourceUsage {
+    pub cpu_time: Duration,
+    /// Resident set of the run's largest process.
+    pub peak_rss_bytes: u64,
+}
+
+/// How the worker turns the input into a process.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExecMode {
+    /// Hand the whole line to the host shell (`sh -c` / `cmd /C`).
+    Shell,
+    /// Split the line into argv and spawn the program directly, no shell.
+    Direct,
+}
+
+/// Everything the worker needs to start one run.
+#[derive(Clone, Debug)]
+pub struct RunSpec {
+    pub cmd: String,
+    pub mode: ExecMode,
+    /// Shell override from config / `.pipetuirc`.
+    pub shell: Option<String>,
+    pub cwd: Option<PathBuf>,
+    pub env: BTreeMap<String, String>,
+    /// Fed to the command's stdin, which is otherwise inherited.
+    pub stdin: Option<String>,
+    /// Run under a pseudo-terminal instead of plain pipes.
+    pub pty: bool,
+    /// Run in a fresh temporary directory instead of `cwd`, removed
+    /// afterwards.
+    pub scratch: bool,
+}
+
+impl RunSpec {
+    /// The run as one line to paste into a terminal elsewhere, such as
+    /// `cd /path && KEY=VALUE sh -c 'cmd'`: the cwd and env it runs with
+    /// spelled out, through the same shell in shell mode and as quoted
+    /// words in direct mode. Without a configured cwd it is pipetui's
+    /// own. Captured stdin isn't included.
+    pub fn shell_line(&self) -> String {
+        let mut line = String::new();
+        let cwd = self.cwd.clone().or_else(|| std::env::current_dir().ok());
+        if self.scratch {
+            line.push_str("cd \"$(mktemp -d)\" && ");
+        } else if let Some(cwd) = cwd {
+            line.push_str(&format!("cd {} && ", quote_word(&cwd.to_string_lossy())));
+        }
+        for (key, value) in &self.env {
+            line.push_str(&format!("{key}={} ", quote_word(value)));
+        }
+        match self.mode {
+            ExecMode::Shell => {
+                let (shell, flag) = shell_invocation(self.shell.as_deref());
+                line.push_str(&format!(
+                    "{} {flag} {}",
+                    quote_word(&shell),
+                    shell_quote(&self.cmd)
+                ));
+            }
+            // direct mode passes its words as they are: quote each so the
+            // shell doesn't expand `$HOME`, `*.bak` or `~` when pasted
+            ExecMode::Direct => match split_argv(&self.cmd) {
+                Ok(argv) => {
+                    let words: Vec<Cow<str>> = argv
+                        .iter()
+                        .enumerate()
+                        .map(|(idx, word)| match idx {
+                            // `A=1` first would be an assignment, not the program
+                            0 if word.contains('=') => Cow::Owned(shell_quote(word)),
+                            _ => quote_word(word),
+                        })
+                        .collect();
+                    line.push_str(&words.join(" "));
+                }
+                // it can't run either; show what was typed
+                Err(_) => line.push_str(&self.cmd),
+            },
+        }
+        line
+    }
 }
 
 pub enum WorkerMsg {
-    Run(String),
+    Run(RunSpec),
+    /// Kill the running command (if any) and drop the queued run.
+    Cancel,
+    /// Kill any running command and stop the worker thread.
+    Shutdown,
 }
 
+/// How often the worker checks on a running child and its inbox.
+const CHILD_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// How long after the child exits its output is still drained from
+/// processes it left holding the pipes (`sleep 60 & echo hi`) before the
+/// run is wrapped up without them.
+const DRAIN_GRACE: Duration = Duration::from_millis(250);
+
 pub enum UiMsg {
     Started(String),
+    /// PID of the child, sent right after it spawns.
+    Spawned(u32),
+    /// The temporary directory a scratch run works in, until it finishes.
+    ScratchDir(PathBuf),
     StdoutChunk(String),
     StderrChunk(String),
     Finished(ExecResult),
 }
 
-pub fn spawn_worker(rx: Receiver<WorkerMsg>, tx_ui: Sender<UiMsg>) {
-    thread::spawn(move || {
-        while let Ok(msg) = rx.recv() {
-            match msg {
-                WorkerMsg::Run(cmd) => {
-                    let _ = tx_ui.send(UiMsg::Started(cmd.clone()));
-
-                    #[cfg(target_os = "windows")]
-                    let mut command = Command::new("cmd");
-                    #[cfg(target_os = "windows")]
-                    command.args(["/C", &cmd]);
-                    #[cfg(not(target_os = "windows"))]
-                    let mut command = Command::new("sh");
-                    #[cfg(not(target_os = "windows"))]
-                    command.args(["-c", &cmd]);
-
-                    command.stdout(Stdio::piped()).stderr(Stdio::piped());
-
-                    match command.spawn() {
-                        Ok(mut child) => {
-                            let stdout_log = Arc::new(Mutex::new(String::new()));
-                            let stderr_log = Arc::new(Mutex::new(String::new()));
-
-                            let (tx_stdout_chunk, rx_stdout_chunk) = unbounded::<String>();
-                            let (tx_stderr_chunk, rx_stderr_chunk) = unbounded::<String>();
-
-                            let stdout_handle = child.stdout.take().map(|stdout| {
-                                let tx_chunk = tx_stdout_chunk.clone();
-                                let log = Arc::clone(&stdout_log);
-                                thread::spawn(move || stream_pipe(stdout, tx_chunk, log))
-                            });
-
-                            let stderr_handle = child.stderr.take().map(|stderr| {
-                                let tx_chunk = tx_stderr_chunk.clone();
-                                let log = Arc::clone(&stderr_log);
-                                thread::spawn(move || stream_pipe(stderr, tx_chunk, log))
-                            });
-
-                            drop(tx_stdout_chunk);
-                            drop(tx_stderr_chunk);
-
-                            let agg_tx = tx_ui.clone();
-                            let aggregator = thread::spawn(move || {
-                                aggregate_streams(rx_stdout_chunk, rx_stderr_chunk, agg_tx);
-                            });
-
-                            let status = child.wait();
-
-                            if let Some(handle) = stdout_handle {
-                                let _ = handle.join();
-                            }
-                            if let Some(handle) = stderr_handle {
-                                let _ = handle.join();
-                            }
-                            let _ = aggregator.join();
-
-                            let status_code = status
-                                .as_ref()
-                                .ok()
-                                .and_then(|s| s.code())
-                                .unwrap_or(-1);
-
-                            let stdout = stdout_log
-                                .lock()
-                                .map(|buf| buf.clone())
-                                .unwrap_or_default();
-                            let stderr = stderr_log
-                                .lock()
-                                .map(|buf| buf.clone())
-                                .unwrap_or_default();
-
-                            let _ = tx_ui.send(UiMsg::Finished(ExecResult {
-                                cmd,
-                                status: status_code,
-                                stdout,
-                                stderr,
-                            }));
-                        }
-                        Err(e) => {
-                            let _ = tx_ui.send(UiMsg::Finished(ExecResult {
-                                cmd,
-                                status: -1,
-                                stdout: String::new(),
-                                stderr: format!("Failed to spawn: {e}"),
-                            }));
-                        }
-                    }
+/// Program and flag used to hand a command line to the host shell.
+/// Precedence: `PIPETUI_SHELL`, then `configured`, then the platform default.
+pub fn shell_invocation(configured: Option<&str>) -> (String, &'static str) {
+    #[cfg(target_os = "windows")]
+    let (default_shell, flag) = ("cmd", "/C");
+    #[cfg(not(target_os = "windows"))]
+    let (default_shell, flag) = ("sh", "-c");
+
+    match std::env::var("PIPETUI_SHELL") {
+        Ok(shell) if !shell.trim().is_empty() => (shell, flag),
+        _ => (configured.unwrap_or(default_shell).to_string(), flag),
+    }
+}
+
+/// Environment variable set for `on_finish` hooks, so a script can tell
+/// it was started by pipetui.
+pub const HOOK_VAR: &str = "PIPETUI_HOOK";
+
+/// Placeholders of `on_finish` hooks and the environment variables that
+/// carry their values.
+const HOOK_PLACEHOLDERS: [(&str, &str); 3] = [
+    ("{cmd}", "PIPETUI_CMD"),
+    ("{status}", "PIPETUI_STATUS"),
+    ("{duration}", "PIPETUI_DURATION"),
+];
+
+/// Run the `template` hook for the finished `res` through the shell of
+/// `spec`, in its cwd and env, without waiting for it. The values are
+/// passed in environment variables and each placeholder becomes a
+/// reference to its variable (`{cmd}` is `${PIPETUI_CMD}`), so the shell
+/// expands the command as data and never parses it, inside double quotes
+/// or not; unquoted, it is split into words like any expansion. cmd.exe
+/// substitutes `%VAR%` before parsing, so there the values are not safe
+/// from its metacharacters.
+/// The hook's output goes nowhere and it never reaches the worker, so it
+/// can't disturb the screen or start another run. A pipetui started by a
+/// hook runs none itself, so hooks can't chain.
+pub fn spawn_hook(template: &str, res: &ExecResult, spec: &RunSpec) -> Result<()> {
+    if std::env::var_os(HOOK_VAR).is_some() {
+        return Ok(());
+    }
+    let values = [
+        res.cmd.clone(),
+        res.status.code().to_string(),
+        format!("{:.2}", res.duration.as_secs_f64()),
+    ];
+    let mut hook = template.to_string();
+    for (placeholder, var) in HOOK_PLACEHOLDERS {
+        #[cfg(target_os = "windows")]
+        let reference = format!("%{var}%");
+        #[cfg(not(target_os = "windows"))]
+        let reference = format!("${{{var}}}");
+        hook = hook.replace(placeholder, &reference);
+    }
+    let (shell, flag) = shell_invocation(spec.shell.as_deref());
+    let mut command = Command::new(&shell);
+    command
+        .args([flag, &hook])
+        .envs(&spec.env)
+        .envs(HOOK_PLACEHOLDERS.iter().map(|(_, var)| var).zip(values))
+        .env(HOOK_VAR, "1")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    if let Some(cwd) = &spec.cwd {
+        command.current_dir(cwd);
+    }
+    let mut child = command.spawn().map_err(|source| Error::Spawn {
+        program: shell,
+        source,
+    })?;
+    tracing::debug!(hook = %hook, pid = child.id(), "hook started");
+    // reap it when it exits
+    thread::spawn(move || child.wait());
+    Ok(())
+}
+
+/// The program and arguments `spec` runs: the shell with the command, or
+/// the command's own words in direct mode.
+fn command_argv(spec: &RunSpec) -> Result<Vec<String>> {
+    match spec.mode {
+        ExecMode::Shell => {
+            let (shell, flag) = shell_invocation(spec.shell.as_deref());
+            Ok(vec![shell, flag.to_string(), spec.cmd.clone()])
+        }
+        ExecMode::Direct => split_argv(&spec.cmd),
+    }
+}
+
+/// Build the process for `spec`. Also returns the program name so spawn
+/// errors can say what was missing.
+fn build_command(spec: &RunSpec) -> Result<(Command, String)> {
+    let argv = command_argv(spec)?;
+    let mut command = Command::new(&argv[0]);
+    command.args(&argv[1..]);
+    if let Some(cwd) = &spec.cwd {
+        command.current_dir(cwd);
+    }
+    command.envs(&spec.env);
+    Ok((command, argv[0].clone()))
+}
+
+/// Human-readable description of what `WorkerMsg::Run(spec)` would spawn,
+/// used by the dry-run preview. Nothing is executed.
+pub fn describe_run(spec: &RunSpec) -> Vec<String> {
+    let exec = match spec.mode {
+        ExecMode::Shell => {
+            let (shell, flag) = shell_invocation(spec.shell.as_deref());
+            format!("exec: {shell} {flag} {}", shell_quote(&spec.cmd))
+        }
+        ExecMode::Direct => match split_argv(&spec.cmd) {
+            Ok(argv) => {
+                let quoted: Vec<String> = argv.iter().map(|arg| shell_quote(arg)).collect();
+                format!("exec: {} (direct, no shell)", quoted.join(" "))
+            }
+            Err(e) => format!("exec: <{e}>"),
+        },
+    };
+    let cwd = match &spec.cwd {
+        _ if spec.scratch => "(a fresh temporary directory, removed after the run)".into(),
+        Some(dir) => dir.display().to_string(),
+        None => std::env::current_dir()
+            .map(|dir| dir.display().to_string())
+            .unwrap_or_else(|_| "<unknown>".into()),
+    };
+    let mut lines = vec![exec, format!("cwd:  {cwd}")];
+    if spec.pty {
+        lines.push("tty:  pseudo-terminal (stdout and stderr merged)".into());
+    }
+    if let Some(input) = &spec.stdin {
+        lines.push(format!(
+            "stdin: {} lines captured from a previous run",
+            input.lines().count()
+        ));
+    }
+    if spec.env.is_empty() {
+        lines.push("env:  (inherited, no overrides)".into());
+    } else {
+        for (key, value) in &spec.env {
+            lines.push(format!("env:  {key}={}", shell_quote(value)));
+        }
+    }
+    lines
+}
+
+/// Reap the child `pid` with `wait4`, which also reports what it used;
+/// without `block`, `None` while it still runs.
+#[cfg(unix)]
+fn reap(pid: u32, block: bool) -> io::Result<Option<(Exit, ResourceUsage)>> {
+    use std::os::unix::process::ExitStatusExt;
+
+    let mut status = 0;
+    // SAFETY: rusage is plain data, all zeroes is a valid value
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    let flags = if block { 0 } else { libc::WNOHANG };
+    loop {
+        // SAFETY: both out-pointers are valid for the duration of the call
+        match unsafe { libc::wait4(pid as libc::pid_t, &mut status, flags, &mut usage) } {
+            0 => return Ok(None),
+            -1 => {
+                let e = io::Error::last_os_error();
+                if e.kind() != io::ErrorKind::Interrupted {
+                    return Err(e);
                 }
             }
+            _ => break,
+        }
+    }
+    let micros = |time: libc::timeval| time.tv_sec as u64 * 1_000_000 + time.tv_usec as u64;
+    // ru_maxrss is bytes on macOS and KiB everywhere else
+    #[cfg(target_os = "macos")]
+    let peak_rss_bytes = usage.ru_maxrss as u64;
+    #[cfg(not(target_os = "macos"))]
+    let peak_rss_bytes = usage.ru_maxrss as u64 * 1024;
+    let usage = ResourceUsage {
+        cpu_time: Duration::from_micros(micros(usage.ru_utime) + micros(usage.ru_stime)),
+        peak_rss_bytes,
+    };
+    Ok(Some((
+        std::process::ExitStatus::from_raw(status).into(),
+        usage,
+    )))
+}
+
+/// What the worker loop should do after a run returns.
+#[derive(PartialEq, Eq)]
+enum Flow {
+    Continue,
+    Shutdown,
+}
+
+/// Start the worker thread. It runs one command at a time and exits on
+/// `WorkerMsg::Shutdown` (or when the UI side hangs up), killing any
+/// command still in flight; join the handle to wait for that.
+pub fn spawn_worker(rx: Receiver<WorkerMsg>, tx_ui: Sender<UiMsg>) -> JoinHandle<()> {
+    thread::spawn(move || {
+        tracing::debug!("worker started");
+        let mut queued = None;
+        while let Some(spec) = next_run(queued.take(), &rx) {
+            if run_one(spec, &rx, &tx_ui, &mut queued) == Flow::Shutdown {
+                break;
+            }
         }
+        tracing::debug!("worker stopped");
+    })
+}
+
+/// The run to start next: the newest of `queued` and every `Run` already
+/// waiting in `rx`, so a burst of auto-runs executes only its last command.
+/// Blocks when nothing is pending; `None` means shut down.
+fn next_run(queued: Option<RunSpec>, rx: &Receiver<WorkerMsg>) -> Option<RunSpec> {
+    let mut latest = match queued {
+        Some(spec) => spec,
+        None => loop {
+            match rx.recv() {
+                Ok(WorkerMsg::Run(spec)) => break spec,
+                // nothing running to cancel
+                Ok(WorkerMsg::Cancel) => {}
+                Ok(WorkerMsg::Shutdown) | Err(_) => return None,
+            }
+        },
+    };
+    loop {
+        match rx.try_recv() {
+            Ok(WorkerMsg::Run(spec)) => {
+                tracing::debug!(superseded = %latest.cmd, "coalescing queued run");
+                latest = spec;
+            }
+            Ok(WorkerMsg::Cancel) => return next_run(None, rx),
+            Ok(WorkerMsg::Shutdown) | Err(TryRecvError::Disconnected) => return None,
+            Err(TryRecvError::Empty) => return Some(latest),
+        }
+    }
+}
+
+/// Run `spec` to completion, reporting through `tx_ui`. Messages arriving
+/// meanwhile are handled here: a run replaces whatever was queued before
+/// it, cancel kills the child and clears the queue, shutdown kills the
+/// child and stops.
+fn run_one(
+    mut spec: RunSpec,
+    rx: &Receiver<WorkerMsg>,
+    tx_ui: &Sender<UiMsg>,
+    queued: &mut Option<RunSpec>,
+) -> Flow {
+    let cmd = spec.cmd.clone();
+    tracing::info!(cmd = %cmd, mode = ?spec.mode, "run started");
+    let _ = tx_ui.send(UiMsg::Started(cmd.clone()));
+
+    let started_at = Instant::now();
+    // removed when dropped, however this function returns
+    let (_scratch, spawned) = match spec.scratch.then(ScratchDir::create).transpose() {
+        Ok(scratch) => {
+            if let Some(dir) = &scratch {
+                spec.cwd = Some(dir.0.clone());
+                let _ = tx_ui.send(UiMsg::ScratchDir(dir.0.clone()));
+            }
+            let spawned = if spec.pty {
+                spawn_pty(&spec)
+            } else {
+                spawn_piped(&spec)
+            };
+            (scratch, spawned)
+        }
+        Err(e) => (None, Err(e)),
+    };
+    let Spawned {
+        mut process,
+        stdin,
+        stdout,
+        stderr,
+    } = match spawned {
+        Ok(spawned) => spawned,
+        Err(e) => {
+            let _ = tx_ui.send(UiMsg::Finished(ExecResult {
+                cmd,
+                status: Exit::Unknown,
+                stdout: String::new(),
+                stderr: e.to_string(),
+                duration: Duration::ZERO,
+                usage: None,
+                cancelled: false,
+                spawn_failed: true,
+            }));
+            return Flow::Continue;
+        }
+    };
+    tracing::debug!(pid = process.id(), pty = spec.pty, "spawned");
+    let _ = tx_ui.send(UiMsg::Spawned(process.id()));
+
+    // written from its own thread so a command that doesn't read all of it
+    // (e.g. `head`) can't block the worker; dropping the pipe sends EOF
+    if let (Some(input), Some(mut stdin)) = (spec.stdin, stdin) {
+        thread::spawn(move || {
+            let _ = stdin.write_all(input.as_bytes());
+            if spec.pty {
+                // a terminal has no pipe to close: send its EOF character
+                let _ = stdin.write_all(b"\x04");
+            }
+        });
+    }
+
+    let stdout_log = Arc::new(Mutex::new(String::new()));
+    let stderr_log = Arc::new(Mutex::new(String::new()));
+
+    let (tx_stdout_chunk, rx_stdout_chunk) = unbounded::<String>();
+    let (tx_stderr_chunk, rx_stderr_chunk) = unbounded::<String>();
+
+    let stdout_handle = stdout.map(|stdout| {
+        let tx_chunk = tx_stdout_chunk.clone();
+        let log = Arc::clone(&stdout_log);
+        thread::spawn(move || stream_pipe(stdout, tx_chunk, log))
     });
+
+    let stderr_handle = stderr.map(|stderr| {
+        let tx_chunk = tx_stderr_chunk.clone();
+        let log = Arc::clone(&stderr_log);
+        thread::spawn(move || stream_pipe(stderr, tx_chunk, log))
+    });
+
+    drop(tx_stdout_chunk);
+    drop(tx_stderr_chunk);
+
+    let agg_tx = tx_ui.clone();
+    let (tx_stop, rx_stop) = crossbeam_channel::bounded::<()>(0);
+    let aggregator = thread::spawn(move || {
+        aggregate_streams(rx_stdout_chunk, rx_stderr_chunk, rx_stop, agg_tx);
+    });
+
+    // poll the child so control messages are seen while it runs
+    let mut flow = Flow::Continue;
+    let mut cancelled = false;
+    let reaped = loop {
+        match process.try_wait() {
+            Ok(Some(reaped)) => break Ok(reaped),
+            Ok(None) => {}
+            Err(e) => break Err(e),
+        }
+        match rx.recv_timeout(CHILD_POLL_INTERVAL) {
+            Ok(WorkerMsg::Run(next)) => {
+                if let Some(superseded) = queued.replace(next) {
+                    tracing::debug!(superseded = %superseded.cmd, "coalescing queued run");
+                }
+            }
+            Ok(WorkerMsg::Cancel) => {
+                tracing::debug!(pid = process.id(), "cancel requested, killing child");
+                process.kill();
+                queued.take();
+                cancelled = true;
+                break process.wait();
+            }
+            Ok(WorkerMsg::Shutdown) | Err(RecvTimeoutError::Disconnected) => {
+                tracing::debug!(pid = process.id(), "shutdown requested, killing child");
+                process.kill();
+                flow = Flow::Shutdown;
+                break process.wait();
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+        }
+    };
+    let duration = started_at.elapsed();
+    let (status, usage) = match reaped {
+        Ok((status, usage)) => (Ok(status), usage),
+        Err(e) => (Err(e), None),
+    };
+
+    // the readers end when the last process holding the pipes does; past
+    // the grace they are left to it and what they read goes nowhere
+    let pgid = process.id();
+    let readers: Vec<JoinHandle<()>> = [stdout_handle, stderr_handle]
+        .into_iter()
+        .flatten()
+        .collect();
+    let deadline = Instant::now() + DRAIN_GRACE;
+    while flow == Flow::Continue
+        && Instant::now() < deadline
+        && !readers.iter().all(JoinHandle::is_finished)
+    {
+        match rx.recv_timeout(CHILD_POLL_INTERVAL) {
+            Ok(WorkerMsg::Run(next)) => {
+                queued.replace(next);
+            }
+            Ok(WorkerMsg::Cancel) => {
+                kill_group(pgid);
+                queued.take();
+                cancelled = true;
+                break;
+            }
+            Ok(WorkerMsg::Shutdown) | Err(RecvTimeoutError::Disconnected) => {
+                kill_group(pgid);
+                flow = Flow::Shutdown;
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+        }
+    }
+    if !readers.iter().all(JoinHandle::is_finished) {
+        tracing::debug!(
+            pid = pgid,
+            "output still open after exit, no longer waiting for it"
+        );
+    }
+    drop(tx_stop);
+    let _ = aggregator.join();
+
+    if flow == Flow::Shutdown {
+        return flow;
+    }
+
+    let status = status.unwrap_or(Exit::Unknown);
+    tracing::info!(cmd = %cmd, %status, ?duration, "run finished");
+
+    let stdout = stdout_log.lock().map(|buf| buf.clone()).unwrap_or_default();
+    let stderr = stderr_log.lock().map(|buf| buf.clone()).unwrap_or_default();
+
+    let _ = tx_ui.send(UiMsg::Finished(ExecResult {
+        cmd,
+        status,
+        stdout,
+        stderr,
+        duration,
+        usage,
+        cancelled,
+        spawn_failed: false,
+    }));
+    flow
+}
+
+/// Temporary working directory of a scratch run. Dropping it deletes it
+/// with everything the run left there.
+struct ScratchDir(PathBuf);
+
+impl ScratchDir {
+    fn create() -> Result<Self> {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        loop {
+            let path = std::env::temp_dir().join(format!(
+                "pipetui-run-{}-{}",
+                std::process::id(),
+                NEXT.fetch_add(1, Ordering::Relaxed)
+            ));
+            match std::fs::create_dir(&path) {
+                Ok(()) => return Ok(ScratchDir(path)),
+                // left behind by an earlier pipetui with the same pid
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(Error::io(path)(e)),
+            }
+        }
+    }
+}
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_dir_all(&self.0) {
+            tracing::warn!(dir = %self.0.display(), error = %e, "cannot remove scratch dir");
+        }
+    }
+}
+
+/// A spawned command with the ends of its standard streams that were
+/// redirected.
+struct Spawned {
+    process: Process,
+    stdin: Option<Box<dyn Write + Send>>,
+    stdout: Option<Box<dyn Read + Send>>,
+    stderr: Option<Box<dyn Read + Send>>,
 }
 
-fn stream_pipe(pipe: impl Read, tx: Sender<String>, log: Arc<Mutex<String>>) {
-    let mut reader = BufReader::new(pipe);
-    let mut buf = Vec::with_capacity(4096);
+enum Process {
+    Piped(Child),
+    /// Under a pseudo-terminal. `_master` keeps the terminal open until the
+    /// run is over so its output can be drained.
+    Pty {
+        child: Box<dyn portable_pty::Child + Send + Sync>,
+        _master: Box<dyn MasterPty + Send>,
+    },
+}
+
+impl Process {
+    fn id(&self) -> u32 {
+        match self {
+            Process::Piped(child) => child.id(),
+            Process::Pty { child, .. } => child.process_id().unwrap_or(0),
+        }
+    }
+
+    /// How the child ended, if it has, and what it used where the
+    /// platform says.
+    fn try_wait(&mut self) -> io::Result<Option<(Exit, Option<ResourceUsage>)>> {
+        #[cfg(unix)]
+        if self.id() != 0 {
+            return Ok(reap(self.id(), false)?.map(|(exit, usage)| (exit, Some(usage))));
+        }
+        let exit = match self {
+            Process::Piped(child) => child.try_wait()?.map(Exit::from),
+            Process::Pty { child, .. } => child.try_wait()?.map(Exit::from),
+        };
+        Ok(exit.map(|exit| (exit, None)))
+    }
+
+    fn wait(&mut self) -> io::Result<(Exit, Option<ResourceUsage>)> {
+        #[cfg(unix)]
+        if self.id() != 0 {
+            if let Some((exit, usage)) = reap(self.id(), true)? {
+                return Ok((exit, Some(usage)));
+            }
+        }
+        let exit = match self {
+            Process::Piped(child) => child.wait()?.into(),
+            Process::Pty { child, .. } => child.wait()?.into(),
+        };
+        Ok((exit, None))
+    }
+
+    /// Kill the child and, on Unix, the rest of its process group.
+    fn kill(&mut self) {
+        kill_group(self.id());
+        let _ = match self {
+            Process::Piped(child) => child.kill(),
+            Process::Pty { child, .. } => child.kill(),
+        };
+    }
+}
+
+/// Kill what is left of the process group `pgid` (Unix only): every
+/// stage of a pipeline, and jobs it put in the background, but not what
+/// moved to a session of its own.
+fn kill_group(pgid: u32) {
+    #[cfg(unix)]
+    if pgid != 0 {
+        use nix::sys::signal::{killpg, Signal};
+        use nix::unistd::Pid;
+        let _ = killpg(Pid::from_raw(pgid as i32), Signal::SIGKILL);
+    }
+}
+
+#[cfg(unix)]
+fn disable_echo(fd: std::os::fd::RawFd) {
+    use nix::sys::termios::{tcgetattr, tcsetattr, LocalFlags, SetArg};
+    // SAFETY: `fd` is the pty master, open for the duration of this call
+    let fd = unsafe { std::os::fd::BorrowedFd::borrow_raw(fd) };
+    if let Ok(mut termios) = tcgetattr(fd) {
+        termios.local_flags.remove(LocalFlags::ECHO);
+        let _ = tcsetattr(fd, SetArg::TCSANOW, &termios);
+    }
+}
+
+/// Spawn with piped stdout/stderr (and stdin when there is input for it).
+/// The error's message is shown in the stderr pane.
+fn spawn_piped(spec: &RunSpec) -> Result<Spawned> {
+    let (mut command, program) = build_command(spec).inspect_err(|e| {
+        tracing::warn!(error = %e, "argv parse failed");
+    })?;
+    command.stdout(Stdio::piped()).stderr(Stdio::piped());
+    if spec.stdin.is_some() {
+        command.stdin(Stdio::piped());
+    }
+    // own process group, so a kill reaches every stage of a pipeline
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut command, 0);
+
+    let mut child = command.spawn().map_err(|e| {
+        tracing::warn!(program = %program, error = %e, "spawn failed");
+        let not_found = e.kind() == io::ErrorKind::NotFound;
+        if not_found && spec.mode == ExecMode::Shell {
+            Error::ShellNotFound(program.clone())
+        } else if not_found {
+            Error::CommandNotFound(program.clone())
+        } else {
+            Error::Spawn {
+                program: program.clone(),
+                source: e,
+            }
+        }
+    })?;
+    let stdin = child
+        .stdin
+        .take()
+        .map(|pipe| Box::new(pipe) as Box<dyn Write + Send>);
+    let stdout = child
+        .stdout
+        .take()
+        .map(|pipe| Box::new(pipe) as Box<dyn Read + Send>);
+    let stderr = child
+        .stderr
+        .take()
+        .map(|pipe| Box::new(pipe) as Box<dyn Read + Send>);
+    Ok(Spawned {
+        process: Process::Piped(child),
+        stdin,
+        stdout,
+        stderr,
+    })
+}
+
+/// Spawn under a pseudo-terminal sized like ours, so the command sees a
+/// TTY and keeps colors and progress output. Stdout and stderr share the
+/// terminal and both land in the stdout pane.
+fn spawn_pty(spec: &RunSpec) -> Result<Spawned> {
+    let argv = command_argv(spec).inspect_err(|e| {
+        tracing::warn!(error = %e, "argv parse failed");
+    })?;
+    let failed = |e: anyhow::Error| {
+        tracing::warn!(program = %argv[0], error = %e, "pty spawn failed");
+        Error::Pty(e.to_string())
+    };
+    let (cols, rows) = crossterm::terminal::size().unwrap_or((80, 24));
+    let pair = native_pty_system()
+        .openpty(PtySize {
+            rows,
+            cols,
+            pixel_width: 0,
+            pixel_height: 0,
+        })
+        .map_err(failed)?;
+    let mut command = CommandBuilder::new(&argv[0]);
+    command.args(&argv[1..]);
+    // portable-pty would otherwise start in $HOME
+    match &spec.cwd {
+        Some(cwd) => command.cwd(cwd),
+        None => {
+            if let Ok(cwd) = std::env::current_dir() {
+                command.cwd(cwd);
+            }
+        }
+    }
+    for (key, value) in &spec.env {
+        command.env(key, value);
+    }
+    let child = pair.slave.spawn_command(command).map_err(failed)?;
+    // only the child may hold the slave, or reads never see EOF
+    drop(pair.slave);
+    let stdout = pair.master.try_clone_reader().map_err(failed)?;
+    let stdin = match spec.stdin {
+        Some(_) => {
+            // captured input shouldn't be echoed into the output
+            #[cfg(unix)]
+            if let Some(fd) = pair.master.as_raw_fd() {
+                disable_echo(fd);
+            }
+            Some(pair.master.take_writer().map_err(failed)?)
+        }
+        None => None,
+    };
+    Ok(Spawned {
+        process: Process::Pty {
+            child,
+            _master: pair.master,
+        },
+        stdin,
+        stdout: Some(stdout),
+        stderr: None,
+    })
+}
+
+/// Forward whatever `pipe` produces as soon as it is read, not per line,
+/// so output without a trailing newline (prompts, progress) shows up while
+/// the command is still running.
+fn stream_pipe(mut pipe: impl Read, tx: Sender<String>, log: Arc<Mutex<String>>) {
+    let mut buf = [0u8; 4096];
+    let mut pending = Vec::new();
     loop {
-        buf.clear();
-        match reader.read_until(b'\n', &mut buf) {
+        match pipe.read(&mut buf) {
             Ok(0) => break,
-            Ok(_) => {
-                let chunk = String::from_utf8_lossy(&buf).to_string();
-                if let Ok(mut guard) = log.lock() {
-                    guard.push_str(&chunk);
-                }
-                if tx.send(chunk).is_err() {
-                    break;
+            Ok(n) => pending.extend_from_slice(&buf[..n]),
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(_) => break,
+        }
+        let chunk = take_utf8(&mut pending);
+        if chunk.is_empty() {
+            continue;
+        }
+        if let Ok(mut guard) = log.lock() {
+            guard.push_str(&chunk);
+        }
+        if tx.send(chunk).is_err() {
+            return;
+        }
+    }
+    // a sequence cut off by EOF
+    if !pending.is_empty() {
+        let chunk = String::from_utf8_lossy(&pending).into_owned();
+        if let Ok(mut guard) = log.lock() {
+            guard.push_str(&chunk);
+        }
+        let _ = tx.send(chunk);
+    }
+}
+
+/// Decode `bytes`, leaving a trailing incomplete UTF-8 sequence in place for
+/// the next read to complete. Invalid bytes become U+FFFD.
+fn take_utf8(bytes: &mut Vec<u8>) -> String {
+    let mut out = String::with_capacity(bytes.len());
+    let mut rest = bytes.as_slice();
+    loop {
+        match std::str::from_utf8(rest) {
+            Ok(text) => {
+                out.push_str(text);
+                rest = &[];
+                break;
+            }
+            Err(e) => {
+                let (valid, after) = rest.split_at(e.valid_up_to());
+                out.push_str(std::str::from_utf8(valid).unwrap_or_default());
+                match e.error_len() {
+                    Some(len) => {
+                        out.push(char::REPLACEMENT_CHARACTER);
+                        rest = &after[len..];
+                    }
+                    None => {
+                        rest = after;
+                        break;
+                    }
                 }
             }
-            Err(_) => break,
         }
     }
+    let tail = rest.len();
+    bytes.drain(..bytes.len() - tail);
+    out
 }
 
+/// Forward the chunks the readers send, batched, until both streams end
+/// or `rx_stop` says the run is over; then what already arrived is sent.
 fn aggregate_streams(
-    rx_stdout: Receiver<String>,
-    rx_stderr: Receiver<String>,
+    mut rx_stdout: Receiver<String>,
+    mut rx_stderr: Receiver<String>,
+    rx_stop: Receiver<()>,
     tx_ui: Sender<UiMsg>,
 ) {
     let ticker = crossbeam_channel::tick(Duration::from_millis(250));
@@ -151,21 +962,41 @@ fn aggregate_streams(
 
     loop {
         crossbeam_channel::select! {
+            // batches are cut whenever the other stream speaks, so the UI
+            // still sees stdout and stderr in the order they were written
             recv(rx_stdout) -> msg => match msg {
                 Ok(chunk) => {
+                    if !pending_stderr.is_empty() {
+                        let _ = tx_ui.send(UiMsg::StderrChunk(std::mem::take(&mut pending_stderr)));
+                    }
                     pending_stdout.push_str(&chunk);
                     continue;
                 }
-                Err(_) => stdout_open = false,
+                Err(_) => {
+                    // a closed channel is always ready: stop selecting it
+                    stdout_open = false;
+                    rx_stdout = crossbeam_channel::never();
+                }
             },
             recv(rx_stderr) -> msg => match msg {
                 Ok(chunk) => {
+                    if !pending_stdout.is_empty() {
+                        let _ = tx_ui.send(UiMsg::StdoutChunk(std::mem::take(&mut pending_stdout)));
+                    }
                     pending_stderr.push_str(&chunk);
                     continue;
                 }
-                Err(_) => stderr_open = false,
+                Err(_) => {
+                    stderr_open = false;
+                    rx_stderr = crossbeam_channel::never();
+                }
             },
             recv(ticker) -> _ => {},
+            recv(rx_stop) -> _ => {
+                pending_stdout.extend(rx_stdout.try_iter());
+                pending_stderr.extend(rx_stderr.try_iter());
+                break;
+            }
         }
 
         if !pending_stdout.is_empty() {
@@ -177,10 +1008,8 @@ fn aggregate_streams(
             let _ = tx_ui.send(UiMsg::StderrChunk(chunk));
         }
 
-        if !stdout_open && !stderr_open {
-            if pending_stdout.is_empty() && pending_stderr.is_empty() {
-                break;
-            }
+        if !stdout_open && !stderr_open && pending_stdout.is_empty() && pending_stderr.is_empty() {
+            break;
         }
     }
 
@@ -191,3 +1020,193 @@ fn aggregate_streams(
         let _ = tx_ui.send(UiMsg::StderrChunk(pending_stderr));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(cmd: &str) -> RunSpec {
+        RunSpec {
+            cmd: cmd.into(),
+            mode: ExecMode::Shell,
+            shell: None,
+            cwd: None,
+            env: BTreeMap::new(),
+            stdin: None,
+            pty: false,
+            scratch: false,
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn hook_values_are_not_parsed_by_the_shell() {
+        let dir = std::env::temp_dir().join(format!("pipetui-test-{}-hook", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let cmd = r#"echo "$(touch pwned)"; touch pwned2 '"#;
+        let res = ExecResult {
+            cmd: cmd.into(),
+            status: Exit::Code(3),
+            stdout: String::new(),
+            stderr: String::new(),
+            duration: Duration::from_millis(1500),
+            usage: None,
+            cancelled: false,
+            spawn_failed: false,
+        };
+        let spec = RunSpec {
+            cwd: Some(dir.clone()),
+            ..spec(cmd)
+        };
+        let hook = r#"printf '%s|%s|%s' "{cmd}" {status} {duration} > out.tmp && mv out.tmp out"#;
+        spawn_hook(hook, &res, &spec).unwrap();
+        let out = dir.join("out");
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !out.exists() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(
+            std::fs::read_to_string(&out).unwrap(),
+            format!("{cmd}|3|1.50")
+        );
+        assert!(!dir.join("pwned").exists() && !dir.join("pwned2").exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn shell_line_spells_out_the_cwd() {
+        let here = std::env::current_dir().unwrap();
+        let line = spec("ls").shell_line();
+        assert!(line.starts_with(&format!("cd {} && ", quote_word(&here.to_string_lossy()))));
+        let spec = RunSpec {
+            cwd: Some("/tmp/my dir".into()),
+            env: BTreeMap::from([("A".into(), "1 2".into())]),
+            mode: ExecMode::Direct,
+            ..spec("ls -l")
+        };
+        assert_eq!(spec.shell_line(), "cd '/tmp/my dir' && A='1 2' ls -l");
+        let spec = RunSpec {
+            cwd: Some("/tmp".into()),
+            mode: ExecMode::Direct,
+            ..self::spec(r#"rm *.bak "$HOME" ~/x {a,b} 'it''s'"#)
+        };
+        assert_eq!(
+            spec.shell_line(),
+            r#"cd /tmp && rm '*.bak' '$HOME' '~/x' '{a,b}' its"#
+        );
+        let spec = RunSpec {
+            mode: ExecMode::Direct,
+            ..self::spec("X=1 env")
+        };
+        assert!(spec.shell_line().ends_with(" 'X=1' env"));
+    }
+
+    #[test]
+    fn rapid_runs_coalesce_to_the_newest() {
+        let (tx, rx) = unbounded();
+        for cmd in ["echo 1", "echo 2", "echo 3"] {
+            tx.send(WorkerMsg::Run(spec(cmd))).unwrap();
+        }
+        assert_eq!(
+            next_run(None, &rx).map(|spec| spec.cmd).as_deref(),
+            Some("echo 3")
+        );
+        // the queued one loses to a newer send too
+        tx.send(WorkerMsg::Run(spec("echo 4"))).unwrap();
+        let next = next_run(Some(spec("echo queued")), &rx);
+        assert_eq!(next.map(|spec| spec.cmd).as_deref(), Some("echo 4"));
+    }
+
+    #[test]
+    fn cancel_drops_runs_sent_before_it() {
+        let (tx, rx) = unbounded();
+        tx.send(WorkerMsg::Run(spec("echo stale"))).unwrap();
+        tx.send(WorkerMsg::Cancel).unwrap();
+        tx.send(WorkerMsg::Run(spec("echo fresh"))).unwrap();
+        assert_eq!(
+            next_run(None, &rx).map(|spec| spec.cmd).as_deref(),
+            Some("echo fresh")
+        );
+        tx.send(WorkerMsg::Shutdown).unwrap();
+        assert!(next_run(None, &rx).is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn output_without_a_newline_reaches_the_ui() {
+        let (tx_worker, rx_worker) = unbounded();
+        let (tx_ui, rx_ui) = unbounded();
+        let worker = spawn_worker(rx_worker, tx_ui);
+        // a prompt that waits: the text must show before the run ends
+        tx_worker
+            .send(WorkerMsg::Run(spec("printf 'Continue? '; sleep 2")))
+            .unwrap();
+        let prompt = rx_ui
+            .iter()
+            .find_map(|msg| match msg {
+                UiMsg::StdoutChunk(chunk) => Some(chunk),
+                UiMsg::Finished(_) => Some(String::new()),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(prompt, "Continue? ");
+        tx_worker.send(WorkerMsg::Shutdown).unwrap();
+        worker.join().unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn cancel_while_draining_reports_the_run_cancelled() {
+        let (tx_worker, rx_worker) = unbounded();
+        let (tx_ui, rx_ui) = unbounded();
+        let worker = spawn_worker(rx_worker, tx_ui);
+        // the shell exits at once, the background job keeps stdout open
+        tx_worker
+            .send(WorkerMsg::Run(spec("sleep 3 & exit 0")))
+            .unwrap();
+        rx_ui
+            .iter()
+            .find(|msg| matches!(msg, UiMsg::Spawned(_)))
+            .unwrap();
+        thread::sleep(DRAIN_GRACE / 3);
+        tx_worker.send(WorkerMsg::Cancel).unwrap();
+        let res = rx_ui
+            .iter()
+            .find_map(|msg| match msg {
+                UiMsg::Finished(res) => Some(res),
+                _ => None,
+            })
+            .unwrap();
+        assert!(res.cancelled);
+        tx_worker.send(WorkerMsg::Shutdown).unwrap();
+        worker.join().unwrap();
+    }
+
+    #[test]
+    fn worker_runs_only_the_newest_of_a_burst() {
+        let (tx_worker, rx_worker) = unbounded();
+        let (tx_ui, rx_ui) = unbounded();
+        // queued before the worker looks, as when it was busy
+        for n in 1..=5 {
+            tx_worker
+                .send(WorkerMsg::Run(spec(&format!("echo {n}"))))
+                .unwrap();
+        }
+        let worker = spawn_worker(rx_worker, tx_ui);
+        let started: Vec<String> = rx_ui
+            .iter()
+            .filter_map(|msg| match msg {
+                UiMsg::Started(cmd) => Some(cmd),
+                UiMsg::Finished(_) => {
+                    tx_worker.send(WorkerMsg::Shutdown).ok();
+                    None
+                }
+                _ => None,
+            })
+            .collect();
+        worker.join().unwrap();
+        assert_eq!(started, ["echo 5"]);
+    }
+}
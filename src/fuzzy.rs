@@ -0,0 +1,152 @@
+/// A fuzzy match of a query against some text: higher `score` is better,
+/// `positions` are the byte offsets of the matched characters in the text.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FuzzyMatch {
+    pub score: i64,
+    pub positions: Vec<usize>,
+}
+
+const MATCH: i64 = 16;
+const CONSECUTIVE: i64 = 8;
+const WORD_START: i64 = 8;
+
+/// Match the characters of `query` in order in `text`, ignoring case and
+/// whitespace in the query, and pick the best-scoring placement: every
+/// match scores, consecutive ones and ones starting a word score extra,
+/// each skipped character and a late start cost. So in `git checkout`,
+/// `gco` matches the `c` of `checkout` rather than the one after `e`.
+/// `None` when the query doesn't fit at all.
+///
+/// Kept here rather than taken from `fuzzy-matcher` or `nucleo`: history
+/// entries are short, so one pass per query character is cheap, and the
+/// scoring stays tuned to shell commands (a word starts after `-`, `/` or
+/// a space) without another dependency.
+pub fn score(query: &str, text: &str) -> Option<FuzzyMatch> {
+    let query: Vec<char> = query
+        .chars()
+        .filter(|ch| !ch.is_whitespace())
+        .flat_map(char::to_lowercase)
+        .collect();
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let same = |idx: usize, wanted: char| chars[idx].1.to_lowercase().eq(Some(wanted));
+
+    // cheap check first, most entries don't match at all
+    let mut wanted = query.iter().peekable();
+    for idx in 0..chars.len() {
+        if wanted.next_if(|&&ch| same(idx, ch)).is_some() && wanted.peek().is_none() {
+            break;
+        }
+    }
+    if wanted.peek().is_some() {
+        return None;
+    }
+    if query.is_empty() {
+        return Some(FuzzyMatch {
+            score: 0,
+            positions: Vec::new(),
+        });
+    }
+
+    // best[i][j]: best score with query[..=i] matched and query[i] at
+    // chars[j]; from[i][j]: where query[i - 1] was then. The gap cost is
+    // linear, so the best earlier non-adjacent match is a running maximum
+    // of best + position and each row is a single pass.
+    let n = chars.len();
+    let mut best = vec![vec![None::<i64>; n]; query.len()];
+    let mut from = vec![vec![0; n]; query.len()];
+    for (i, &ch) in query.iter().enumerate() {
+        let mut before: Option<(i64, usize)> = None;
+        for j in 0..n {
+            if let Some(k) = j.checked_sub(2).filter(|_| i > 0) {
+                if let Some(prev) = best[i - 1][k] {
+                    if before.is_none_or(|(value, _)| prev + k as i64 > value) {
+                        before = Some((prev + k as i64, k));
+                    }
+                }
+            }
+            if !same(j, ch) {
+                continue;
+            }
+            let mut gain = MATCH;
+            if j == 0 || !chars[j - 1].1.is_alphanumeric() {
+                gain += WORD_START;
+            }
+            if i == 0 {
+                best[i][j] = Some(gain - j as i64 / 4);
+                continue;
+            }
+            let adjacent = j
+                .checked_sub(1)
+                .and_then(|k| Some((best[i - 1][k]? + CONSECUTIVE, k)));
+            let gapped = before.map(|(value, k)| (value - j as i64 + 1, k));
+            let pick = match (adjacent, gapped) {
+                (Some(a), Some(g)) => Some(if g.0 > a.0 { g } else { a }),
+                (a, g) => a.or(g),
+            };
+            if let Some((score, k)) = pick {
+                best[i][j] = Some(score + gain);
+                from[i][j] = k;
+            }
+        }
+    }
+
+    let last = query.len() - 1;
+    let (mut j, score) = best[last]
+        .iter()
+        .enumerate()
+        .filter_map(|(j, score)| Some((j, (*score)?)))
+        .max_by_key(|&(j, score)| (score, std::cmp::Reverse(j)))?;
+    let mut positions = vec![0; query.len()];
+    for i in (0..=last).rev() {
+        positions[i] = chars[j].0;
+        j = from[i][j];
+    }
+    Some(FuzzyMatch { score, positions })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranks_above(query: &str, better: &str, worse: &str) -> bool {
+        score(query, better).unwrap().score > score(query, worse).unwrap().score
+    }
+
+    #[test]
+    fn prefix_beats_scattered() {
+        assert!(ranks_above("git", "git status", "grep -i text"));
+        assert!(ranks_above("make", "make test", "cmake -B target"));
+    }
+
+    #[test]
+    fn consecutive_beats_gapped() {
+        assert!(ranks_above("log", "git log", "ls -l /opt/go"));
+    }
+
+    #[test]
+    fn word_starts_are_preferred() {
+        let found = score("gco", "git checkout").unwrap();
+        assert_eq!(found.positions, [0, 4, 9]);
+    }
+
+    #[test]
+    fn case_is_ignored() {
+        assert_eq!(score("GIT", "git"), score("git", "git"));
+        assert_eq!(score("git", "GIT").unwrap().positions, [0, 1, 2]);
+        assert!(score("café", "CAFÉ").is_some());
+    }
+
+    #[test]
+    fn positions_are_byte_offsets() {
+        let found = score("ïve", "naïve café").unwrap();
+        assert_eq!(found.positions, [2, 4, 5]);
+        assert_eq!(score("é", "naïve café").unwrap().positions, [10]);
+    }
+
+    #[test]
+    fn query_must_match_in_order() {
+        assert_eq!(score("tig", "git"), None);
+        assert_eq!(score("i", "ï"), None);
+        assert_eq!(score(" g i ", "git").unwrap().positions, [0, 1]);
+    }
+}
@@ -0,0 +1,156 @@
+use std::io::{self, Write};
+use crossbeam_channel::unbounded;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::terminal;
+use crate::error::Result;
+use crate::execution::{spawn_worker, UiMsg, WorkerMsg};
+use crate::history::App;
+use crate::utility;
+use crate::Error;
+
+/// Run `cmd` once without the TUI, streaming stdout/stderr to the real
+/// stdout/stderr wrapped at `width` columns. With `page_height`, stdout
+/// pauses at a `-- more --` prompt every screenful of that many rows;
+/// quitting there stops the command and ends with status 0, as `more`
+/// does. Returns the command's status.
+pub fn run(cmd: &str, width: u16, page_height: Option<u16>) -> Result<i32> {
+    let app = App::new();
+    let (tx_worker, rx_worker) = unbounded::<WorkerMsg>();
+    let (tx_ui, rx_ui) = unbounded::<UiMsg>();
+    let worker = spawn_worker(rx_worker, tx_ui);
+    tx_worker
+        .send(WorkerMsg::Run(app.run_spec(cmd)))
+        .map_err(|_| Error::WorkerGone)?;
+
+    let mut out = WrappedWriter::new(io::stdout().lock(), width as usize);
+    out.pager = page_height.map(Pager::new);
+    let mut err = WrappedWriter::new(io::stderr().lock(), width as usize);
+    let mut status = -1;
+    for msg in rx_ui.iter() {
+        match msg {
+            UiMsg::Started(_) | UiMsg::Spawned(_) | UiMsg::ScratchDir(_) => {}
+            UiMsg::StdoutChunk(chunk) => {
+                let quit_before = out.quit();
+                out.push(&chunk).map_err(Error::Output)?;
+                if out.quit() && !quit_before {
+                    tx_worker.send(WorkerMsg::Cancel).ok();
+                }
+            }
+            UiMsg::StderrChunk(chunk) => err.push(&chunk).map_err(Error::Output)?,
+            UiMsg::Finished(res) => {
+                status = if out.quit() { 0 } else { res.status.code() };
+                break;
+            }
+        }
+    }
+    out.finish().map_err(Error::Output)?;
+    err.finish().map_err(Error::Output)?;
+
+    tx_worker.send(WorkerMsg::Shutdown).ok();
+    let _ = worker.join();
+    Ok(status)
+}
+
+/// Pauses output every screenful at a `-- more --` prompt (`--page`).
+struct Pager {
+    /// Rows per screenful, the prompt's row included.
+    height: usize,
+    /// Rows still to write before the next prompt.
+    left: usize,
+    /// The user quit at a prompt; nothing more is written.
+    quit: bool,
+}
+
+impl Pager {
+    fn new(height: u16) -> Self {
+        let height = (height as usize).max(2);
+        Self {
+            height,
+            left: height - 1,
+            quit: false,
+        }
+    }
+
+    /// Wait at the prompt for `Space` (next screenful), `Enter` (next
+    /// row) or `q` (stop), reading keys from the terminal in raw mode.
+    fn prompt(&mut self, out: &mut impl Write) -> io::Result<()> {
+        write!(out, "\x1b[7m-- more --\x1b[0m")?;
+        out.flush()?;
+        terminal::enable_raw_mode()?;
+        let key = loop {
+            match event::read() {
+                Ok(Event::Key(key)) if key.kind != KeyEventKind::Release => break Ok(key),
+                Ok(_) => {}
+                Err(e) => break Err(e),
+            }
+        };
+        terminal::disable_raw_mode()?;
+        write!(out, "\r\x1b[K")?;
+        let key = key?;
+        match key.code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => self.quit = true,
+            KeyCode::Char('q' | 'Q') | KeyCode::Esc => self.quit = true,
+            KeyCode::Enter | KeyCode::Down | KeyCode::Char('j') => self.left = 1,
+            _ => self.left = self.height - 1,
+        }
+        Ok(())
+    }
+}
+
+/// Writes streamed chunks line by line, wrapping each line at `width`.
+struct WrappedWriter<W: Write> {
+    inner: W,
+    width: usize,
+    partial: String,
+    pager: Option<Pager>,
+}
+
+impl<W: Write> WrappedWriter<W> {
+    fn new(inner: W, width: usize) -> Self {
+        Self {
+            inner,
+            width,
+            partial: String::new(),
+            pager: None,
+        }
+    }
+
+    /// Whether the user quit at the pager's prompt.
+    fn quit(&self) -> bool {
+        self.pager.as_ref().is_some_and(|pager| pager.quit)
+    }
+
+    fn push(&mut self, chunk: &str) -> io::Result<()> {
+        self.partial.push_str(chunk);
+        while let Some(pos) = self.partial.find('\n') {
+            let line = self.partial[..pos].trim_end_matches('\r').to_string();
+            self.partial.drain(..=pos);
+            self.write_line(&line)?;
+        }
+        self.inner.flush()
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if !self.partial.is_empty() {
+            let line = std::mem::take(&mut self.partial);
+            self.write_line(line.trim_end_matches('\r'))?;
+        }
+        self.inner.flush()
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        for row in utility::wrap_line(line, self.width) {
+            if let Some(pager) = &mut self.pager {
+                if pager.left == 0 && !pager.quit {
+                    pager.prompt(&mut self.inner)?;
+                }
+                if pager.quit {
+                    return Ok(());
+                }
+                pager.left -= 1;
+            }
+            writeln!(self.inner, "{row}")?;
+        }
+        Ok(())
+    }
+}
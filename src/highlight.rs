@@ -0,0 +1,104 @@
+use std::borrow::Cow;
+use std::str::FromStr;
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use regex::Regex;
+use crate::config::HighlightRule;
+
+/// A highlight rule with its regex compiled once at startup.
+pub struct Highlighter {
+    regex: Regex,
+    style: Style,
+}
+
+/// Compile the configured rules. Invalid ones are skipped and reported
+/// as a single message for the status line.
+pub fn compile_rules(rules: &[HighlightRule]) -> (Vec<Highlighter>, Option<String>) {
+    let mut compiled = Vec::new();
+    let mut errors = Vec::new();
+    for rule in rules {
+        let style = match rule_style(rule) {
+            Ok(style) => style,
+            Err(e) => {
+                errors.push(format!("{}: {e}", rule.pattern));
+                continue;
+            }
+        };
+        match Regex::new(&rule.pattern) {
+            Ok(regex) => compiled.push(Highlighter { regex, style }),
+            Err(e) => errors.push(format!("{}: {e}", rule.pattern)),
+        }
+    }
+    let error =
+        (!errors.is_empty()).then(|| format!("highlight rules skipped: {}", errors.join("; ")));
+    (compiled, error)
+}
+
+fn rule_style(rule: &HighlightRule) -> Result<Style, String> {
+    let mut style = Style::default();
+    if let Some(fg) = &rule.fg {
+        style = style.fg(Color::from_str(fg).map_err(|_| format!("unknown color '{fg}'"))?);
+    }
+    if let Some(bg) = &rule.bg {
+        style = style.bg(Color::from_str(bg).map_err(|_| format!("unknown color '{bg}'"))?);
+    }
+    if rule.bold {
+        style = style.add_modifier(Modifier::BOLD);
+    }
+    Ok(style)
+}
+
+/// Split `line` into spans styled by the first rule matching each region.
+/// Earlier rules win where matches overlap.
+pub fn highlight_line<'a>(line: &'a str, rules: &[Highlighter]) -> Line<'a> {
+    if rules.is_empty() {
+        return Line::from(line);
+    }
+    let mut matches: Vec<(usize, usize, Style)> = Vec::new();
+    for rule in rules {
+        for m in rule.regex.find_iter(line) {
+            if m.is_empty() {
+                continue;
+            }
+            let overlaps = matches
+                .iter()
+                .any(|&(start, end, _)| m.start() < end && start < m.end());
+            if !overlaps {
+                matches.push((m.start(), m.end(), rule.style));
+            }
+        }
+    }
+    if matches.is_empty() {
+        return Line::from(line);
+    }
+    matches.sort_by_key(|&(start, _, _)| start);
+
+    let mut spans = Vec::with_capacity(matches.len() * 2 + 1);
+    let mut pos = 0;
+    for (start, end, style) in matches {
+        if start > pos {
+            spans.push(Span::raw(&line[pos..start]));
+        }
+        spans.push(Span::styled(&line[start..end], style));
+        pos = end;
+    }
+    if pos < line.len() {
+        spans.push(Span::raw(&line[pos..]));
+    }
+    Line::from(spans)
+}
+
+/// `highlight_line` for a line that may have been rewritten for display,
+/// in which case the spans own their text.
+pub fn highlight_cow<'a>(line: Cow<'a, str>, rules: &[Highlighter]) -> Line<'a> {
+    match line {
+        Cow::Borrowed(line) => highlight_line(line, rules),
+        Cow::Owned(line) => Line::from(
+            highlight_line(&line, rules)
+                .spans
+                .into_iter()
+                .map(|span| Span::styled(span.content.into_owned(), span.style))
+                .collect::<Vec<_>>(),
+        ),
+    }
+}
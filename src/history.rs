@@ -1,153 +1,1322 @@
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::collections::{HashSet, VecDeque};
 use std::path::PathBuf;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime};
 use ratatui::layout::Rect;
-use ratatui::text::Line;
-use crate::execution::ExecResult;
-use crate::persistence::{self, HISTORY_LIMIT};
-use crate::parser::{next_grapheme_boundary, prev_grapheme_boundary};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use unicode_width::UnicodeWidthStr;
+use crate::action::{self, Action};
+use crate::columns::ColumnCut;
+use crate::error::Result;
+use crate::config::{Config, ControlChars, FailureAlert, Timestamps};
+use crate::highlight::{self, Highlighter};
+use crate::fuzzy;
+use crate::notes::Notes;
+use crate::execution::{self, ExecMode, ExecResult, Exit, RunSpec};
+use crate::persistence::{self, UiState, HISTORY_LIMIT};
+use crate::table::{self, Tabular};
+use crate::parser::{
+    self, next_grapheme_boundary, next_word_boundary, prev_grapheme_boundary, prev_word_boundary,
+    column_offset, cursor_column, is_incomplete, last_word, quote_word, strip_comment, word_before,
+};
+use crate::utility;
+
+/// Output panes that can be maximized with the zoom toggle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Pane {
+    Stdout,
+    Stderr,
+}
+
+/// Part of a session that receives keys; `Tab` cycles through them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Focus {
+    Input,
+    Stdout,
+    Stderr,
+    /// The notes pane, while it is shown.
+    Notes,
+}
+
+/// Editing mode of the input when `vi_mode` is on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EditMode {
+    /// Keys type into the input, as without `vi_mode`.
+    Insert,
+    /// Keys are vi motions and commands.
+    Normal,
+}
+
+/// How one output pane is viewed, independently of the other.
+#[derive(Debug)]
+pub struct PaneView {
+    /// First line shown; `None` follows the end.
+    pub top: Option<usize>,
+    /// Columns scrolled off to the left while not wrapping.
+    pub left: usize,
+    /// Wrap long lines; otherwise they are cut at the pane's edge.
+    pub wrap: bool,
+    /// First line actually shown by the last frame, updated while rendering.
+    pub shown: Cell<usize>,
+}
+
+impl PaneView {
+    fn new(wrap: bool) -> Self {
+        Self {
+            top: None,
+            left: 0,
+            wrap,
+            shown: Cell::new(0),
+        }
+    }
+}
+
+/// Quiet period after the last edit before an auto-run fires.
+pub const AUTO_RUN_DEBOUNCE: Duration = Duration::from_millis(250);
+
+/// Quiet period after the last edit before the input is saved as a draft.
+pub const DRAFT_SAVE_IDLE: Duration = Duration::from_secs(2);
+
+/// Silence after which a run without captured stdin is suspected of
+/// waiting for input.
+pub const STDIN_HINT_AFTER: Duration = Duration::from_secs(3);
+
+/// How often the screen refreshes while a command runs, for its output
+/// and the silent-run hint.
+pub const RUNNING_TICK: Duration = Duration::from_millis(100);
+
+/// Consecutive failures of the same command after which it no longer
+/// auto-runs; `Enter` still runs it.
+pub const AUTO_RUN_FAILURE_LIMIT: u32 = 3;
+
+/// Lifecycle of the input and the command it runs.
+///
+/// `Idle --edit--> Pending --settled--> Running --finish--> Finished`;
+/// an edit in `Finished` goes back to `Pending`. An edit while `Running`
+/// queues the input in `App::pending_cmd`, which is dispatched as soon as
+/// the current run finishes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RunState {
+    /// Nothing has been edited or run yet.
+    Idle,
+    /// The input changed at `edited_at` and hasn't been run since.
+    Pending { edited_at: Instant },
+    /// A run was dispatched to the worker. `manual` runs are added to
+    /// history when they finish.
+    Running { manual: bool },
+    /// The last run completed and the input hasn't changed since.
+    Finished,
+}
+
+/// Maximum number of runs kept in the session run log.
+pub const RUN_LOG_LIMIT: usize = 100;
+
+/// Outcome of one run this session, shown in the run log overlay.
+#[derive(Clone, Debug)]
+pub struct RunRecord {
+    pub cmd: String,
+    pub status: Exit,
+    pub duration: Duration,
+}
+
+/// Popups drawn over the panes; while one is open it receives all keys.
+#[derive(Clone, Debug)]
+pub enum Overlay {
+    /// What the armed dry run would have executed.
+    DryRun(Vec<String>),
+    /// Session run log, newest first; `selected` indexes into that order.
+    RunLog { selected: usize },
+    /// `wc`-style summary of the current stdout.
+    Stats(Vec<String>),
+    /// Command palette: actions matching `query`, `selected` indexing the
+    /// filtered list.
+    Palette { query: String, selected: usize },
+    /// Input saved by a previous session that didn't exit cleanly.
+    RestoreDraft(String),
+    /// Asks before wiping the saved history.
+    ConfirmClearHistory,
+    /// History search (`Ctrl+R`): entries fuzzily matching `query`, best
+    /// first, `selected` indexing `matches`.
+    HistorySearch {
+        query: String,
+        matches: Vec<HistoryMatch>,
+        selected: usize,
+    },
+}
+
+/// A history entry found by the history search.
+#[derive(Clone, Debug)]
+pub struct HistoryMatch {
+    /// Index into `App::history`.
+    pub entry: usize,
+    /// Byte offsets of the characters that matched the query.
+    pub positions: Vec<usize>,
+}
+
+/// Columns taken by the timestamp gutter, its trailing space included.
+const GUTTER_WIDTH: usize = 10;
+
+/// Lines scanned when counting unique stdout lines, so the stats popup
+/// stays instant on huge buffers.
+pub const UNIQUE_SCAN_LIMIT: usize = 100_000;
+
+/// Output pinned as a known-good baseline that later runs are compared to.
+#[derive(Clone, Debug)]
+pub struct PinnedOutput {
+    /// The command that produced the baseline.
+    pub label: String,
+    pub lines: Vec<String>,
+    /// Result for the most recent run since pinning, if any.
+    pub comparison: Option<PinComparison>,
+}
+
+/// Output from before `:pipe` ran it through a command, to go back to.
+#[derive(Clone, Debug)]
+pub struct PipeSource {
+    /// The command that produced it.
+    pub cmd: Option<String>,
+    pub lines: Vec<String>,
+    pub error_lines: Vec<String>,
+    pub raw_stdout: String,
+}
+
+/// When an output line arrived, for the timestamp gutter.
+#[derive(Clone, Copy, Debug)]
+pub struct Stamp {
+    pub at: SystemTime,
+    /// Time since its run started.
+    pub offset: Duration,
+}
+
+/// What one output pane holds, before fitting it to an area.
+struct PaneContent<'a> {
+    lines: &'a [String],
+    /// Unterminated last line of a running command.
+    tail: &'a str,
+    /// Command echoed in a header row above the output (`echo_command`).
+    echo: Option<&'a str>,
+    /// Lines evicted by the line cap, announced in a notice row.
+    dropped: usize,
+    /// Start no later than this line instead of the view's own top.
+    anchor: Option<usize>,
+    /// Scroll and wrap of the pane; receives the first line shown.
+    view: &'a PaneView,
+    /// Lines drawn reversed.
+    selected: Option<std::ops::RangeInclusive<usize>>,
+    /// Stderr lines shown inline before the line at their position.
+    marks: &'a [(usize, String)],
+    control_chars: ControlChars,
+    /// Columns to show of each line instead of the whole line.
+    columns: Option<&'a ColumnCut>,
+    /// Arrival of each line, shown in a gutter as `timestamps` says.
+    stamps: &'a [Stamp],
+    timestamps: Timestamps,
+    /// Show a run of identical lines as its first, with a `(×N)` count.
+    fold_repeats: bool,
+}
+
+impl<'a> PaneContent<'a> {
+    /// The last lines (plus the unterminated tail) that fit in `height` rows
+    /// of `width` columns, wrapped or not as the view says, under the echoed
+    /// command and a notice row when lines were dropped by the line cap. A
+    /// view scrolled up starts at its top line instead, if that is earlier.
+    fn visible_chunk(
+        &self,
+        height: usize,
+        width: usize,
+        highlighters: &[Highlighter],
+    ) -> Vec<Line<'a>> {
+        // rows in display order, each with the index of the line it shows
+        // (a stderr mark: the line it precedes)
+        let texts = self
+            .lines
+            .iter()
+            .map(|s| s.as_str())
+            .chain(Some(self.tail).filter(|extra| !extra.is_empty()));
+        let mut marks = self.marks.iter().peekable();
+        let mut rows: Vec<(usize, bool, Cow<'a, str>)> = Vec::new();
+        let mut end = 0;
+        // the line a fold is counting, and how many times it came so far
+        let mut repeated: Option<(&str, usize)> = None;
+        for (idx, text) in texts.enumerate() {
+            end = idx + 1;
+            if self.fold_repeats {
+                let marked = marks.peek().is_some_and(|(pos, _)| *pos <= idx);
+                match &mut repeated {
+                    Some((last, count)) if *last == text && !marked => {
+                        *count += 1;
+                        continue;
+                    }
+                    _ => {
+                        count_repeats(&mut rows, repeated.map_or(1, |(_, count)| count));
+                        repeated = Some((text, 1));
+                    }
+                }
+            }
+            while let Some((_, mark)) = marks.next_if(|(pos, _)| *pos <= idx) {
+                rows.push((idx, true, Cow::Owned(format!("⟪stderr: {mark}⟫"))));
+            }
+            let text = match self.columns {
+                Some(cut) => Cow::Owned(
+                    utility::show_control_chars(&cut.apply(text), self.control_chars).into_owned(),
+                ),
+                None => utility::show_control_chars(text, self.control_chars),
+            };
+            rows.push((idx, false, text));
+        }
+        count_repeats(&mut rows, repeated.map_or(1, |(_, count)| count));
+        for (_, mark) in marks {
+            rows.push((end, true, Cow::Owned(format!("⟪stderr: {mark}⟫"))));
+        }
+        let dim = Style::default().add_modifier(Modifier::DIM);
+        let mut header = Vec::new();
+        if let Some(cmd) = self.echo.filter(|_| !rows.is_empty()) {
+            header.push(Line::styled(format!("$ {cmd}"), dim));
+        }
+        if self.dropped > 0 {
+            header.push(Line::styled(
+                format!(
+                    "… {} earlier lines dropped …",
+                    utility::format_count(self.dropped)
+                ),
+                dim,
+            ));
+        }
+        let height = height.saturating_sub(header.len());
+        let shown: Vec<&str> = rows.iter().map(|(_, _, s)| s.as_ref()).collect();
+        let gutter_width = match self.timestamps {
+            Timestamps::Off => 0,
+            _ => GUTTER_WIDTH,
+        };
+        let width = if self.view.wrap {
+            width.saturating_sub(gutter_width).max(1)
+        } else {
+            usize::MAX
+        };
+        let mut start = utility::tail_start(&shown, height, width);
+        if let Some(anchor) = self.anchor.or(self.view.top) {
+            let anchor_row = rows.iter().position(|&(idx, _, _)| idx >= anchor);
+            start = start.min(anchor_row.unwrap_or(rows.len()));
+        }
+        self.view
+            .shown
+            .set(rows.get(start).map_or(self.lines.len(), |&(idx, _, _)| idx));
+        let mark_style = Style::default().fg(Color::Red).add_modifier(Modifier::DIM);
+        header
+            .into_iter()
+            .chain(rows.into_iter().skip(start).map(|(idx, mark, s)| {
+                if mark {
+                    return Line::styled(s, mark_style);
+                }
+                let mut line = highlight::highlight_cow(s, highlighters);
+                if gutter_width > 0 {
+                    let gutter = match (self.stamps.get(idx), self.timestamps) {
+                        (Some(stamp), Timestamps::Relative) => {
+                            format!("{:>8.3}s ", stamp.offset.as_secs_f64())
+                        }
+                        (Some(stamp), _) => format!("{:>9} ", utility::format_clock(stamp.at)),
+                        (None, _) => " ".repeat(gutter_width),
+                    };
+                    line.spans.insert(0, Span::styled(gutter, dim));
+                }
+                match &self.selected {
+                    Some(range) if range.contains(&idx) => {
+                        line.patch_style(Style::default().add_modifier(Modifier::REVERSED))
+                    }
+                    _ => line,
+                }
+            }))
+            .collect()
+    }
+}
+
+/// Note on the last row, a folded run of identical lines, how many there
+/// were.
+fn count_repeats(rows: &mut [(usize, bool, Cow<'_, str>)], count: usize) {
+    if let Some((_, false, text)) = rows.last_mut().filter(|_| count > 1) {
+        text.to_mut().push_str(&format!(" (×{count})"));
+    }
+}
+
+/// External program the output can be opened in, with the TUI suspended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Viewer {
+    /// `$PAGER`, else `less -R`; the output arrives on its stdin.
+    Pager,
+    /// `$VISUAL` or `$EDITOR`, else `vi`; on a temporary copy of the output.
+    Editor,
+}
+
+/// Whether `input` is meant for pipetui itself (`:watch ...`, `:cut ...`,
+/// `:pipe ...`, `:clear-history`) and must never reach the shell. A name
+/// still being typed (`:wat`) counts too, so it doesn't auto-run halfway.
+fn is_pseudo_command(input: &str) -> bool {
+    let input = input.trim_start();
+    [":watch", ":cut", ":pipe", ":clear-history"]
+        .iter()
+        .any(|name| {
+            pseudo_arg(input, name).is_some() || (input.starts_with(':') && name.starts_with(input))
+        })
+}
+
+/// What follows the pseudo-command `name` in `input`, if `input` is that
+/// command: `:pipefoo` is not `:pipe` with `foo`.
+fn pseudo_arg<'a>(input: &'a str, name: &str) -> Option<&'a str> {
+    input
+        .strip_prefix(name)
+        .filter(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace))
+}
+
+/// Output of a quiet auto-run, held back from the panes until it exits 0.
+#[derive(Debug, Default)]
+pub struct StagedOutput {
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// Visual-line selection over `output_lines`: the fixed `anchor` and the
+/// moving `cursor` bound the range (in either order).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LineSelection {
+    pub anchor: usize,
+    pub cursor: usize,
+}
+
+impl LineSelection {
+    pub fn range(self) -> std::ops::RangeInclusive<usize> {
+        self.anchor.min(self.cursor)..=self.anchor.max(self.cursor)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PinComparison {
+    Matches,
+    /// First differing line, 1-based.
+    DiffersAt(usize),
+}
+
+impl PinComparison {
+    pub fn between(pinned: &[String], current: &[String]) -> Self {
+        match pinned.iter().zip(current).position(|(a, b)| a != b) {
+            Some(idx) => PinComparison::DiffersAt(idx + 1),
+            None if pinned.len() != current.len() => {
+                PinComparison::DiffersAt(pinned.len().min(current.len()) + 1)
+            }
+            None => PinComparison::Matches,
+        }
+    }
+}
 
 pub struct App {
     pub input: String,
     pub cursor: usize,
     pub history: Vec<String>,
     pub hist_pos: Option<usize>,
+    /// What was typed when history navigation began: only entries starting
+    /// with it are visited, and it comes back past the newest match.
+    pub hist_prefix: String,
+    /// The last entry history navigation stopped at, kept across edits
+    /// for `history_resume`; cleared by running, clearing the input or
+    /// walking past the newest entry.
+    pub hist_anchor: Option<usize>,
     pub output_lines: Vec<String>,
     pub error_lines: Vec<String>,
     pub status_line: String,
     pub history_path: Option<PathBuf>,
+    /// The status line said history won't persist; said once per session.
+    history_warned: bool,
+    /// How many of the oldest `history` entries the history file has; the
+    /// rest were added here and not saved yet.
+    history_saved: usize,
     pub stdout_partial: String,
+    /// Stdout of the current run exactly as received, escape codes and
+    /// all, for copying raw output.
+    pub raw_stdout: String,
+    /// Lines evicted from the front of each pane by `max_output_lines`
+    /// during the current run.
+    pub stdout_dropped: usize,
+    pub stderr_dropped: usize,
+    /// Bytes of stdout and of stderr received from the current run,
+    /// including any dropped or held back since.
+    pub stdout_bytes: usize,
+    pub stderr_bytes: usize,
     pub stderr_partial: String,
-    pub is_running: bool,
+    /// The panes still hold the previous run's output while the current
+    /// run hasn't produced anything yet (`keep_output_until_new`).
+    pub stale_output: bool,
+    /// When each line of `output_lines` and `error_lines` arrived.
+    pub stdout_stamps: Vec<Stamp>,
+    pub stderr_stamps: Vec<Stamp>,
+    /// What the timestamp gutter shows, if anything.
+    pub timestamps: Timestamps,
+    /// Commands whose latest run couldn't be started, to tell "never ran"
+    /// from "ran and failed" in the history search; saved next to the
+    /// history file.
+    pub never_ran: HashSet<String>,
+    /// Stderr lines to annotate stdout with (`annotate_stderr`), each with
+    /// the index of the stdout line that followed it.
+    pub stderr_marks: Vec<(usize, String)>,
+    /// Whether the current run's stdout/stderr reached the panes as chunks.
+    pub stdout_streamed: bool,
+    pub stderr_streamed: bool,
+    /// Output of the quiet auto-run in flight (`quiet_auto_run`).
+    pub staged: Option<StagedOutput>,
+    pub run_state: RunState,
     pub last_run_cmd: Option<String>,
+    /// Command of the run in flight (or the last one), as started by the
+    /// worker, and the one whose output the panes currently hold.
+    pub started_cmd: Option<String>,
+    pub output_cmd: Option<String>,
+    /// Input edited while a run was in flight, dispatched when it finishes.
+    pub pending_cmd: Option<String>,
+    /// A run sent to the worker while another was in flight, which starts
+    /// as soon as that one finishes: whether it was started by hand. A
+    /// newer one replaces it, as the worker keeps only the newest.
+    queued_manual: Option<bool>,
+    /// The last command that failed and how many times in a row it did.
+    pub failure_streak: Option<(String, u32)>,
+    pub dry_run_armed: bool,
+    pub overlay: Option<Overlay>,
+    pub run_log: VecDeque<RunRecord>,
+    pub zoom: Option<Pane>,
+    pub exec_mode: ExecMode,
+    /// Horizontal scroll of the input box, updated while rendering.
+    pub input_scroll: Cell<u16>,
+    pub config: Config,
+    /// The `.pipetuirc` applied on top of `config`, if any.
+    pub rc_file: Option<PathBuf>,
+    pub highlighters: Vec<Highlighter>,
+    pub progress_regex: Option<Regex>,
+    pub pinned: Option<PinnedOutput>,
+    /// When the input last changed, and whether that change still has to
+    /// be saved as a draft.
     pub last_edit_at: Option<Instant>,
-    pub append_history_on_finish: bool,
+    pub draft_dirty: bool,
+    pub focus: Focus,
+    /// Viewer asked for by a key, opened by the main loop.
+    pub view_request: Option<Viewer>,
+    /// Set by `reset`: the main loop clears the terminal and redraws.
+    pub clear_screen_request: bool,
+    /// Interval of watch mode, re-running the input on a timer, and when
+    /// the next watch run is due.
+    pub watch: Option<Duration>,
+    pub watch_next_at: Option<Instant>,
+    /// Columns the stdout pane shows of each line (`:cut`).
+    pub column_cut: Option<ColumnCut>,
+    /// Show tabular stdout aligned in a table rather than as text.
+    pub table_view: bool,
+    /// Fold runs of identical output lines into one with a count.
+    pub fold_repeats: bool,
+    /// Append each run's output below the previous run's instead of
+    /// replacing it.
+    pub append_output: bool,
+    /// Run each command in a fresh temporary directory (`scratch_runs`).
+    pub scratch_runs: bool,
+    /// Where the running scratch run works.
+    pub scratch_dir: Option<PathBuf>,
+    /// Enter inserts a newline instead of running the input.
+    pub multiline: bool,
+    /// Vertical scroll of the input box, updated while rendering.
+    pub input_vscroll: Cell<u16>,
+    /// Edit the input per `char` regardless of its size.
+    pub literal_edit: bool,
+    pub edit_mode: EditMode,
+    /// First key of a two-key normal-mode command (`d` of `dd`).
+    pub vi_pending: Option<char>,
+    /// Last `Alt+.` insertion: how many history entries back it came
+    /// from, where it starts in the input, and the word inserted.
+    last_arg: Option<(usize, usize, String)>,
+    pub stdout_pane: PaneView,
+    pub stderr_pane: PaneView,
+    /// Stdout lines being selected for copying; keys drive it while set.
+    pub selection: Option<LineSelection>,
+    /// When the current run started, and whether it has printed anything.
+    pub run_started_at: Option<Instant>,
+    pub output_seen: bool,
+    /// PID of the running child, shown in the status line.
+    pub child_pid: Option<u32>,
+    /// Stdout of an earlier run fed to every run's stdin while armed.
+    pub captured_stdin: Option<String>,
+    /// Output shown before `:pipe`, while the panes show what came out.
+    pub pipe_source: Option<PipeSource>,
+    /// A `:pipe` run for the main loop to send to the worker.
+    pub pipe_request: Option<RunSpec>,
+    /// The run in flight is a `:pipe` transform, not the user's command.
+    pipe_running: bool,
+    pub notes: Notes,
+    /// Set when a run fails with `FailureAlert::Flash`; the next frame is
+    /// drawn inverted and clears it.
+    pub flash: Cell<bool>,
 }
 
 impl App {
     pub fn new() -> Self {
-        let history_path = persistence::history_file().ok();
+        // without a usable data dir, history lives in memory only
+        let (history_path, history_error) = match persistence::history_file() {
+            Ok(path) => (Some(path), None),
+            Err(e) => (None, Some(e)),
+        };
         let history = history_path
             .as_ref()
             .and_then(|path| persistence::load_history(path).ok())
             .unwrap_or_default();
+        let never_ran = history_path
+            .as_deref()
+            .map(persistence::load_never_ran)
+            .unwrap_or_default();
+        // everything startup has to report, shown together
+        let mut notices = Vec::new();
+        let mut config =
+            match persistence::config_file().and_then(|path| persistence::load_config(&path)) {
+                Ok(config) => config,
+                Err(e) => {
+                    notices.push(format!("config ignored: {e}"));
+                    Config::default()
+                }
+            };
+        let mut rc_file = None;
+        let rc_path = std::env::current_dir()
+            .ok()
+            .and_then(|dir| persistence::find_project_rc(&dir));
+        if let Some(path) = rc_path {
+            match persistence::load_project_rc(&path) {
+                Ok(rc) => {
+                    let rc_dir = path.parent().unwrap_or(&path).to_path_buf();
+                    let ignored = rc.apply(&mut config, &rc_dir);
+                    let mut loaded = format!("loaded {}", path.display());
+                    if !ignored.is_empty() {
+                        loaded.push_str(&format!(
+                            " (env not in rc_env_allow ignored: {})",
+                            ignored.join(", ")
+                        ));
+                    }
+                    notices.push(loaded);
+                    rc_file = Some(path);
+                }
+                Err(e) => notices.push(format!("{} ignored: {e}", persistence::PROJECT_RC)),
+            }
+        }
+        let (highlighters, highlight_error) = highlight::compile_rules(&config.highlights);
+        notices.extend(highlight_error);
+        let progress_regex = match config.progress_pattern.as_deref().map(Regex::new) {
+            Some(Ok(regex)) => Some(regex),
+            Some(Err(e)) => {
+                notices.push(format!("progress_pattern ignored: {e}"));
+                None
+            }
+            None => None,
+        };
+        if let Some(e) = &history_error {
+            tracing::warn!(error = %e, "history will not be saved");
+            notices.push(format!("history not saved: {}", e.reason()));
+        }
+        let status_line = if notices.is_empty() {
+            String::from("Ready")
+        } else {
+            notices.join("; ")
+        };
+        // prefilled only; `Idle` keeps it from auto-running
+        let input = config.startup_command.clone().unwrap_or_default();
 
         Self {
-            input: String::new(),
-            cursor: 0,
+            cursor: input.len(),
+            input,
+            history_saved: history.len(),
             history,
             hist_pos: None,
-            output_lines: vec!["(output will appear here)".into()],
+            hist_prefix: String::new(),
+            hist_anchor: None,
+            output_lines: Vec::new(),
             error_lines: Vec::new(),
-            status_line: "Ready".into(),
+            status_line,
             history_path,
+            history_warned: history_error.is_some(),
             stdout_partial: String::new(),
+            raw_stdout: String::new(),
+            stdout_dropped: 0,
+            stdout_bytes: 0,
+            stderr_bytes: 0,
+            stderr_dropped: 0,
             stderr_partial: String::new(),
-            is_running: false,
+            stale_output: false,
+            stdout_stamps: Vec::new(),
+            stderr_stamps: Vec::new(),
+            timestamps: config.timestamps,
+            never_ran,
+            stderr_marks: Vec::new(),
+            stdout_streamed: false,
+            stderr_streamed: false,
+            staged: None,
+            run_state: RunState::Idle,
             last_run_cmd: None,
+            started_cmd: None,
+            output_cmd: None,
+            pending_cmd: None,
+            queued_manual: None,
+            failure_streak: None,
+            dry_run_armed: false,
+            overlay: None,
+            run_log: VecDeque::new(),
+            zoom: None,
+            exec_mode: config.exec_mode.unwrap_or(ExecMode::Shell),
+            input_scroll: Cell::new(0),
+            stdout_pane: PaneView::new(config.wrap_stdout),
+            stderr_pane: PaneView::new(config.wrap_stderr),
+            scratch_runs: config.scratch_runs,
+            config,
+            rc_file,
+            highlighters,
+            progress_regex,
+            pinned: None,
             last_edit_at: None,
-            append_history_on_finish: false,
+            draft_dirty: false,
+            focus: Focus::Input,
+            view_request: None,
+            clear_screen_request: false,
+            watch: None,
+            column_cut: None,
+            table_view: false,
+            fold_repeats: false,
+            watch_next_at: None,
+            append_output: false,
+            scratch_dir: None,
+            multiline: false,
+            input_vscroll: Cell::new(0),
+            literal_edit: false,
+            edit_mode: EditMode::Insert,
+            vi_pending: None,
+            last_arg: None,
+            selection: None,
+            run_started_at: None,
+            output_seen: false,
+            child_pid: None,
+            captured_stdin: None,
+            pipe_source: None,
+            pipe_request: None,
+            pipe_running: false,
+            notes: Notes::load(),
+            flash: Cell::new(false),
         }
     }
 
-    pub fn begin_run(&mut self, _cmd: String) {
+    pub fn begin_run(&mut self, cmd: String) {
+        self.started_cmd = Some(cmd);
         self.status_line = "running...".into();
+        self.run_started_at = Some(Instant::now());
+        self.output_seen = false;
+        self.stdout_bytes = 0;
+        self.stderr_bytes = 0;
+        self.scratch_dir = None;
+        if !self.pipe_running {
+            // fresh output of the command itself replaces what was piped
+            self.pipe_source = None;
+        }
+        if !self.is_running() {
+            // not dispatched through `prepare_run`, so treat it as an auto-run
+            self.run_state = RunState::Running { manual: false };
+        }
+        if self.config.quiet_auto_run && self.run_state == (RunState::Running { manual: false }) {
+            // the panes keep what they show until this run succeeds
+            self.staged = Some(StagedOutput::default());
+            self.status_line = "running (quiet)...".into();
+        } else {
+            self.start_output();
+        }
+    }
+
+    /// Make room in the panes for the output of the run just started.
+    /// Both panes go together: stderr kept on screen (stale) is dropped
+    /// with stdout by the first chunk of either or by the finish, so a run
+    /// without stderr never shows the previous run's.
+    fn start_output(&mut self) {
+        self.stdout_streamed = false;
+        self.stderr_streamed = false;
+        if self.append_output {
+            // a transcript: the new output goes below a header for this run
+            let header = format!(
+                "── $ {}  {} ──",
+                self.started_cmd.as_deref().unwrap_or_default(),
+                utility::format_clock(SystemTime::now())
+            );
+            if !self.error_lines.is_empty() {
+                self.error_lines.push(header.clone());
+            }
+            self.output_lines.push(header);
+            self.output_cmd = self.started_cmd.clone();
+            self.stamp_lines();
+            self.enforce_line_cap();
+        } else if self.config.keep_output_until_new || self.watch.is_some() {
+            // swapped out by the first chunk (or the finish) of this run
+            self.stale_output = true;
+        } else {
+            self.clear_output();
+        }
+    }
+
+    fn clear_output(&mut self) {
         self.output_lines.clear();
         self.error_lines.clear();
+        self.stdout_stamps.clear();
+        self.stderr_stamps.clear();
         self.stdout_partial.clear();
         self.stderr_partial.clear();
-        self.is_running = true;
+        self.raw_stdout.clear();
+        self.output_cmd = self.started_cmd.clone();
+        self.stdout_dropped = 0;
+        self.stderr_dropped = 0;
+        self.stderr_marks.clear();
+        self.selection = None;
+        for view in [&mut self.stdout_pane, &mut self.stderr_pane] {
+            view.top = None;
+            view.left = 0;
+        }
+        self.stale_output = false;
+    }
+
+    /// Shown instead of the status while a run has been silent since it
+    /// started, usually because it reads stdin (`grep foo` with no file).
+    pub fn silent_run_hint(&self) -> Option<&'static str> {
+        let started = self.run_started_at?;
+        let silent = self.is_running()
+            && !self.output_seen
+            && self.captured_stdin.is_none()
+            && !self.pipe_running
+            && started.elapsed() >= STDIN_HINT_AFTER;
+        silent.then_some("no output — command may be waiting for stdin (Ctrl+K to cancel)")
+    }
+
+    /// Prepare to cancel the running command; true if there is one, in
+    /// which case the caller sends `WorkerMsg::Cancel`. Queued input is
+    /// dropped too, so nothing starts in its place.
+    pub fn request_cancel(&mut self) -> bool {
+        if !self.is_running() {
+            return false;
+        }
+        self.pending_cmd = None;
+        self.queued_manual = None;
+        self.status_line = "cancelling...".into();
+        true
+    }
+
+    pub fn is_running(&self) -> bool {
+        matches!(self.run_state, RunState::Running { .. })
     }
 
     pub fn append_stdout_chunk(&mut self, chunk: String) {
-        Self::append_chunk(chunk, &mut self.stdout_partial, &mut self.output_lines);
+        self.stdout_bytes += chunk.len();
+        if let Some(staged) = &mut self.staged {
+            staged.stdout.push_str(&chunk);
+            self.output_seen = true;
+            return;
+        }
+        if self.stale_output {
+            self.clear_output();
+        }
+        self.raw_stdout.push_str(&chunk);
+        self.output_seen = true;
+        self.stdout_streamed = true;
+        Self::append_chunk(
+            chunk,
+            &mut self.stdout_partial,
+            &mut self.output_lines,
+            &self.config,
+        );
+        self.stamp_lines();
+        self.enforce_line_cap();
     }
 
     pub fn append_stderr_chunk(&mut self, chunk: String) {
-        Self::append_chunk(chunk, &mut self.stderr_partial, &mut self.error_lines);
+        self.stderr_bytes += chunk.len();
+        if let Some(staged) = &mut self.staged {
+            staged.stderr.push_str(&chunk);
+            self.output_seen = true;
+            return;
+        }
+        if self.stale_output {
+            self.clear_output();
+        }
+        self.output_seen = true;
+        self.stderr_streamed = true;
+        let before = self.error_lines.len();
+        Self::append_chunk(
+            chunk,
+            &mut self.stderr_partial,
+            &mut self.error_lines,
+            &self.config,
+        );
+        self.mark_stderr_since(before);
+        self.stamp_lines();
+        self.enforce_line_cap();
+    }
+
+    /// Record stderr lines from index `from` on as marks at the current
+    /// end of stdout, when `annotate_stderr` is on.
+    fn mark_stderr_since(&mut self, from: usize) {
+        if !self.config.annotate_stderr {
+            return;
+        }
+        let pos = self.output_lines.len();
+        for line in &self.error_lines[from..] {
+            self.stderr_marks.push((pos, line.clone()));
+        }
+    }
+
+    /// Stamp the lines added since the last call with the current time,
+    /// and forget the stamps of lines since removed from the end.
+    fn stamp_lines(&mut self) {
+        let stamp = Stamp {
+            at: SystemTime::now(),
+            offset: self
+                .run_started_at
+                .map_or(Duration::ZERO, |at| at.elapsed()),
+        };
+        self.stdout_stamps.resize(self.output_lines.len(), stamp);
+        self.stderr_stamps.resize(self.error_lines.len(), stamp);
+    }
+
+    /// Evict the oldest lines beyond `max_output_lines`, counting them so
+    /// the panes can say output was dropped.
+    fn enforce_line_cap(&mut self) {
+        let cap = self.config.max_output_lines;
+        if cap == 0 {
+            return;
+        }
+        if self.output_lines.len() > cap {
+            let excess = self.output_lines.len() - cap;
+            self.output_lines.drain(..excess);
+            self.stdout_stamps
+                .drain(..excess.min(self.stdout_stamps.len()));
+            self.stdout_dropped += excess;
+            // keep selecting the same lines while they still exist
+            if let Some(selection) = &mut self.selection {
+                selection.anchor = selection.anchor.saturating_sub(excess);
+                selection.cursor = selection.cursor.saturating_sub(excess);
+            }
+            self.stderr_marks.retain(|&(pos, _)| pos >= excess);
+            for (pos, _) in &mut self.stderr_marks {
+                *pos -= excess;
+            }
+        }
+        if self.error_lines.len() > cap {
+            let excess = self.error_lines.len() - cap;
+            self.error_lines.drain(..excess);
+            self.stderr_stamps
+                .drain(..excess.min(self.stderr_stamps.len()));
+            self.stderr_dropped += excess;
+        }
+        if self.stderr_marks.len() > cap {
+            let excess = self.stderr_marks.len() - cap;
+            self.stderr_marks.drain(..excess);
+        }
     }
 
-    fn append_chunk(chunk: String, partial: &mut String, lines: &mut Vec<String>) {
+    fn append_chunk(chunk: String, partial: &mut String, lines: &mut Vec<String>, config: &Config) {
         partial.push_str(&chunk);
         while let Some(pos) = partial.find('\n') {
-            let mut line = partial[..pos].to_string();
-            if line.ends_with('\r') {
-                line.pop();
-            }
-            lines.push(line);
+            lines.push(Self::finish_line(&partial[..pos], config));
             partial.drain(..=pos);
         }
     }
 
+    /// A complete output line as stored: without the `\r` of a CRLF ending,
+    /// tabs expanded to `tab_width` stops, and with trailing whitespace
+    /// removed if `trim_trailing_whitespace` is set. Leading whitespace is
+    /// always kept.
+    fn finish_line(raw: &str, config: &Config) -> String {
+        let line = raw.strip_suffix('\r').unwrap_or(raw);
+        let line = utility::expand_tabs(line, config.tab_width);
+        if config.trim_trailing_whitespace {
+            line.trim_end().to_string()
+        } else {
+            line
+        }
+    }
+
+    /// Drop blank lines at the end only; blank lines between output stay.
+    fn strip_trailing_blank_lines(lines: &mut Vec<String>) {
+        while lines.last().is_some_and(|line| line.trim().is_empty()) {
+            lines.pop();
+        }
+    }
+
     fn flush_partials(&mut self) {
         if !self.stdout_partial.is_empty() {
-            let line = self.stdout_partial.trim_end_matches('\r').to_string();
+            let line = Self::finish_line(&self.stdout_partial, &self.config);
             self.output_lines.push(line);
             self.stdout_partial.clear();
         }
         if !self.stderr_partial.is_empty() {
-            let line = self.stderr_partial.trim_end_matches('\r').to_string();
+            let line = Self::finish_line(&self.stderr_partial, &self.config);
             self.error_lines.push(line);
             self.stderr_partial.clear();
+            self.mark_stderr_since(self.error_lines.len() - 1);
         }
     }
 
     pub fn finish_run(&mut self, res: ExecResult) {
-        self.flush_partials();
-        if self.output_lines.is_empty() {
-            if res.stdout.is_empty() {
-                self.output_lines.push("<no stdout>".into());
-            } else {
-                self.output_lines = res.stdout.lines().map(|s| s.to_string()).collect();
+        self.child_pid = None;
+        self.scratch_dir = None;
+        // a quiet auto-run only reaches the panes if it succeeded
+        let shown = match self.staged.take() {
+            Some(staged) if res.status.success() && !res.cancelled => {
+                self.start_output();
+                if !staged.stdout.is_empty() {
+                    self.append_stdout_chunk(staged.stdout);
+                }
+                if !staged.stderr.is_empty() {
+                    self.append_stderr_chunk(staged.stderr);
+                }
+                true
+            }
+            Some(_) => false,
+            None => true,
+        };
+        if shown {
+            if self.stale_output {
+                self.clear_output();
+            }
+            // every chunk the worker logged was streamed before `Finished`,
+            // so `res` only fills in a stream that delivered nothing, such
+            // as the message of a spawn error
+            if !self.stdout_streamed && !res.stdout.is_empty() {
+                self.raw_stdout.push_str(&res.stdout);
+                Self::append_chunk(
+                    res.stdout.clone(),
+                    &mut self.stdout_partial,
+                    &mut self.output_lines,
+                    &self.config,
+                );
+            }
+            if !self.stderr_streamed && !res.stderr.is_empty() {
+                Self::append_chunk(
+                    res.stderr.clone(),
+                    &mut self.stderr_partial,
+                    &mut self.error_lines,
+                    &self.config,
+                );
+            }
+            self.flush_partials();
+            self.stamp_lines();
+            self.enforce_line_cap();
+            if self.config.strip_trailing_blank_lines {
+                Self::strip_trailing_blank_lines(&mut self.output_lines);
+                Self::strip_trailing_blank_lines(&mut self.error_lines);
+            }
+        }
+        let mut status = if res.cancelled {
+            format!("cancelled after {}", utility::format_duration(res.duration))
+        } else {
+            format!("{}  {}", res.status, utility::format_duration(res.duration))
+        };
+        if let Some(usage) = res.usage {
+            status.push_str(&format!(
+                "  cpu {}  peak {}",
+                utility::format_duration(usage.cpu_time),
+                utility::format_bytes(usage.peak_rss_bytes as usize)
+            ));
+        }
+        if !res.cancelled {
+            self.failure_streak = match self.failure_streak.take() {
+                _ if res.status.success() => None,
+                Some((cmd, count)) if cmd == res.cmd => Some((cmd, count.saturating_add(1))),
+                _ => Some((res.cmd.clone(), 1)),
+            };
+        }
+        if !shown && !res.cancelled {
+            status = format!("last auto-run failed: {status}");
+        }
+        if self.auto_run_paused(&res.cmd) {
+            status.push_str("  auto-run paused after repeated failures (Enter to force)");
+        }
+        self.status_line = status;
+        self.run_started_at = None;
+        if shown && !res.status.success() && !res.cancelled {
+            match self.config.failure_alert {
+                FailureAlert::Off => {}
+                FailureAlert::Bell => utility::ring_bell(),
+                FailureAlert::Flash => self.flash.set(true),
+            }
+        }
+        if let Some(pin) = self.pinned.as_mut().filter(|_| shown) {
+            pin.comparison = Some(PinComparison::between(&pin.lines, &self.output_lines));
+        }
+        if let Some(hook) = self.config.on_finish.as_deref().filter(|_| !res.cancelled) {
+            if let Err(e) = execution::spawn_hook(hook, &res, &self.run_spec(&res.cmd)) {
+                self.status_line
+                    .push_str(&format!("  on_finish hook failed: {e}"));
+            }
+        }
+        self.record_run(&res);
+        let manual = matches!(self.run_state, RunState::Running { manual: true });
+        // a queued edit that ended up identical to what just ran is moot
+        if self.pending_cmd.as_deref() == Some(res.cmd.as_str()) {
+            self.pending_cmd = None;
+        }
+        self.run_state = if let Some(manual) = self.queued_manual.take() {
+            // the worker starts the queued run right away
+            RunState::Running { manual }
+        } else if self.pending_cmd.is_some() {
+            RunState::Pending {
+                edited_at: Instant::now(),
             }
+        } else {
+            RunState::Finished
+        };
+        // a transform on its own would wait for the terminal's stdin
+        let piped = std::mem::take(&mut self.pipe_running);
+        let keep = !res.spawn_failed || self.config.history_spawn_failures;
+        if manual && !piped && keep && !res.cmd.trim().is_empty() {
+            self.append_history(res.cmd.clone());
+        }
+        if res.spawn_failed || (!res.cancelled && self.never_ran.contains(&res.cmd)) {
+            self.mark_never_ran(&res.cmd, res.spawn_failed);
+        }
+    }
+
+    /// Record whether the latest run of `cmd` never started, in memory and
+    /// next to the history file so the mark outlives the session.
+    fn mark_never_ran(&mut self, cmd: &str, never_ran: bool) {
+        if never_ran {
+            self.never_ran.insert(cmd.to_string());
+        } else {
+            self.never_ran.remove(cmd);
+        }
+        let Some(path) = &self.history_path else {
+            return;
+        };
+        match persistence::mark_never_ran(path, cmd, never_ran) {
+            Ok(marks) => self.never_ran = marks,
+            Err(e) => tracing::warn!(error = %e, "cannot save never-ran marks"),
+        }
+    }
+
+    /// Percentage (0 to 100) in the latest line of a running command's
+    /// output, stdout before stderr, per `progress_pattern`. The last match
+    /// wins, since carriage-return progress bars pile up on one line.
+    pub fn progress(&self) -> Option<f64> {
+        let regex = self.progress_regex.as_ref()?;
+        if !self.is_running() || self.stale_output {
+            return None;
         }
-        if self.error_lines.is_empty() && !res.stderr.is_empty() {
-            self.error_lines = res.stderr.lines().map(|s| s.to_string()).collect();
+        let latest = |partial: &str, lines: &[String]| -> Option<f64> {
+            let line = if partial.is_empty() {
+                lines.last()?.as_str()
+            } else {
+                partial
+            };
+            let captures = regex.captures_iter(line).last()?;
+            let percent: f64 = captures.get(1)?.as_str().parse().ok()?;
+            Some(percent.clamp(0.0, 100.0))
+        };
+        latest(&self.stdout_partial, &self.output_lines)
+            .or_else(|| latest(&self.stderr_partial, &self.error_lines))
+    }
+
+    /// Text shown in an empty stdout pane.
+    pub fn stdout_placeholder(&self) -> &str {
+        let labels = &self.config.labels;
+        match self.run_state {
+            RunState::Running { .. } => &labels.waiting,
+            _ if self.last_run_cmd.is_some() => &labels.no_stdout,
+            _ => &labels.idle,
         }
-        self.status_line = format!("exit {}", res.status);
-        self.is_running = false;
-        if self.append_history_on_finish && !res.cmd.trim().is_empty() {
-            self.append_history(res.cmd);
+    }
+
+    /// Text shown in an empty stderr pane.
+    pub fn stderr_placeholder(&self) -> &str {
+        match self.run_state {
+            RunState::Running { .. } => "",
+            _ => &self.config.labels.no_stderr,
         }
-        self.append_history_on_finish = false;
     }
 
     pub fn stdout_view<'a>(&'a self, area: Rect) -> Vec<Line<'a>> {
-        Self::visible_chunk(
-            &self.output_lines,
-            (!self.stdout_partial.is_empty()).then_some(self.stdout_partial.as_str()),
-            area,
+        let content = PaneContent {
+            lines: &self.output_lines,
+            tail: &self.stdout_partial,
+            echo: self
+                .output_cmd
+                .as_deref()
+                .filter(|_| self.config.echo_command),
+            dropped: self.stdout_dropped,
+            anchor: self.selection.map(|selection| selection.cursor),
+            view: &self.stdout_pane,
+            selected: self.selection.map(LineSelection::range),
+            marks: &self.stderr_marks,
+            control_chars: self.config.control_chars,
+            columns: self.column_cut.as_ref(),
+            stamps: &self.stdout_stamps,
+            timestamps: self.timestamps,
+            fold_repeats: self.fold_repeats,
+        };
+        content.visible_chunk(
+            area.height.saturating_sub(2) as usize, // minus borders
+            area.width.saturating_sub(2) as usize,
+            &self.highlighters,
         )
     }
 
+    /// The stdout as a table for the table view, cut to the rows that fit
+    /// in `area` (the last ones, or from the view's top when scrolled up);
+    /// `None` shows it as text, as when it doesn't look tabular.
+    pub fn stdout_table(&self, area: Rect) -> Option<Tabular> {
+        if !self.table_view || self.output_lines.len() > table::TABLE_SCAN_LIMIT {
+            return None;
+        }
+        let lines: Vec<String> = self
+            .output_lines
+            .iter()
+            .map(|line| match &self.column_cut {
+                Some(cut) => utility::strip_ansi(&cut.apply(line)),
+                None => utility::strip_ansi(line),
+            })
+            .collect();
+        let mut tabular = table::detect(&lines)?;
+        let height = (area.height.saturating_sub(2) as usize) // minus borders
+            .saturating_sub(usize::from(tabular.header.is_some()));
+        let mut start = tabular.rows.len().saturating_sub(height);
+        if let Some(top) = self.stdout_pane.top {
+            start = start.min(
+                tabular
+                    .rows
+                    .iter()
+                    .take_while(|(idx, _)| *idx < top)
+                    .count(),
+            );
+        }
+        self.stdout_pane.shown.set(
+            tabular
+                .rows
+                .get(start)
+                .map_or(self.output_lines.len(), |(idx, _)| *idx),
+        );
+        tabular.rows.drain(..start);
+        tabular.rows.truncate(height);
+        Some(tabular)
+    }
+
     pub fn stderr_view<'a>(&'a self, area: Rect) -> Vec<Line<'a>> {
-        Self::visible_chunk(
-            &self.error_lines,
-            (!self.stderr_partial.is_empty()).then_some(self.stderr_partial.as_str()),
-            area,
+        self.stderr_content(self.stderr_dropped).visible_chunk(
+            area.height.saturating_sub(2) as usize,
+            area.width.saturating_sub(2) as usize,
+            &self.highlighters,
         )
     }
 
-    fn visible_chunk<'a>(
-        lines: &'a [String],
-        tail: Option<&'a str>,
-        area: Rect,
-    ) -> Vec<Line<'a>> {
-        let height = area.height.saturating_sub(2) as usize; // minus borders
-        let mut display: Vec<&'a str> = lines.iter().map(|s| s.as_str()).collect();
-        if let Some(extra) = tail {
-            if !extra.is_empty() {
-                display.push(extra);
-            }
+    /// The row of stderr the pane would show last, `width` columns wide,
+    /// for the compact layout's one-line stderr.
+    pub fn stderr_row(&self, width: u16) -> Option<Line<'_>> {
+        self.stderr_content(0)
+            .visible_chunk(1, width as usize, &self.highlighters)
+            .pop()
+    }
+
+    fn stderr_content(&self, dropped: usize) -> PaneContent<'_> {
+        PaneContent {
+            lines: &self.error_lines,
+            tail: &self.stderr_partial,
+            echo: None,
+            dropped,
+            anchor: None,
+            view: &self.stderr_pane,
+            selected: None,
+            marks: &[],
+            control_chars: self.config.control_chars,
+            columns: None,
+            stamps: &self.stderr_stamps,
+            timestamps: self.timestamps,
+            fold_repeats: self.fold_repeats,
+        }
+    }
+
+    /// Move keyboard focus to the next part of the session. Returns false
+    /// when it wraps back to the input, so the workspace can move on to
+    /// the next session instead.
+    pub fn focus_next(&mut self) -> bool {
+        self.focus = match self.focus {
+            Focus::Input => Focus::Stdout,
+            Focus::Stdout => Focus::Stderr,
+            Focus::Stderr if self.notes.visible => Focus::Notes,
+            Focus::Stderr | Focus::Notes => Focus::Input,
+        };
+        self.focus != Focus::Input
+    }
+
+    /// The output pane holding focus, if any.
+    pub fn focused_pane(&self) -> Option<Pane> {
+        match self.focus {
+            Focus::Input | Focus::Notes => None,
+            Focus::Stdout => Some(Pane::Stdout),
+            Focus::Stderr => Some(Pane::Stderr),
+        }
+    }
+
+    fn pane_view_mut(&mut self, pane: Pane) -> (&mut PaneView, &[String]) {
+        match pane {
+            Pane::Stdout => (&mut self.stdout_pane, &self.output_lines),
+            Pane::Stderr => (&mut self.stderr_pane, &self.error_lines),
         }
-        if display.is_empty() {
-            return Vec::new();
+    }
+
+    /// Scroll `pane` by `delta` lines from what was last shown; scrolling
+    /// past the last line goes back to following the end.
+    pub fn scroll(&mut self, pane: Pane, delta: isize) {
+        let (view, lines) = self.pane_view_mut(pane);
+        let top = view.shown.get().saturating_add_signed(delta);
+        view.top = (top + 1 < lines.len()).then_some(top);
+    }
+
+    /// Scroll unwrapped `pane` sideways by `delta` columns, stopping where
+    /// the longest line's last column comes into view.
+    pub fn scroll_sideways(&mut self, pane: Pane, delta: isize) {
+        let (view, lines) = self.pane_view_mut(pane);
+        if view.wrap {
+            return;
         }
-        let total = display.len();
-        let start = total.saturating_sub(height);
-        display[start..]
+        let widest = lines
             .iter()
-            .map(|s| Line::from(*s))
-            .collect()
+            .map(|line| UnicodeWidthStr::width(line.as_str()))
+            .max()
+            .unwrap_or(0);
+        view.left = view
+            .left
+            .saturating_add_signed(delta)
+            .min(widest.saturating_sub(1));
+    }
+
+    /// Switch `pane` between wrapping long lines and cutting them off.
+    pub fn toggle_wrap(&mut self, pane: Pane) {
+        let (view, _) = self.pane_view_mut(pane);
+        view.wrap = !view.wrap;
+        view.left = 0;
+        let name = match pane {
+            Pane::Stdout => "stdout",
+            Pane::Stderr => "stderr",
+        };
+        self.status_line = format!(
+            "{name} {}",
+            if view.wrap {
+                "wraps long lines"
+            } else {
+                "cuts long lines (←/→ scroll)"
+            }
+        );
+    }
+
+    /// Show `pane` from its first line (for stderr, usually the first
+    /// error) instead of following its end.
+    pub fn scroll_to_top(&mut self, pane: Pane) {
+        self.pane_view_mut(pane).0.top = Some(0);
+    }
+
+    /// Go back to following the end of `pane`.
+    pub fn scroll_to_end(&mut self, pane: Pane) {
+        self.pane_view_mut(pane).0.top = None;
     }
 
     fn append_history(&mut self, entry: String) {
@@ -160,128 +1329,956 @@ impl App {
         if self.history.len() > HISTORY_LIMIT {
             let remove_count = self.history.len() - HISTORY_LIMIT;
             self.history.drain(0..remove_count);
+            self.history_saved = self.history_saved.saturating_sub(remove_count);
+        }
+        if let Err(e) = self.sync_history() {
+            tracing::warn!(error = %e, "cannot save history");
+            if !std::mem::replace(&mut self.history_warned, true) {
+                self.status_line
+                    .push_str(&format!("  history not saved: {}", e.reason()));
+            }
         }
-        persistence::save_history(self);
         self.hist_pos = None;
+        self.hist_anchor = None;
     }
 
-    pub fn history_prev(&mut self) {
-        if self.history.is_empty() {
+    /// Save the entries added since the last save, merged into the history
+    /// file as other instances left it, and take on the merged history.
+    /// History positions keep pointing at the same commands.
+    fn sync_history(&mut self) -> Result<()> {
+        let Some(path) = &self.history_path else {
+            return Ok(());
+        };
+        let merged = persistence::save_history(path, &self.history[self.history_saved..])?;
+        let remap = |idx: Option<usize>| {
+            let entry = self.history.get(idx?)?;
+            merged.iter().rposition(|other| other == entry)
+        };
+        (self.hist_pos, self.hist_anchor) = (remap(self.hist_pos), remap(self.hist_anchor));
+        self.history_saved = merged.len();
+        self.history = merged;
+        self.never_ran = persistence::load_never_ran(path);
+        Ok(())
+    }
+
+    /// Quietly take on what the other pane saved to the history file
+    /// meanwhile, as focus moves here, so both browse the same history.
+    pub fn refresh_history(&mut self) {
+        if let Err(e) = self.sync_history() {
+            tracing::warn!(error = %e, "cannot refresh history");
+        }
+    }
+
+    /// Pull in entries another pipetui saved to the history file since this
+    /// one loaded it; what is being typed or browsed stays as it is.
+    pub fn reload_history(&mut self) {
+        if self.history_path.is_none() {
+            self.status_line = "no history file".into();
+            return;
+        }
+        let known: HashSet<String> = self.history.iter().cloned().collect();
+        if let Err(e) = self.sync_history() {
+            self.status_line = format!("cannot reload history: {e}");
             return;
         }
-        let next_idx = match self.hist_pos {
-            None => self.history.len().saturating_sub(1),
-            Some(0) => 0,
-            Some(idx) => idx - 1,
+        let added = self
+            .history
+            .iter()
+            .filter(|entry| !known.contains(*entry))
+            .count();
+        self.status_line = match added {
+            0 => "history is up to date".into(),
+            1 => "reloaded history: 1 new entry".into(),
+            n => format!("reloaded history: {n} new entries"),
         };
-        self.hist_pos = Some(next_idx);
-        self.input = self.history[next_idx].clone();
-        self.cursor = self.input.len();
-        self.last_run_cmd = None;
-        self.mark_edited();
+    }
+
+    /// Recall the previous history entry starting with the prefix. A fresh
+    /// navigation takes what is typed now as the prefix. With
+    /// `history_resume` and an edited entry, it resumes the search that
+    /// recalled that entry instead: same prefix, from that entry back, so
+    /// editing `git log` found by `git` and pressing `↑` goes on through
+    /// the older `git` entries.
+    pub fn history_prev(&mut self) {
+        let end = match self.hist_pos {
+            Some(idx) => idx,
+            None => match self.hist_anchor.filter(|_| self.config.history_resume) {
+                Some(idx) => (idx + 1).min(self.history.len()),
+                None => {
+                    self.hist_prefix = self.input.clone();
+                    self.history.len()
+                }
+            },
+        };
+        let prefix = &self.hist_prefix;
+        if let Some(idx) = self.history[..end]
+            .iter()
+            .rposition(|entry| entry.starts_with(prefix.as_str()))
+        {
+            self.hist_pos = Some(idx);
+            self.hist_anchor = Some(idx);
+            self.set_history_input(self.history[idx].clone());
+        }
     }
 
     pub fn history_next(&mut self) {
-        if self.history.is_empty() {
+        let Some(idx) = self.hist_pos else {
             return;
-        }
-        if let Some(idx) = self.hist_pos {
-            if idx >= self.history.len().saturating_sub(1) {
+        };
+        let prefix = &self.hist_prefix;
+        match self.history[idx + 1..]
+            .iter()
+            .position(|entry| entry.starts_with(prefix.as_str()))
+        {
+            Some(offset) => {
+                self.hist_pos = Some(idx + 1 + offset);
+                self.hist_anchor = self.hist_pos;
+                self.set_history_input(self.history[idx + 1 + offset].clone());
+            }
+            None => {
+                // past the newest match: back to what was typed
                 self.hist_pos = None;
-                self.input.clear();
-                self.cursor = 0;
-                self.last_run_cmd = None;
-                self.mark_edited();
-            } else {
-                let next = idx + 1;
-                self.hist_pos = Some(next);
-                self.input = self.history[next].clone();
-                self.cursor = self.input.len();
-                self.last_run_cmd = None;
-                self.mark_edited();
+                self.hist_anchor = None;
+                self.set_history_input(self.hist_prefix.clone());
             }
-        } else {
-            self.input.clear();
-            self.cursor = 0;
-            self.last_run_cmd = None;
-            self.mark_edited();
         }
     }
 
-    pub fn insert_char(&mut self, ch: char) {
-        self.input.insert(self.cursor, ch);
-        self.cursor = next_grapheme_boundary(&self.input, self.cursor);
-        self.hist_pos = None;
+    fn set_history_input(&mut self, input: String) {
+        self.input = input;
+        self.cursor = self.input.len();
+        self.last_run_cmd = None;
         self.mark_edited();
     }
 
-    pub fn delete_backward(&mut self) {
-        if self.cursor == 0 {
-            return;
+    /// Replace `range` of the input with `text` and put the cursor after
+    /// it, unless the input would grow past `max_input_bytes`, in which
+    /// case nothing changes. Returns whether it was done.
+    fn insert_text(&mut self, range: std::ops::Range<usize>, text: &str) -> bool {
+        let limit = self.config.max_input_bytes;
+        if limit > 0 && self.input.len() - range.len() + text.len() > limit {
+            self.status_line = format!("input limit reached ({limit} bytes, max_input_bytes)");
+            return false;
         }
-        let prev = prev_grapheme_boundary(&self.input, self.cursor);
-        self.input.drain(prev..self.cursor);
-        self.cursor = prev;
+        self.cursor = range.start + text.len();
+        self.input.replace_range(range, text);
         self.hist_pos = None;
         self.mark_edited();
+        true
     }
 
-    pub fn delete_forward(&mut self) {
-        if self.cursor >= self.input.len() {
+    /// Insert `ch` at the cursor, unless the input would grow past
+    /// `max_input_bytes`; a paste arrives char by char and stops there,
+    /// leaving what was already accepted as it is.
+    pub fn insert_char(&mut self, ch: char) {
+        let at = self.cursor;
+        let mut buf = [0; 4];
+        if !self.insert_text(at..at, ch.encode_utf8(&mut buf)) {
             return;
         }
-        let next = next_grapheme_boundary(&self.input, self.cursor);
-        self.input.drain(self.cursor..next);
+        if !self.literal_input() {
+            self.cursor = self.next_boundary(at);
+        }
+    }
+
+    /// Insert the last word of the newest history entry at the cursor, like
+    /// `Alt+.` in bash. Pressed again right away, the word is replaced by
+    /// the last word of the entry before, and so on back through history.
+    pub fn insert_last_arg(&mut self) {
+        let previous = self.last_arg.take().filter(|(_, start, word)| {
+            self.cursor == start + word.len() && self.input.get(*start..self.cursor) == Some(word)
+        });
+        let from = previous.as_ref().map_or(0, |(back, _, _)| back + 1);
+        let found = self
+            .history
+            .iter()
+            .rev()
+            .enumerate()
+            .skip(from)
+            .find_map(|(back, cmd)| Some((back, last_word(cmd)?.to_string())));
+        let Some((back, word)) = found else {
+            self.status_line = "no older arguments in history".into();
+            self.last_arg = previous;
+            return;
+        };
+        let start = previous
+            .as_ref()
+            .map_or(self.cursor, |(_, start, _)| *start);
+        if !self.insert_text(start..self.cursor, &word) {
+            self.last_arg = previous;
+            return;
+        }
+        self.last_arg = Some((back, start, word));
+    }
+
+    /// Complete the file name before the cursor against the command's
+    /// working directory. A unique match is inserted quoted as needed
+    /// (`my file.txt` becomes `'my file.txt'`), with a trailing `/` for
+    /// directories; several matches are extended to their common prefix
+    /// and listed in the status line.
+    pub fn complete_path(&mut self) {
+        let (start, word) = word_before(&self.input, self.cursor);
+        let (dir, prefix) = match word.rfind('/') {
+            Some(idx) => word.split_at(idx + 1),
+            None => ("", word.as_str()),
+        };
+        let base = self
+            .config
+            .cwd
+            .clone()
+            .unwrap_or_else(|| PathBuf::from("."));
+        let search = if dir.is_empty() { base } else { base.join(dir) };
+        let mut matches: Vec<(String, bool)> = std::fs::read_dir(&search)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter_map(|entry| {
+                let name = entry.file_name().into_string().ok()?;
+                let wanted =
+                    name.starts_with(prefix) && (prefix.starts_with('.') || !name.starts_with('.'));
+                wanted.then(|| (name, entry.path().is_dir()))
+            })
+            .collect();
+        matches.sort();
+        let Some((first, _)) = matches.first() else {
+            self.status_line = format!("no files match '{word}'");
+            return;
+        };
+        let mut common = first.clone();
+        for (name, _) in &matches[1..] {
+            let len = common
+                .char_indices()
+                .zip(name.chars())
+                .find(|((_, a), b)| a != b)
+                .map_or(common.len().min(name.len()), |((idx, _), _)| idx);
+            common.truncate(len);
+        }
+        let mut completed = format!("{dir}{common}");
+        let mut replacement = match matches.as_slice() {
+            [(_, true)] => {
+                completed.push('/');
+                quote_word(&completed).into_owned()
+            }
+            [(_, false)] => format!("{} ", quote_word(&completed)),
+            _ => {
+                let names: Vec<&str> = matches.iter().map(|(name, _)| name.as_str()).collect();
+                self.status_line = format!("{} matches: {}", names.len(), names.join("  "));
+                quote_word(&completed).into_owned()
+            }
+        };
+        // a partial quoted name can't be closed yet, more may follow
+        if matches.len() > 1 && replacement != completed {
+            replacement.pop();
+        }
+        if self.input[start..self.cursor] == replacement {
+            return;
+        }
+        self.insert_text(start..self.cursor, &replacement);
+    }
+
+    /// Whether the input is edited per `char` instead of per grapheme
+    /// cluster: toggled with `literal_edit`, or forced once the input
+    /// reaches `literal_input_threshold` bytes, where scanning clusters on
+    /// every key makes pasting a long command sluggish.
+    pub fn literal_input(&self) -> bool {
+        let threshold = self.config.literal_input_threshold;
+        self.literal_edit || (threshold > 0 && self.input.len() >= threshold)
+    }
+
+    /// Handle `input` if it is a pseudo-command rather than a shell
+    /// command; returns whether it was one. `:watch <secs>` starts watch
+    /// mode, `:watch off` (or just `:watch` while watching) stops it,
+    /// `:cut <fields>` shows only some columns of stdout, `:pipe <cmd>`
+    /// runs stdout through a command, and `:clear-history` asks to wipe
+    /// the history.
+    pub fn run_pseudo_command(&mut self, input: &str) -> bool {
+        if input.trim() == ":clear-history" {
+            self.clear_input();
+            self.ask_clear_history();
+            return true;
+        }
+        if let Some(arg) = pseudo_arg(input.trim(), ":cut") {
+            self.set_column_cut(arg.trim());
+            return true;
+        }
+        if let Some(arg) = pseudo_arg(input.trim(), ":pipe") {
+            self.pipe_output(arg.trim());
+            return true;
+        }
+        let Some(arg) = pseudo_arg(input.trim(), ":watch") else {
+            return false;
+        };
+        let arg = arg.trim();
+        if arg == "off" || (arg.is_empty() && self.watch.is_some()) {
+            self.stop_watch();
+        } else {
+            let secs = if arg.is_empty() {
+                Ok(self.config.watch_interval)
+            } else {
+                arg.parse::<f64>()
+            };
+            match secs {
+                Ok(secs) if secs >= 0.1 && secs.is_finite() => {
+                    // watch what ran last, not the pseudo-command
+                    match self.last_run_cmd.clone() {
+                        Some(cmd) => {
+                            self.load_input(cmd);
+                            self.start_watch(Duration::from_secs_f64(secs));
+                        }
+                        None => self.status_line = "nothing to watch: run a command first".into(),
+                    }
+                }
+                _ => self.status_line = format!("invalid watch interval '{arg}'"),
+            }
+        }
+        true
+    }
+
+    /// Show only the columns `args` selects of each stdout line (see
+    /// `ColumnCut::parse`); none or `off` shows whole lines again.
+    pub fn set_column_cut(&mut self, args: &str) {
+        // back to what ran, whose output the cut applies to
+        let cmd = self.last_run_cmd.clone().unwrap_or_default();
+        if args.is_empty() || args == "off" {
+            self.load_input(cmd);
+            self.status_line = match self.column_cut.take() {
+                Some(_) => "showing whole lines".into(),
+                None => "no columns cut".into(),
+            };
+            return;
+        }
+        match ColumnCut::parse(args) {
+            Ok(cut) => {
+                self.status_line = format!("showing columns {}", cut.spec);
+                self.column_cut = Some(cut);
+                self.load_input(cmd);
+            }
+            Err(err) => self.status_line = format!("cut: {err}"),
+        }
+    }
+
+    /// Run the stdout shown through `cmd` (`sort | uniq -c`) and show what
+    /// comes out in its place, as if it had been one more stage of the
+    /// pipeline, without running the pipeline again. Piping again works on
+    /// the result; none or `off` goes back to the original output.
+    pub fn pipe_output(&mut self, cmd: &str) {
+        if self.is_running() {
+            self.status_line = "pipe: wait for the run to finish".into();
+            return;
+        }
+        if cmd.is_empty() || cmd == "off" {
+            let Some(source) = self.pipe_source.take() else {
+                self.status_line = "no piped output to undo".into();
+                return;
+            };
+            self.clear_output();
+            self.output_cmd = source.cmd;
+            self.output_lines = source.lines;
+            self.error_lines = source.error_lines;
+            self.raw_stdout = source.raw_stdout;
+            self.load_input(self.last_run_cmd.clone().unwrap_or_default());
+            self.status_line = "original output restored".into();
+            return;
+        }
+        if self.output_lines.is_empty() {
+            self.status_line = "no stdout to pipe".into();
+            return;
+        }
+        let mut stdin = self.output_lines.join("\n");
+        stdin.push('\n');
+        if self.pipe_source.is_none() {
+            self.pipe_source = Some(PipeSource {
+                cmd: self.output_cmd.clone(),
+                lines: self.output_lines.clone(),
+                error_lines: self.error_lines.clone(),
+                raw_stdout: self.raw_stdout.clone(),
+            });
+        }
+        let mut spec = self.run_spec(cmd);
+        spec.stdin = Some(stdin);
+        self.pipe_request = Some(spec);
+        self.pipe_running = true;
+        self.pending_cmd = None;
+        self.run_state = RunState::Running { manual: true };
+        self.load_input(self.last_run_cmd.clone().unwrap_or_default());
+    }
+
+    /// Re-run the input every `interval`, whether or not it changed.
+    pub fn start_watch(&mut self, interval: Duration) {
+        self.watch = Some(interval);
+        self.watch_next_at = Some(Instant::now());
+        self.status_line = format!("watching every {}", utility::format_interval(interval));
+    }
+
+    pub fn stop_watch(&mut self) {
+        self.watch = None;
+        self.watch_next_at = None;
+        self.status_line = "watch stopped".into();
+    }
+
+    /// Start watching at `watch_interval`, or stop.
+    pub fn toggle_watch(&mut self) {
+        if self.watch.is_some() {
+            self.stop_watch();
+        } else {
+            self.start_watch(Duration::from_secs_f64(self.config.watch_interval.max(0.1)));
+        }
+    }
+
+    /// The command for a due watch run, if any; schedules the next one.
+    pub fn take_watch_run(&mut self) -> Option<String> {
+        let interval = self.watch?;
+        if self.is_running() || self.watch_next_at.is_some_and(|at| at > Instant::now()) {
+            return None;
+        }
+        let input = self.strip_trigger(&self.input).unwrap_or(&self.input);
+        let cmd = self.without_comment(input).to_string();
+        if cmd.trim().is_empty() || is_pseudo_command(&cmd) {
+            return None;
+        }
+        self.watch_next_at = Some(Instant::now() + interval);
+        Some(cmd)
+    }
+
+    /// Switch between replacing the output on every run and appending
+    /// each run's output below the previous one.
+    pub fn toggle_append_output(&mut self) {
+        self.append_output = !self.append_output;
+        self.status_line = if self.append_output {
+            "append mode: runs add to the output".into()
+        } else {
+            "replace mode: each run replaces the output".into()
+        };
+    }
+
+    /// Cycle the timestamp gutter: off, time since the run started, wall
+    /// clock.
+    pub fn cycle_timestamps(&mut self) {
+        self.timestamps = match self.timestamps {
+            Timestamps::Off => Timestamps::Relative,
+            Timestamps::Relative => Timestamps::Clock,
+            Timestamps::Clock => Timestamps::Off,
+        };
+        self.status_line = match self.timestamps {
+            Timestamps::Off => "timestamps off",
+            Timestamps::Relative => "timestamps: seconds since the run started",
+            Timestamps::Clock => "timestamps: wall clock",
+        }
+        .into();
+    }
+
+    /// Switch stdout between text and the table view, which shows output
+    /// that looks tabular as aligned columns.
+    pub fn toggle_table_view(&mut self) {
+        self.table_view = !self.table_view;
+        self.status_line = if self.table_view {
+            "table view: tabular output is shown as aligned columns".into()
+        } else {
+            "table view off".into()
+        };
+    }
+
+    /// Fold runs of identical lines in both panes into one line with a
+    /// `(×N)` count, like a live `uniq -c`; the output itself is kept.
+    pub fn toggle_fold_repeats(&mut self) {
+        self.fold_repeats = !self.fold_repeats;
+        self.status_line = if self.fold_repeats {
+            "repeated lines folded into one with a count".into()
+        } else {
+            "repeated lines shown in full".into()
+        };
+    }
+
+    /// Show the notes pane and focus it; from the notes pane, hide it
+    /// again (saving the notes) and go back to the input.
+    pub fn toggle_notes(&mut self) {
+        if self.notes.visible && self.focus == Focus::Notes {
+            self.notes.visible = false;
+            self.notes.save_if_idle(true);
+            self.focus = Focus::Input;
+        } else {
+            self.notes.visible = true;
+            self.focus = Focus::Notes;
+        }
+    }
+
+    pub fn toggle_scratch_runs(&mut self) {
+        self.scratch_runs = !self.scratch_runs;
+        self.status_line = if self.scratch_runs {
+            "scratch runs: each run in a fresh temporary directory".into()
+        } else {
+            "scratch runs off: runs use the working directory".into()
+        };
+    }
+
+    pub fn toggle_literal_edit(&mut self) {
+        self.literal_edit = !self.literal_edit;
+        self.status_line = if self.literal_edit {
+            "literal edit on: cursor moves by character".into()
+        } else {
+            "literal edit off".into()
+        };
+    }
+
+    fn prev_boundary(&self, idx: usize) -> usize {
+        if self.literal_input() {
+            self.input[..idx]
+                .char_indices()
+                .next_back()
+                .map_or(0, |(i, _)| i)
+        } else {
+            prev_grapheme_boundary(&self.input, idx)
+        }
+    }
+
+    fn next_boundary(&self, idx: usize) -> usize {
+        if self.literal_input() {
+            self.input[idx..]
+                .chars()
+                .next()
+                .map_or(idx, |ch| idx + ch.len_utf8())
+        } else {
+            next_grapheme_boundary(&self.input, idx)
+        }
+    }
+
+    pub fn delete_backward(&mut self) {
+        if self.cursor == 0 {
+            return;
+        }
+        let prev = self.prev_boundary(self.cursor);
+        self.input.drain(prev..self.cursor);
+        self.cursor = prev;
+        self.hist_pos = None;
+        self.mark_edited();
+    }
+
+    pub fn delete_forward(&mut self) {
+        if self.cursor >= self.input.len() {
+            return;
+        }
+        let next = self.next_boundary(self.cursor);
+        self.input.drain(self.cursor..next);
+        self.hist_pos = None;
+        self.mark_edited();
+    }
+
+    pub fn delete_word_forward(&mut self) {
+        let end = next_word_boundary(&self.input, self.cursor);
+        if end == self.cursor {
+            return;
+        }
+        self.input.drain(self.cursor..end);
         self.hist_pos = None;
         self.mark_edited();
     }
 
+    /// Swap the graphemes around the cursor (the last two at end of line)
+    /// and step past them, like readline's `transpose-chars`.
+    pub fn transpose_chars(&mut self) {
+        if self.cursor == 0 {
+            return;
+        }
+        let mut mid = self.cursor;
+        if mid >= self.input.len() {
+            mid = self.prev_boundary(mid);
+            if mid == 0 {
+                return;
+            }
+        }
+        let start = self.prev_boundary(mid);
+        let end = self.next_boundary(mid);
+        let swapped = format!("{}{}", &self.input[mid..end], &self.input[start..mid]);
+        self.input.replace_range(start..end, &swapped);
+        self.cursor = end;
+        self.hist_pos = None;
+        self.mark_edited();
+    }
+
+    pub fn upcase_word(&mut self) {
+        self.transform_word(|word| word.to_uppercase());
+    }
+
+    pub fn downcase_word(&mut self) {
+        self.transform_word(|word| word.to_lowercase());
+    }
+
+    pub fn capitalize_word(&mut self) {
+        self.transform_word(|word| {
+            let mut out = String::with_capacity(word.len());
+            let mut seen_alnum = false;
+            for ch in word.chars() {
+                if !seen_alnum && ch.is_alphanumeric() {
+                    seen_alnum = true;
+                    out.extend(ch.to_uppercase());
+                } else {
+                    out.extend(ch.to_lowercase());
+                }
+            }
+            out
+        });
+    }
+
+    /// Rewrite the span from the cursor to the end of the next word and move
+    /// the cursor past it. Case mapping can change the byte length, so the
+    /// new end comes from the replacement.
+    fn transform_word(&mut self, transform: impl Fn(&str) -> String) {
+        let end = next_word_boundary(&self.input, self.cursor);
+        if end == self.cursor {
+            return;
+        }
+        let replaced = transform(&self.input[self.cursor..end]);
+        // `ŉ` upcases to `ʼN`: the input may grow
+        self.insert_text(self.cursor..end, &replaced);
+    }
+
+    /// Replace the input without scheduling an auto-run.
+    pub fn load_input(&mut self, input: String) {
+        self.cursor = input.len();
+        self.input = input;
+        self.hist_pos = None;
+    }
+
     pub fn clear_input(&mut self) {
         self.input.clear();
         self.cursor = 0;
         self.hist_pos = None;
+        self.hist_anchor = None;
         self.mark_edited();
     }
 
+    /// Start of the input's current line (of the whole input unless it
+    /// spans several lines).
     pub fn move_cursor_home(&mut self) {
-        self.cursor = 0;
+        self.cursor = self.line_start(self.cursor);
     }
 
     pub fn move_cursor_end(&mut self) {
-        self.cursor = self.input.len();
+        self.cursor = self.line_end(self.cursor);
+    }
+
+    fn line_start(&self, idx: usize) -> usize {
+        self.input[..idx].rfind('\n').map_or(0, |nl| nl + 1)
+    }
+
+    fn line_end(&self, idx: usize) -> usize {
+        self.input[idx..]
+            .find('\n')
+            .map_or(self.input.len(), |nl| idx + nl)
+    }
+
+    /// Row and display column of the cursor in a multi-line input.
+    pub fn cursor_position(&self) -> (usize, usize) {
+        let start = self.line_start(self.cursor);
+        let row = self.input[..start].matches('\n').count();
+        let line = &self.input[start..self.cursor];
+        let col = if self.literal_input() {
+            UnicodeWidthStr::width(line)
+        } else {
+            cursor_column(line, line.len())
+        };
+        (row, col)
+    }
+
+    /// Arrow keys: move between the lines of a multi-line input, and walk
+    /// the history from its first/last line.
+    pub fn line_or_history(&mut self, delta: isize) {
+        if !self.move_line(delta) {
+            if delta < 0 {
+                self.history_prev();
+            } else {
+                self.history_next();
+            }
+        }
+    }
+
+    /// Move the cursor one line up (`delta` -1) or down (1) at the same
+    /// column. Returns false on the first/last line.
+    fn move_line(&mut self, delta: isize) -> bool {
+        let start = self.line_start(self.cursor);
+        let (_, col) = self.cursor_position();
+        let target = if delta < 0 {
+            if start == 0 {
+                return false;
+            }
+            self.line_start(start - 1)
+        } else {
+            let end = self.line_end(self.cursor);
+            if end == self.input.len() {
+                return false;
+            }
+            end + 1
+        };
+        let line = &self.input[target..self.line_end(target)];
+        self.cursor = target + column_offset(line, col);
+        true
+    }
+
+    /// Switch between Enter running the input and Enter inserting a
+    /// newline (`Alt+Enter` runs), for composing multi-line scripts.
+    pub fn toggle_multiline(&mut self) {
+        self.multiline = !self.multiline;
+        self.status_line = if self.multiline {
+            "multi-line input: Enter inserts a newline, Alt+Enter runs".into()
+        } else {
+            "single-line input: Enter runs".into()
+        };
+    }
+
+    /// Leave insert mode; like vi, the cursor steps back onto the last
+    /// character typed.
+    pub fn enter_normal_mode(&mut self) {
+        if self.edit_mode == EditMode::Insert && self.cursor > self.line_start(self.cursor) {
+            self.move_cursor_left();
+        }
+        self.edit_mode = EditMode::Normal;
+        self.vi_pending = None;
+    }
+
+    pub fn enter_insert_mode(&mut self) {
+        self.edit_mode = EditMode::Insert;
+        self.vi_pending = None;
+    }
+
+    /// Delete from the cursor to the end of its line (vi `D`).
+    pub fn delete_to_line_end(&mut self) {
+        let end = self.line_end(self.cursor);
+        if end == self.cursor {
+            return;
+        }
+        self.input.drain(self.cursor..end);
+        self.hist_pos = None;
+        self.mark_edited();
     }
 
     pub fn move_cursor_left(&mut self) {
-        self.cursor = prev_grapheme_boundary(&self.input, self.cursor);
+        self.cursor = self.prev_boundary(self.cursor);
     }
 
     pub fn move_cursor_right(&mut self) {
-        self.cursor = next_grapheme_boundary(&self.input, self.cursor);
+        self.cursor = self.next_boundary(self.cursor);
+    }
+
+    pub fn move_word_left(&mut self) {
+        self.cursor = prev_word_boundary(&self.input, self.cursor);
+    }
+
+    pub fn move_word_right(&mut self) {
+        self.cursor = next_word_boundary(&self.input, self.cursor);
     }
 
     pub fn mark_edited(&mut self) {
-        self.last_edit_at = Some(Instant::now());
+        // a failure streak is about one command: editing it starts afresh
+        let input = self.strip_trigger(&self.input).unwrap_or(&self.input);
+        if self
+            .failure_streak
+            .as_ref()
+            .is_some_and(|(failed, _)| *failed != self.without_comment(input))
+        {
+            self.failure_streak = None;
+        }
+        let now = Instant::now();
+        self.last_edit_at = Some(now);
+        self.draft_dirty = true;
+        if self.is_running() {
+            self.pending_cmd = Some(self.input.clone());
+        } else {
+            self.run_state = RunState::Pending { edited_at: now };
+        }
     }
 
-    pub fn should_auto_run(&self) -> bool {
-        if self.is_running {
-            return false;
+    /// Save the input as a draft once it has been left alone for
+    /// `DRAFT_SAVE_IDLE`.
+    pub fn save_draft_if_idle(&mut self) {
+        let idle = self
+            .last_edit_at
+            .is_some_and(|at| at.elapsed() >= DRAFT_SAVE_IDLE);
+        if self.draft_dirty && idle {
+            persistence::save_draft(&self.input);
+            self.draft_dirty = false;
         }
-        let Some(edit_at) = self.last_edit_at else {
-            return false;
+    }
+
+    /// Ask whether to restore `draft`, unless it is what the input already
+    /// holds.
+    pub fn offer_draft(&mut self, draft: String) {
+        if draft != self.input {
+            self.overlay = Some(Overlay::RestoreDraft(draft));
+        }
+    }
+
+    /// Ask for confirmation before `:clear-history` wipes the history.
+    pub fn ask_clear_history(&mut self) {
+        self.overlay = Some(Overlay::ConfirmClearHistory);
+    }
+
+    /// Close the confirmation, wiping the history in memory and on disk
+    /// if `confirmed`.
+    pub fn resolve_clear_history(&mut self, confirmed: bool) {
+        if !matches!(self.overlay.take(), Some(Overlay::ConfirmClearHistory)) {
+            return;
+        }
+        if !confirmed {
+            self.status_line = "history kept".into();
+            return;
+        }
+        let count = self.history.len();
+        self.history.clear();
+        self.never_ran.clear();
+        self.history_saved = 0;
+        self.hist_pos = None;
+        self.hist_anchor = None;
+        self.hist_prefix.clear();
+        self.status_line = match persistence::clear_history(self) {
+            Ok(()) => format!("cleared {count} history entries"),
+            Err(e) => format!("history cleared for this session, but not on disk: {e}"),
         };
-        if edit_at.elapsed() < Duration::from_millis(250) {
-            return false;
+    }
+
+    /// Answer the restore prompt: load the draft or drop it.
+    pub fn resolve_draft(&mut self, restore: bool) {
+        let Some(Overlay::RestoreDraft(draft)) = self.overlay.take() else {
+            return;
+        };
+        if restore {
+            self.load_input(draft);
+            self.status_line = "restored unsaved input".into();
+        } else {
+            persistence::clear_draft();
         }
-        if self.input.trim().is_empty() {
-            return false;
+    }
+
+    /// When this session next needs attention without any key being
+    /// pressed: every `RUNNING_TICK` while a command runs, else the next
+    /// debounced auto-run, watch run, draft or notes save. `None` when nothing is
+    /// pending, so the main loop can just wait for input.
+    pub fn next_wakeup(&self) -> Option<Instant> {
+        let now = Instant::now();
+        if self.is_running() {
+            return Some(now + RUNNING_TICK);
         }
-        if let Some(last) = &self.last_run_cmd {
-            if last == &self.input {
-                return false;
+        let auto_run = match self.run_state {
+            RunState::Pending { edited_at } => {
+                let input = self.strip_trigger(&self.input).unwrap_or(&self.input);
+                Some(edited_at + self.auto_run_delay(&self.without_comment(input)))
             }
+            _ => None,
+        };
+        let watch = self.watch_next_at.filter(|_| self.watch.is_some());
+        let draft = self
+            .last_edit_at
+            .filter(|_| self.draft_dirty)
+            .map(|at| at + DRAFT_SAVE_IDLE);
+        // a deadline already passed was either handled or is moot
+        [auto_run, watch, draft, self.notes.save_due()]
+            .into_iter()
+            .flatten()
+            .filter(|at| *at > now)
+            .min()
+    }
+
+    /// The command to auto-run once the debounce has elapsed, if any.
+    pub fn auto_run_cmd(&self) -> Option<String> {
+        let RunState::Pending { edited_at } = self.run_state else {
+            return None;
+        };
+        let cmd = self.without_comment(self.strip_trigger(&self.input)?);
+        if edited_at.elapsed() < self.auto_run_delay(&cmd)
+            || cmd.trim().is_empty()
+            || is_pseudo_command(&cmd)
+            || self.last_run_cmd.as_deref() == Some(&*cmd)
+            || self.auto_run_paused(&cmd)
+            || is_incomplete(&cmd)
+            || self.auto_run_refusal(&cmd).is_some()
+        {
+            return None;
+        }
+        Some(cmd.to_string())
+    }
+
+    /// Why `cmd` may not be auto-run per `auto_run_allow` and
+    /// `auto_run_block`, if it may not: each simple command in it must
+    /// start with an allowed prefix (when any are set) and none with a
+    /// blocked one. Input that hides what it runs, like `$(...)`, is only
+    /// auto-run while neither list is set.
+    fn auto_run_refusal(&self, cmd: &str) -> Option<&'static str> {
+        let (allow, block) = (&self.config.auto_run_allow, &self.config.auto_run_block);
+        if allow.is_empty() && block.is_empty() {
+            return None;
+        }
+        let Some(commands) = parser::simple_commands(cmd) else {
+            return Some("press Enter to run: too complex to auto-run");
+        };
+        let matches_any = |command: &str, prefixes: &[String]| {
+            prefixes
+                .iter()
+                .any(|prefix| parser::command_matches(command, prefix))
+        };
+        if commands.iter().any(|command| matches_any(command, block)) {
+            Some("press Enter to run: blocked from auto-run")
+        } else if !allow.is_empty() && !commands.iter().all(|command| matches_any(command, allow)) {
+            Some("press Enter to run: not on the auto-run allowlist")
+        } else {
+            None
+        }
+    }
+
+    /// Shown instead of the status while the edited input won't auto-run
+    /// because of `auto_run_allow` or `auto_run_block`.
+    pub fn auto_run_hint(&self) -> Option<&'static str> {
+        if !matches!(self.run_state, RunState::Pending { .. }) {
+            return None;
+        }
+        let cmd = self.without_comment(self.strip_trigger(&self.input)?);
+        if cmd.trim().is_empty() || is_pseudo_command(&cmd) || is_incomplete(&cmd) {
+            return None;
+        }
+        self.auto_run_refusal(&cmd)
+    }
+
+    /// Debounce before auto-running `cmd`, doubled for each time in a row
+    /// it has just failed, up to `AUTO_RUN_FAILURE_LIMIT` doublings (watch
+    /// and forced runs keep counting past the pause).
+    fn auto_run_delay(&self, cmd: &str) -> Duration {
+        match &self.failure_streak {
+            Some((failed, count)) if failed == cmd => {
+                AUTO_RUN_DEBOUNCE.saturating_mul(1 << (*count).min(AUTO_RUN_FAILURE_LIMIT))
+            }
+            _ => AUTO_RUN_DEBOUNCE,
+        }
+    }
+
+    /// Whether `cmd` has failed `AUTO_RUN_FAILURE_LIMIT` times in a row,
+    /// so only `Enter` runs it again.
+    fn auto_run_paused(&self, cmd: &str) -> bool {
+        matches!(&self.failure_streak, Some((failed, count))
+            if failed == cmd && *count >= AUTO_RUN_FAILURE_LIMIT)
+    }
+
+    /// `cmd` without its trailing `#` comments (one per line of a
+    /// multi-line input) when `strip_comments` is on.
+    pub fn without_comment<'a>(&self, cmd: &'a str) -> Cow<'a, str> {
+        if !self.config.strip_comments {
+            Cow::Borrowed(cmd)
+        } else if cmd.contains('\n') {
+            Cow::Owned(
+                cmd.lines()
+                    .map(strip_comment)
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            )
+        } else {
+            Cow::Borrowed(strip_comment(cmd))
+        }
+    }
+
+    /// `input` without the configured auto-run trigger, or `None` when a
+    /// trigger is set and `input` doesn't end with it.
+    pub fn strip_trigger<'a>(&self, input: &'a str) -> Option<&'a str> {
+        match self.config.auto_run_trigger.as_deref() {
+            Some(trigger) if !trigger.is_empty() => input.strip_suffix(trigger),
+            _ => Some(input),
         }
-        true
     }
 
     pub fn prepare_run(&mut self, cmd: &str, manual: bool) -> bool {
@@ -289,9 +2286,1072 @@ impl App {
             return false;
         }
         self.hist_pos = None;
+        if self.dry_run_armed {
+            // preview only: nothing is spawned and nothing reaches history;
+            // settle the state so the same input doesn't auto-run right after
+            self.dry_run_armed = false;
+            self.overlay = Some(Overlay::DryRun(execution::describe_run(
+                &self.run_spec(cmd),
+            )));
+            self.status_line = "dry run (not executed)".into();
+            if !self.is_running() {
+                self.run_state = if self.last_run_cmd.is_some() {
+                    RunState::Finished
+                } else {
+                    RunState::Idle
+                };
+            }
+            return false;
+        }
         self.last_run_cmd = Some(cmd.to_string());
-        self.last_edit_at = None;
-        self.append_history_on_finish = manual;
+        self.pending_cmd = None;
+        if self.is_running() {
+            // the state stays the running command's until it finishes
+            self.queued_manual = Some(manual);
+            self.status_line = format!("queued after the running command: {cmd}");
+        } else {
+            self.run_state = RunState::Running { manual };
+        }
         true
     }
+
+    /// The input queued while the previous run was busy, once that run has
+    /// finished. It skips the debounce: the user already waited.
+    pub fn take_queued_run(&mut self) -> Option<String> {
+        if self.is_running() {
+            return None;
+        }
+        let cmd = self.pending_cmd.take()?;
+        let cmd = self.without_comment(self.strip_trigger(&cmd)?);
+        (!cmd.trim().is_empty()
+            && !is_pseudo_command(&cmd)
+            && !self.auto_run_paused(&cmd)
+            && !is_incomplete(&cmd)
+            && self.auto_run_refusal(&cmd).is_none())
+        .then(|| cmd.into_owned())
+    }
+
+    /// Payload for `WorkerMsg::Run` reflecting the current session settings.
+    pub fn run_spec(&self, cmd: &str) -> RunSpec {
+        RunSpec {
+            cmd: cmd.to_string(),
+            mode: self.exec_mode,
+            shell: self.config.shell.clone(),
+            cwd: self.config.cwd.clone(),
+            env: self.config.env.clone(),
+            stdin: self.captured_stdin.clone(),
+            pty: self.config.pty,
+            scratch: self.scratch_runs,
+        }
+    }
+
+    /// Arm the current stdout as stdin for the following runs, or disarm
+    /// it if already armed.
+    pub fn toggle_captured_stdin(&mut self) {
+        if self.captured_stdin.take().is_some() {
+            self.status_line = "captured stdin cleared".into();
+            return;
+        }
+        let mut text = self.output_lines.join("\n");
+        if !text.is_empty() {
+            text.push('\n');
+        }
+        self.status_line = format!(
+            "captured {} lines as stdin for the next runs",
+            self.output_lines.len()
+        );
+        self.captured_stdin = Some(text);
+    }
+
+    /// Restore view preferences saved by an earlier session. A mode set
+    /// explicitly in the config wins over the saved one.
+    pub fn apply_ui_state(&mut self, state: UiState) {
+        if self.config.exec_mode.is_none() {
+            if let Some(mode) = state.exec_mode {
+                self.exec_mode = mode;
+            }
+        }
+        self.zoom = state.zoom;
+    }
+
+    pub fn ui_state(&self) -> UiState {
+        UiState {
+            exec_mode: Some(self.exec_mode),
+            zoom: self.zoom,
+        }
+    }
+
+    pub fn toggle_exec_mode(&mut self) {
+        self.exec_mode = match self.exec_mode {
+            ExecMode::Shell => ExecMode::Direct,
+            ExecMode::Direct => ExecMode::Shell,
+        };
+        self.status_line = match self.exec_mode {
+            ExecMode::Shell => "shell mode: commands run via the shell".into(),
+            ExecMode::Direct => "direct mode: single program, no shell".into(),
+        };
+        // same text may behave differently now, so let it auto-run again
+        self.last_run_cmd = None;
+        self.mark_edited();
+    }
+
+    pub fn toggle_dry_run(&mut self) {
+        self.dry_run_armed = !self.dry_run_armed;
+        self.status_line = if self.dry_run_armed {
+            "dry run armed: next run will only be previewed".into()
+        } else {
+            "dry run disarmed".into()
+        };
+    }
+
+    /// Maximize `pane`, or restore the normal layout if it is already zoomed.
+    /// The input box is hidden while zoomed but keeps receiving keys.
+    pub fn toggle_zoom(&mut self, pane: Pane) {
+        self.zoom = if self.zoom == Some(pane) {
+            None
+        } else {
+            Some(pane)
+        };
+    }
+
+    pub fn close_overlay(&mut self) {
+        self.overlay = None;
+    }
+
+    /// Pin the current stdout as the baseline for later runs.
+    pub fn pin_output(&mut self) {
+        let label = self.last_run_cmd.clone().unwrap_or_default();
+        self.status_line = format!("pinned {} lines of '{label}'", self.output_lines.len());
+        self.pinned = Some(PinnedOutput {
+            label,
+            lines: self.output_lines.clone(),
+            comparison: None,
+        });
+    }
+
+    pub fn unpin_output(&mut self) {
+        if self.pinned.take().is_some() {
+            self.status_line = "pin cleared".into();
+        }
+    }
+
+    /// Text of the current stdout (stderr while that pane has focus), either
+    /// as received (`raw`, ANSI escapes intact, after the echoed command if
+    /// any) or as the plain text of the pane, with the pane's name.
+    fn output_text(&self, raw: bool) -> (&'static str, String) {
+        if self.focus == Focus::Stderr {
+            let text = self.error_lines.join("\n");
+            let text = if raw {
+                text
+            } else {
+                utility::strip_ansi(&text)
+            };
+            return ("stderr", text);
+        }
+        let text = if raw {
+            // the echoed command only rides along in the raw copy
+            match self
+                .output_cmd
+                .as_ref()
+                .filter(|_| self.config.echo_command)
+            {
+                Some(cmd) => format!("$ {cmd}\n{}", self.raw_stdout),
+                None => self.raw_stdout.clone(),
+            }
+        } else {
+            let mut text = self.output_lines.join("\n");
+            if !self.stdout_partial.is_empty() {
+                text.push('\n');
+                text.push_str(&self.stdout_partial);
+            }
+            utility::strip_ansi(&text)
+        };
+        ("stdout", text)
+    }
+
+    /// Copy the focused output to the clipboard, `raw` or plain.
+    pub fn copy_output(&mut self, raw: bool) {
+        let (pane, text) = self.output_text(raw);
+        utility::copy_to_clipboard(&text);
+        let kind = if raw { "raw" } else { "plain" };
+        self.status_line = format!("copied {} bytes of {pane} ({kind})", text.len());
+    }
+
+    /// Copy the focused output for pasting into a bug report or chat: plain
+    /// text with control characters made visible, in a Markdown code block
+    /// unless `share_fenced` is off. As a `snippet`, the command and how it
+    /// exited come first and last, like a terminal transcript.
+    pub fn copy_for_sharing(&mut self, snippet: bool) {
+        let (pane, text) = self.output_text(false);
+        if text.is_empty() && !snippet {
+            self.status_line = format!("no {pane} to copy");
+            return;
+        }
+        let mut text = utility::sanitize(&text);
+        if snippet {
+            let Some(cmd) = self.output_cmd.as_deref() else {
+                self.status_line = "nothing has run yet".into();
+                return;
+            };
+            let status = self
+                .run_log
+                .back()
+                .filter(|record| record.cmd == cmd && !self.is_running())
+                .map(|record| format!("\n[{}]", record.status))
+                .unwrap_or_default();
+            let body = if text.is_empty() {
+                text
+            } else {
+                format!("\n{text}")
+            };
+            text = format!("$ {}{body}{status}", utility::sanitize(cmd));
+        }
+        if self.config.share_fenced {
+            text = utility::markdown_fence(&text);
+        }
+        utility::copy_to_clipboard(&text);
+        let kind = if snippet { "snippet" } else { "for sharing" };
+        self.status_line = format!("copied {} bytes of {pane} ({kind})", text.len());
+    }
+
+    /// Copy the command that produced the output (else the input) as a
+    /// self-contained shell line with its cwd and env, for reproducing it
+    /// elsewhere.
+    pub fn copy_shell_line(&mut self) {
+        let cmd = match self.output_cmd.as_deref() {
+            Some(cmd) => cmd.to_string(),
+            None if !self.input.trim().is_empty() => self.input.trim().to_string(),
+            None => {
+                self.status_line = "nothing to copy".into();
+                return;
+            }
+        };
+        let line = self.run_spec(&cmd).shell_line();
+        utility::copy_to_clipboard(&line);
+        self.status_line = format!("copied shell command ({} bytes)", line.len());
+    }
+
+    /// The panic button, after cancelling any run: drop what the panes
+    /// hold and whatever the cancelled run still sends, stop watching, and
+    /// have the main loop clear the terminal, in case the output left it
+    /// garbled.
+    pub fn reset(&mut self) {
+        if self.is_running() {
+            // like a failed quiet auto-run, the rest of it is never shown
+            self.staged = Some(StagedOutput::default());
+        }
+        self.watch = None;
+        self.watch_next_at = None;
+        self.overlay = None;
+        self.zoom = None;
+        self.column_cut = None;
+        self.pipe_source = None;
+        self.clear_output();
+        self.status_line = "Ready".into();
+        self.clear_screen_request = true;
+    }
+
+    /// Ask the main loop to show the focused output in `viewer`.
+    pub fn request_viewer(&mut self, viewer: Viewer) {
+        self.view_request = Some(viewer);
+    }
+
+    /// The pending viewer request with the text to show in it: raw for the
+    /// pager (`less -R` keeps colors), plain for the editor.
+    pub fn take_view_request(&mut self) -> Option<(Viewer, String)> {
+        let viewer = self.view_request.take()?;
+        let (pane, text) = self.output_text(viewer == Viewer::Pager);
+        if text.is_empty() {
+            self.status_line = format!("no {pane} to show");
+            return None;
+        }
+        Some((viewer, text))
+    }
+
+    /// Start selecting stdout lines, beginning at the last one.
+    pub fn start_selection(&mut self) {
+        let Some(last) = self.output_lines.len().checked_sub(1) else {
+            self.status_line = "no stdout to select".into();
+            return;
+        };
+        self.selection = Some(LineSelection {
+            anchor: last,
+            cursor: last,
+        });
+    }
+
+    pub fn move_selection(&mut self, delta: isize) {
+        let last = self.output_lines.len().saturating_sub(1);
+        if let Some(selection) = &mut self.selection {
+            selection.cursor = selection.cursor.saturating_add_signed(delta).min(last);
+        }
+    }
+
+    pub fn cancel_selection(&mut self) {
+        self.selection = None;
+    }
+
+    /// Copy the selected lines as plain text and leave selection mode.
+    pub fn copy_selection(&mut self) {
+        let Some(selection) = self.selection.take() else {
+            return;
+        };
+        let range = selection.range();
+        let Some(lines) = self.output_lines.get(range.clone()) else {
+            return;
+        };
+        utility::copy_to_clipboard(&utility::strip_ansi(&lines.join("\n")));
+        self.status_line = format!(
+            "copied lines {}-{} of stdout",
+            range.start() + 1,
+            range.end() + 1
+        );
+    }
+
+    pub fn show_stats(&mut self) {
+        self.overlay = Some(Overlay::Stats(self.output_stats()));
+    }
+
+    fn output_stats(&self) -> Vec<String> {
+        let lines = &self.output_lines;
+        let non_empty = lines.iter().filter(|l| !l.trim().is_empty()).count();
+        let words: usize = lines.iter().map(|l| l.split_whitespace().count()).sum();
+        let unique: HashSet<&str> = lines
+            .iter()
+            .take(UNIQUE_SCAN_LIMIT)
+            .map(|l| l.as_str())
+            .collect();
+        let unique = if lines.len() > UNIQUE_SCAN_LIMIT {
+            format!(
+                "{}+ (first {UNIQUE_SCAN_LIMIT} lines scanned)",
+                unique.len()
+            )
+        } else {
+            unique.len().to_string()
+        };
+        let longest = lines
+            .iter()
+            .enumerate()
+            .map(|(idx, l)| (UnicodeWidthStr::width(l.as_str()), idx))
+            .max_by_key(|&(width, idx)| (width, std::cmp::Reverse(idx)));
+        let longest = match longest {
+            Some((width, idx)) => format!("{width} columns (line {})", idx + 1),
+            None => "-".into(),
+        };
+        vec![
+            format!("lines:     {}", lines.len()),
+            format!("non-empty: {non_empty}"),
+            format!("unique:    {unique}"),
+            format!("words:     {words}"),
+            format!("longest:   {longest}"),
+        ]
+    }
+
+    pub fn toggle_run_log(&mut self) {
+        self.overlay = match self.overlay {
+            Some(Overlay::RunLog { .. }) => None,
+            _ => Some(Overlay::RunLog { selected: 0 }),
+        };
+    }
+
+    pub fn run_log_move(&mut self, delta: isize) {
+        if let Some(Overlay::RunLog { selected }) = &mut self.overlay {
+            let last = self.run_log.len().saturating_sub(1);
+            *selected = selected.saturating_add_signed(delta).min(last);
+        }
+    }
+
+    pub fn open_palette(&mut self) {
+        self.overlay = Some(Overlay::Palette {
+            query: String::new(),
+            selected: 0,
+        });
+    }
+
+    pub fn palette_edit(&mut self, ch: Option<char>) {
+        if let Some(Overlay::Palette { query, selected }) = &mut self.overlay {
+            match ch {
+                Some(ch) => query.push(ch),
+                None => {
+                    query.pop();
+                }
+            }
+            *selected = 0;
+        }
+    }
+
+    pub fn palette_move(&mut self, delta: isize) {
+        if let Some(Overlay::Palette { query, selected }) = &mut self.overlay {
+            let last = action::filter(query).len().saturating_sub(1);
+            *selected = selected.saturating_add_signed(delta).min(last);
+        }
+    }
+
+    /// Close the palette, returning the selected action if any matched.
+    pub fn take_palette_choice(&mut self) -> Option<Action> {
+        let Some(Overlay::Palette { query, selected }) = self.overlay.take() else {
+            return None;
+        };
+        action::filter(&query).get(selected).copied()
+    }
+
+    pub fn open_history_search(&mut self) {
+        self.overlay = Some(Overlay::HistorySearch {
+            query: String::new(),
+            matches: self.search_history(""),
+            selected: 0,
+        });
+    }
+
+    pub fn history_search_edit(&mut self, ch: Option<char>) {
+        let Some(Overlay::HistorySearch { mut query, .. }) = self.overlay.take() else {
+            return;
+        };
+        match ch {
+            Some(ch) => query.push(ch),
+            None => {
+                query.pop();
+            }
+        }
+        let matches = self.search_history(&query);
+        self.overlay = Some(Overlay::HistorySearch {
+            query,
+            matches,
+            selected: 0,
+        });
+    }
+
+    pub fn history_search_move(&mut self, delta: isize) {
+        if let Some(Overlay::HistorySearch {
+            matches, selected, ..
+        }) = &mut self.overlay
+        {
+            let last = matches.len().saturating_sub(1);
+            *selected = selected.saturating_add_signed(delta).min(last);
+        }
+    }
+
+    /// History entries matching `query`, best score first and newest first
+    /// among equals; each command is listed once.
+    fn search_history(&self, query: &str) -> Vec<HistoryMatch> {
+        let mut seen = HashSet::new();
+        let mut scored: Vec<(i64, HistoryMatch)> = self
+            .history
+            .iter()
+            .enumerate()
+            .rev()
+            .filter(|(_, cmd)| seen.insert(cmd.as_str()))
+            .filter_map(|(entry, cmd)| {
+                let found = fuzzy::score(query, cmd)?;
+                Some((
+                    found.score,
+                    HistoryMatch {
+                        entry,
+                        positions: found.positions,
+                    },
+                ))
+            })
+            .collect();
+        // stable, so ties stay newest first
+        scored.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+        scored.into_iter().map(|(_, found)| found).collect()
+    }
+
+    /// Load the selected search result into the input and close the search.
+    pub fn accept_history_search(&mut self) {
+        let Some(Overlay::HistorySearch {
+            matches, selected, ..
+        }) = self.overlay.take()
+        else {
+            return;
+        };
+        let Some(cmd) = matches
+            .get(selected)
+            .and_then(|found| self.history.get(found.entry))
+        else {
+            return;
+        };
+        self.input = cmd.clone();
+        self.cursor = self.input.len();
+        self.hist_pos = None;
+        self.mark_edited();
+    }
+
+    /// Load the selected run log entry into the input and close the log.
+    pub fn recall_run_log_entry(&mut self) {
+        let Some(Overlay::RunLog { selected }) = self.overlay else {
+            return;
+        };
+        self.overlay = None;
+        // the log is displayed newest first
+        let Some(record) = self.run_log.iter().rev().nth(selected) else {
+            return;
+        };
+        self.input = record.cmd.clone();
+        self.cursor = self.input.len();
+        self.hist_pos = None;
+        self.mark_edited();
+    }
+
+    fn record_run(&mut self, res: &ExecResult) {
+        self.run_log.push_back(RunRecord {
+            cmd: res.cmd.clone(),
+            status: res.status,
+            duration: res.duration,
+        });
+        if self.run_log.len() > RUN_LOG_LIMIT {
+            self.run_log.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::sync::Once;
+
+    /// A session on the default config, its data dir a fresh temporary
+    /// one and no history file, so tests neither read nor write the user's.
+    pub(crate) fn app() -> App {
+        static DATA_DIR: Once = Once::new();
+        DATA_DIR.call_once(|| {
+            let dir = std::env::temp_dir().join(format!("pipetui-test-{}", std::process::id()));
+            std::env::set_var(persistence::DATA_DIR_VAR, dir);
+        });
+        let mut app = App::new();
+        app.history_path = None;
+        app
+    }
+
+    pub(crate) fn type_str(app: &mut App, text: &str) {
+        for ch in text.chars() {
+            app.insert_char(ch);
+        }
+    }
+
+    /// Pretend the last edit was `ago`.
+    fn edited(app: &mut App, ago: Duration) -> Instant {
+        let edited_at = Instant::now() - ago;
+        app.run_state = RunState::Pending { edited_at };
+        edited_at
+    }
+
+    /// A history file of its own for a test named `name`.
+    pub(crate) fn history_file(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("pipetui-test-{}-{name}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn panes_share_history_through_the_file() {
+        let path = history_file("panes");
+        let (mut left, mut right) = (app(), app());
+        left.history_path = Some(path.clone());
+        right.history_path = Some(path);
+        left.append_history("echo left".into());
+        right.append_history("echo right".into());
+        left.refresh_history();
+        assert_eq!(left.history, ["echo left", "echo right"]);
+        assert_eq!(right.history, ["echo left", "echo right"]);
+    }
+
+    /// What the worker reports for `cmd` exiting with `code`.
+    pub(crate) fn finished(cmd: &str, code: i32) -> ExecResult {
+        ExecResult {
+            cmd: cmd.into(),
+            status: Exit::Code(code),
+            stdout: String::new(),
+            stderr: String::new(),
+            duration: Duration::ZERO,
+            usage: None,
+            cancelled: false,
+            spawn_failed: false,
+        }
+    }
+
+    #[test]
+    fn run_queued_behind_another_keeps_its_own_state() {
+        let mut app = app();
+        assert!(app.prepare_run("sleep 1", false));
+        app.begin_run("sleep 1".into());
+        // Enter twice while it runs: the worker keeps only the newest
+        assert!(app.prepare_run("echo one", true));
+        assert!(app.prepare_run("echo two", true));
+        assert_eq!(app.run_state, RunState::Running { manual: false });
+        app.finish_run(finished("sleep 1", 0));
+        assert!(app.history.is_empty(), "the auto-run isn't history");
+        assert_eq!(app.run_state, RunState::Running { manual: true });
+        app.begin_run("echo two".into());
+        app.finish_run(finished("echo two", 0));
+        assert_eq!(app.history, ["echo two"]);
+        assert_eq!(app.run_state, RunState::Finished);
+    }
+
+    #[test]
+    fn cancel_drops_the_queued_run() {
+        let mut app = app();
+        assert!(app.prepare_run("sleep 1", true));
+        app.begin_run("sleep 1".into());
+        assert!(app.prepare_run("echo queued", true));
+        assert!(app.request_cancel());
+        app.finish_run(ExecResult {
+            cancelled: true,
+            ..finished("sleep 1", 137)
+        });
+        assert!(!app.is_running());
+    }
+
+    #[test]
+    fn unterminated_output_is_kept_on_finish() {
+        let mut app = app();
+        assert!(app.prepare_run("printf 'Password: '", true));
+        app.begin_run("printf 'Password: '".into());
+        app.append_stdout_chunk("line\nPassword: ".into());
+        assert_eq!(app.output_lines, ["line"]);
+        assert_eq!(app.stdout_partial, "Password: ");
+        app.finish_run(finished("printf 'Password: '", 0));
+        assert_eq!(app.output_lines, ["line", "Password: "]);
+        assert!(app.stdout_partial.is_empty());
+    }
+
+    /// Run `cmd`, streaming `out` and `err` as the worker would and then
+    /// finishing with the same text in the result.
+    fn run_streamed(app: &mut App, cmd: &str, manual: bool, out: &str, err: &str) {
+        assert!(app.prepare_run(cmd, manual));
+        app.begin_run(cmd.into());
+        app.append_stdout_chunk(out.into());
+        app.append_stderr_chunk(err.into());
+        app.finish_run(ExecResult {
+            stdout: out.into(),
+            stderr: err.into(),
+            ..finished(cmd, 0)
+        });
+    }
+
+    #[test]
+    fn streamed_output_is_not_repeated_on_finish() {
+        let mut app = app();
+        run_streamed(&mut app, "build", true, "a\nb\n", "warn\n");
+        assert_eq!(app.output_lines, ["a", "b"]);
+        assert_eq!(app.error_lines, ["warn"]);
+        assert_eq!(app.raw_stdout, "a\nb\n");
+    }
+
+    #[test]
+    fn staged_output_is_not_repeated_on_finish() {
+        let mut app = app();
+        app.config.quiet_auto_run = true;
+        run_streamed(&mut app, "build", false, "a\nb\n", "warn\n");
+        assert_eq!(app.output_lines, ["a", "b"]);
+        assert_eq!(app.error_lines, ["warn"]);
+    }
+
+    #[test]
+    fn finish_fills_in_a_stream_that_sent_nothing() {
+        let mut app = app();
+        assert!(app.prepare_run("nope", true));
+        app.begin_run("nope".into());
+        app.append_stdout_chunk("partial\n".into());
+        app.finish_run(ExecResult {
+            stdout: "partial\n".into(),
+            stderr: "failed to spawn nope\n".into(),
+            ..finished("nope", 127)
+        });
+        assert_eq!(app.output_lines, ["partial"]);
+        assert_eq!(app.error_lines, ["failed to spawn nope"]);
+    }
+
+    /// A session whose stdout pane holds `stdout` and stderr pane `stderr`.
+    fn with_output(stdout: &str, stderr: &str) -> App {
+        let mut app = app();
+        run_streamed(&mut app, "cat", true, stdout, stderr);
+        app
+    }
+
+    #[test]
+    fn scrolling_past_the_end_follows_again() {
+        let mut app = with_output("1\n2\n3\n4\n5\n", "");
+        app.stdout_pane.shown.set(2);
+        app.scroll(Pane::Stdout, -1);
+        assert_eq!(app.stdout_pane.top, Some(1));
+        app.stdout_pane.shown.set(1);
+        app.scroll(Pane::Stdout, 2);
+        assert_eq!(app.stdout_pane.top, Some(3));
+        app.stdout_pane.shown.set(3);
+        app.scroll(Pane::Stdout, 10);
+        assert_eq!(app.stdout_pane.top, None);
+    }
+
+    #[test]
+    fn sideways_scroll_stops_at_the_widest_line() {
+        let mut app = with_output("short\na much longer line\n", "");
+        app.scroll_sideways(Pane::Stdout, 5);
+        assert_eq!(app.stdout_pane.left, 0, "wrapping panes don't scroll");
+        app.toggle_wrap(Pane::Stdout);
+        app.scroll_sideways(Pane::Stdout, 5);
+        assert_eq!(app.stdout_pane.left, 5);
+        app.scroll_sideways(Pane::Stdout, 100);
+        assert_eq!(app.stdout_pane.left, "a much longer line".len() - 1);
+        app.scroll_sideways(Pane::Stdout, -100);
+        assert_eq!(app.stdout_pane.left, 0);
+    }
+
+    #[test]
+    fn toggling_wrap_resets_the_sideways_scroll() {
+        let mut app = with_output("a much longer line\n", "");
+        app.toggle_wrap(Pane::Stdout);
+        app.scroll_sideways(Pane::Stdout, 4);
+        app.toggle_wrap(Pane::Stdout);
+        assert_eq!(app.stdout_pane.left, 0);
+        app.toggle_wrap(Pane::Stdout);
+        assert_eq!(app.stdout_pane.left, 0);
+    }
+
+    #[test]
+    fn panes_scroll_independently() {
+        let mut app = with_output("1\n2\n3\nthe widest stdout line\n", "a\nb\nc\nd\n");
+        app.toggle_wrap(Pane::Stdout);
+        app.scroll_sideways(Pane::Stdout, 3);
+        app.stdout_pane.shown.set(3);
+        app.scroll(Pane::Stdout, -2);
+        assert_eq!((app.stdout_pane.top, app.stdout_pane.left), (Some(1), 3));
+        assert_eq!((app.stderr_pane.top, app.stderr_pane.left), (None, 0));
+        assert!(app.stderr_pane.wrap && !app.stdout_pane.wrap);
+        app.scroll_to_top(Pane::Stderr);
+        assert_eq!(app.stderr_pane.top, Some(0));
+        assert_eq!(app.stdout_pane.top, Some(1));
+    }
+
+    #[test]
+    fn resumed_history_search_keeps_its_prefix() {
+        let mut app = app();
+        app.config.history_resume = true;
+        app.history = ["git status", "ls", "git log"].map(String::from).to_vec();
+        type_str(&mut app, "git");
+        app.history_prev();
+        assert_eq!(app.input, "git log");
+        type_str(&mut app, " -p");
+        app.history_prev();
+        assert_eq!(
+            (app.input.as_str(), app.hist_prefix.as_str()),
+            ("git log", "git")
+        );
+        app.history_prev();
+        assert_eq!(app.input, "git status");
+        app.history_next();
+        app.history_next();
+        assert_eq!(app.input, "git");
+    }
+
+    #[test]
+    fn history_search_starts_over_without_resume() {
+        let mut app = app();
+        app.history = ["git status", "ls", "git log"].map(String::from).to_vec();
+        type_str(&mut app, "git");
+        app.history_prev();
+        app.history_prev();
+        assert_eq!(app.input, "git status");
+        type_str(&mut app, " -s");
+        app.history_prev();
+        assert_eq!(app.input, "git status -s", "no entry starts with the edit");
+        app.clear_input();
+        app.history_prev();
+        assert_eq!(app.input, "git log");
+    }
+
+    /// Run `cmd` writing only `out` to stdout, or nothing at all.
+    fn run_stdout_only(app: &mut App, cmd: &str, manual: bool, out: &str) {
+        assert!(app.prepare_run(cmd, manual));
+        app.begin_run(cmd.into());
+        if !out.is_empty() {
+            app.append_stdout_chunk(out.into());
+        }
+        app.finish_run(ExecResult {
+            stdout: out.into(),
+            ..finished(cmd, 0)
+        });
+    }
+
+    #[test]
+    fn a_run_without_stderr_clears_the_previous_stderr() {
+        for out in ["ok\n", ""] {
+            let mut app = app();
+            run_streamed(&mut app, "make", true, "", "warning\n");
+            assert_eq!(app.error_lines, ["warning"]);
+            run_stdout_only(&mut app, "make -s", true, out);
+            assert!(app.error_lines.is_empty(), "{out:?}: {:?}", app.error_lines);
+        }
+    }
+
+    #[test]
+    fn kept_stderr_goes_with_the_first_output_of_the_next_run() {
+        let mut app = app();
+        app.config.keep_output_until_new = true;
+        run_streamed(&mut app, "make", true, "", "warning\n");
+        assert!(app.prepare_run("make -s", true));
+        app.begin_run("make -s".into());
+        assert_eq!(app.error_lines, ["warning"], "kept while nothing arrived");
+        app.append_stdout_chunk("ok\n".into());
+        assert!(app.error_lines.is_empty());
+        app.finish_run(finished("make -s", 0));
+        assert_eq!(app.output_lines, ["ok"]);
+        assert!(app.error_lines.is_empty());
+
+        // a run with no output at all clears it when it finishes
+        run_streamed(&mut app, "make", true, "", "warning\n");
+        run_stdout_only(&mut app, "true", true, "");
+        assert!(app.error_lines.is_empty());
+    }
+
+    #[test]
+    fn quiet_auto_run_without_stderr_clears_the_previous_stderr() {
+        let mut app = app();
+        app.config.quiet_auto_run = true;
+        run_streamed(&mut app, "make", false, "", "warning\n");
+        assert_eq!(app.error_lines, ["warning"]);
+        run_stdout_only(&mut app, "make -s", false, "ok\n");
+        assert_eq!(app.output_lines, ["ok"]);
+        assert!(app.error_lines.is_empty());
+        run_streamed(&mut app, "make", false, "", "warning\n");
+        run_stdout_only(&mut app, "true", false, "");
+        assert!(app.output_lines.is_empty() && app.error_lines.is_empty());
+    }
+
+    /// Run `cmd` as typed into `app` on a real worker, feeding the worker's
+    /// messages back as the UI loop does. Returns whether it failed to
+    /// start, as the worker reported.
+    fn run_on_worker(app: &mut App, cmd: &str) -> bool {
+        use crate::execution::{spawn_worker, UiMsg, WorkerMsg};
+        let (tx_worker, rx_worker) = crossbeam_channel::unbounded();
+        let (tx_ui, rx_ui) = crossbeam_channel::unbounded();
+        let worker = spawn_worker(rx_worker, tx_ui);
+        assert!(app.prepare_run(cmd, true));
+        tx_worker.send(WorkerMsg::Run(app.run_spec(cmd))).unwrap();
+        let res = rx_ui
+            .iter()
+            .find_map(|msg| match msg {
+                UiMsg::Started(cmd) => {
+                    app.begin_run(cmd);
+                    None
+                }
+                UiMsg::Finished(res) => Some(res),
+                _ => None,
+            })
+            .unwrap();
+        tx_worker.send(WorkerMsg::Shutdown).unwrap();
+        worker.join().unwrap();
+        let spawn_failed = res.spawn_failed;
+        app.finish_run(res);
+        spawn_failed
+    }
+
+    #[test]
+    fn commands_that_never_started_are_marked_across_restarts() {
+        let cmd = "pipetui-test-no-such-program --flag";
+        let path = history_file("never-ran");
+        let mut app = app();
+        app.history_path = Some(path.clone());
+        app.exec_mode = ExecMode::Direct;
+        assert!(run_on_worker(&mut app, cmd));
+        assert_eq!(app.history, [cmd]);
+        assert!(app.never_ran.contains(cmd));
+        // what a restart loads
+        assert!(persistence::load_never_ran(&path).contains(cmd));
+
+        // once it runs after all, the mark goes
+        app.exec_mode = ExecMode::Shell;
+        app.finish_run(finished(cmd, 127));
+        assert!(!app.never_ran.contains(cmd));
+        assert!(persistence::load_never_ran(&path).is_empty());
+    }
+
+    #[test]
+    fn commands_that_never_started_can_be_left_out_of_history() {
+        let mut app = app();
+        app.config.history_spawn_failures = false;
+        app.exec_mode = ExecMode::Direct;
+        assert!(run_on_worker(&mut app, "pipetui-test-no-such-program"));
+        assert!(app.history.is_empty());
+        assert!(!app.error_lines.is_empty(), "the spawn error is shown");
+    }
+
+    #[test]
+    fn input_limit_refuses_what_does_not_fit() {
+        let mut app = app();
+        app.config.max_input_bytes = 4;
+        type_str(&mut app, "abé");
+        assert_eq!(app.input, "abé");
+        type_str(&mut app, "x");
+        assert_eq!(app.input, "abé");
+        assert!(app.status_line.starts_with("input limit reached"));
+        // a decomposed é: only the accent that doesn't fit is refused
+        app.clear_input();
+        type_str(&mut app, "abe\u{301}");
+        assert_eq!((app.input.as_str(), app.cursor), ("abe", 3));
+    }
+
+    #[test]
+    fn input_limit_applies_to_case_changes() {
+        let mut app = app();
+        app.config.max_input_bytes = 3;
+        type_str(&mut app, "aŉ");
+        app.move_cursor_home();
+        app.upcase_word();
+        assert_eq!(app.input, "aŉ");
+        assert!(app.status_line.starts_with("input limit reached"));
+        app.config.max_input_bytes = 0;
+        app.upcase_word();
+        assert_eq!((app.input.as_str(), app.cursor), ("AʼN", 4));
+    }
+
+    #[test]
+    fn input_limit_applies_to_inserted_words() {
+        let mut app = app();
+        app.history = vec!["cat /etc/hostname".into(), "ls /tmp".into()];
+        app.config.max_input_bytes = 8;
+        type_str(&mut app, "cd ");
+        app.insert_last_arg();
+        assert_eq!(app.input, "cd /tmp");
+        // the older, longer argument doesn't fit in place of `/tmp`
+        app.insert_last_arg();
+        assert_eq!(app.input, "cd /tmp");
+        assert!(app.status_line.starts_with("input limit reached"));
+        assert_eq!(app.input.len(), app.cursor);
+
+        let dir = std::env::temp_dir().join(format!("pipetui-test-{}-limit", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a-long-file-name.txt"), "").unwrap();
+        app.config.cwd = Some(dir.clone());
+        app.clear_input();
+        type_str(&mut app, "cat a");
+        app.complete_path();
+        assert_eq!(app.input, "cat a");
+        app.config.max_input_bytes = 0;
+        app.complete_path();
+        assert_eq!(app.input, "cat a-long-file-name.txt ");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn failed_history_save_keeps_the_run_result() {
+        let mut app = app();
+        app.history_path = Some(history_file("missing-dir").join("history.json"));
+        assert!(app.prepare_run("false", true));
+        app.begin_run("false".into());
+        app.finish_run(finished("false", 1));
+        let result = format!("{}  ", Exit::Code(1));
+        assert!(app.status_line.starts_with(&result), "{}", app.status_line);
+        assert!(
+            app.status_line.contains("  history not saved: "),
+            "{}",
+            app.status_line
+        );
+    }
+
+    #[test]
+    fn small_peak_memory_is_not_rounded_to_zero() {
+        let mut app = app();
+        assert!(app.prepare_run("true", true));
+        app.begin_run("true".into());
+        app.finish_run(ExecResult {
+            usage: Some(execution::ResourceUsage {
+                cpu_time: Duration::ZERO,
+                peak_rss_bytes: 600 * 1024,
+            }),
+            ..finished("true", 0)
+        });
+        assert!(
+            app.status_line.contains("  peak 600.0 KB"),
+            "{}",
+            app.status_line
+        );
+    }
+
+    #[test]
+    fn failure_backoff_is_capped() {
+        let mut app = app();
+        type_str(&mut app, "false");
+        app.failure_streak = Some(("false".into(), u32::MAX));
+        let cap = AUTO_RUN_DEBOUNCE * 2u32.pow(AUTO_RUN_FAILURE_LIMIT);
+        assert_eq!(app.auto_run_delay("false"), cap);
+        app.finish_run(finished("false", 1));
+        assert_eq!(app.failure_streak, Some(("false".into(), u32::MAX)));
+    }
+
+    #[test]
+    fn editing_the_input_resets_the_failure_streak() {
+        let mut app = app();
+        type_str(&mut app, "fals");
+        app.failure_streak = Some(("fals".into(), AUTO_RUN_FAILURE_LIMIT));
+        assert!(app.auto_run_paused("fals"));
+        type_str(&mut app, "e");
+        assert_eq!(app.failure_streak, None);
+        assert_eq!(app.auto_run_delay("false"), AUTO_RUN_DEBOUNCE);
+    }
+
+    #[test]
+    fn auto_run_waits_for_the_debounce() {
+        let mut app = app();
+        type_str(&mut app, "echo hi");
+        assert_eq!(app.auto_run_cmd(), None);
+        edited(&mut app, AUTO_RUN_DEBOUNCE);
+        assert_eq!(app.auto_run_cmd().as_deref(), Some("echo hi"));
+    }
+
+    #[test]
+    fn auto_run_skips_what_already_ran() {
+        let mut app = app();
+        type_str(&mut app, "echo hi");
+        app.last_run_cmd = Some("echo hi".into());
+        edited(&mut app, AUTO_RUN_DEBOUNCE);
+        assert_eq!(app.auto_run_cmd(), None);
+    }
+
+    #[test]
+    fn auto_run_skips_blank_and_pseudo_commands() {
+        for input in ["   ", ":watch 2", ":clear-history", ":wat", ":clear-hi"] {
+            let mut app = app();
+            type_str(&mut app, input);
+            edited(&mut app, AUTO_RUN_DEBOUNCE);
+            assert_eq!(app.auto_run_cmd(), None, "{input:?}");
+        }
+    }
+
+    #[test]
+    fn pseudo_command_names_end_at_a_word_boundary() {
+        for input in [":pipefoo", ":cutx", ":watch2"] {
+            let mut app = app();
+            assert!(!is_pseudo_command(input), "{input:?}");
+            assert!(!app.run_pseudo_command(input), "{input:?}");
+            assert!(app.column_cut.is_none() && app.watch.is_none(), "{input:?}");
+        }
+        let mut app = app();
+        assert!(is_pseudo_command(":cut\t2"));
+        assert!(app.run_pseudo_command(":cut\t2"));
+        assert!(app.column_cut.is_some());
+    }
+
+    #[test]
+    fn wakeup_is_due_when_the_debounce_ends() {
+        let mut app = app();
+        assert_eq!(app.next_wakeup(), None);
+        type_str(&mut app, "echo hi");
+        let edited_at = edited(&mut app, Duration::from_millis(100));
+        assert_eq!(app.next_wakeup(), Some(edited_at + AUTO_RUN_DEBOUNCE));
+    }
+
+    #[test]
+    fn wakeup_backs_off_after_failures() {
+        let mut app = app();
+        type_str(&mut app, "false");
+        app.failure_streak = Some(("false".into(), 2));
+        let edited_at = edited(&mut app, Duration::ZERO);
+        assert_eq!(app.next_wakeup(), Some(edited_at + AUTO_RUN_DEBOUNCE * 4));
+        edited(&mut app, AUTO_RUN_DEBOUNCE * 2);
+        assert_eq!(app.auto_run_cmd(), None);
+        edited(&mut app, AUTO_RUN_DEBOUNCE * 4);
+        assert_eq!(app.auto_run_cmd().as_deref(), Some("false"));
+    }
 }
@@ -0,0 +1,275 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use crossterm::cursor::{MoveTo, RestorePosition, SavePosition};
+use crossterm::queue;
+use crossterm::style::{
+    Attribute, Print, ResetColor, SetAttribute, SetBackgroundColor, SetForegroundColor,
+};
+use ratatui::buffer::{Buffer, Cell};
+use ratatui::layout::Rect;
+use ratatui::style::Modifier;
+use regex::Regex;
+use unicode_width::UnicodeWidthStr;
+
+/// URLs, and paths with at least one `/` (optionally with a `:line` or
+/// `:line:col` suffix, as compilers print them). Bare file names are left
+/// alone: too many ordinary words look like them.
+fn link_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        Regex::new(r"https?://[^\s<>`'\x22]+|~?[\w.+-]*/(?:[\w.+-]+/)*[\w.+-]+(?::\d+){0,2}")
+            .expect("link pattern is valid")
+    })
+}
+
+/// A run of cells on screen showing something to link to `target`.
+#[derive(Debug, PartialEq, Eq)]
+struct Link {
+    x: u16,
+    y: u16,
+    width: u16,
+    target: String,
+}
+
+/// A link in the text of a row: its byte range and target.
+#[derive(Clone, Debug)]
+struct RowLink {
+    start: usize,
+    len: usize,
+    target: String,
+}
+
+/// The links of the rows on screen, kept from frame to frame by the rows'
+/// text: a redraw only looks for links (and checks paths on disk) in rows
+/// whose text changed. A file created meanwhile is linked once its row
+/// is drawn with different text.
+#[derive(Debug, Default)]
+pub struct Links {
+    cwd: PathBuf,
+    rows: HashMap<String, Vec<RowLink>>,
+}
+
+impl Links {
+    /// The links in `areas` of a drawn frame: URLs, and paths that exist,
+    /// relative ones resolved against `cwd`.
+    fn find(&mut self, buf: &Buffer, areas: &[Rect], cwd: &Path) -> Vec<Link> {
+        if self.cwd != cwd {
+            self.cwd = cwd.to_path_buf();
+            self.rows.clear();
+        }
+        let mut known = std::mem::take(&mut self.rows);
+        let mut links = Vec::new();
+        for area in areas.iter().map(|area| area.intersection(buf.area)) {
+            for y in area.top()..area.bottom() {
+                let (text, columns) = row_text(buf, area, y);
+                let found = match known.remove(&text) {
+                    Some(found) => found,
+                    None => self
+                        .rows
+                        .get(&text)
+                        .cloned()
+                        .unwrap_or_else(|| row_links(&text, cwd)),
+                };
+                let column = |offset: usize| {
+                    columns
+                        .iter()
+                        .rev()
+                        .find(|(start, _)| *start <= offset)
+                        .map_or(area.left(), |&(_, x)| x)
+                };
+                for link in &found {
+                    let x = column(link.start);
+                    let end = column(link.start + link.len - 1) + 1;
+                    links.push(Link {
+                        x,
+                        y,
+                        width: end - x,
+                        target: link.target.clone(),
+                    });
+                }
+                self.rows.insert(text, found);
+            }
+        }
+        links
+    }
+
+    /// Turn the links found in `areas` of the frame just drawn into OSC 8
+    /// hyperlinks by writing their cells again wrapped in the escape, in
+    /// the style they were drawn in. They bypass ratatui's buffer, whose
+    /// width accounting can't hold escapes, so this has to follow every
+    /// redraw; the cursor is left where it was.
+    pub fn write(
+        &mut self,
+        out: &mut impl Write,
+        buf: &Buffer,
+        areas: &[Rect],
+        cwd: &Path,
+    ) -> io::Result<()> {
+        let links = self.find(buf, areas, cwd);
+        if links.is_empty() {
+            return Ok(());
+        }
+        queue!(out, SavePosition)?;
+        for link in &links {
+            queue!(
+                out,
+                MoveTo(link.x, link.y),
+                Print(format!("\x1b]8;;{}\x1b\\", link.target))
+            )?;
+            let mut x = link.x;
+            let mut style = None;
+            while x < link.x + link.width {
+                let cell = buf.get(x, link.y);
+                if style != Some(cell.style()) {
+                    style = Some(cell.style());
+                    queue_style(out, cell)?;
+                }
+                queue!(out, Print(cell.symbol()))?;
+                x += cell.symbol().width().max(1) as u16;
+            }
+            queue!(
+                out,
+                Print("\x1b]8;;\x1b\\"),
+                SetAttribute(Attribute::Reset),
+                ResetColor
+            )?;
+        }
+        queue!(out, RestorePosition)?;
+        out.flush()
+    }
+}
+
+/// Set the colors and attributes `cell` was drawn with, from a clean
+/// slate so nothing of the previous cell carries over.
+fn queue_style(out: &mut impl Write, cell: &Cell) -> io::Result<()> {
+    const ATTRIBUTES: [(Modifier, Attribute); 9] = [
+        (Modifier::BOLD, Attribute::Bold),
+        (Modifier::DIM, Attribute::Dim),
+        (Modifier::ITALIC, Attribute::Italic),
+        (Modifier::UNDERLINED, Attribute::Underlined),
+        (Modifier::SLOW_BLINK, Attribute::SlowBlink),
+        (Modifier::RAPID_BLINK, Attribute::RapidBlink),
+        (Modifier::REVERSED, Attribute::Reverse),
+        (Modifier::HIDDEN, Attribute::Hidden),
+        (Modifier::CROSSED_OUT, Attribute::CrossedOut),
+    ];
+    queue!(
+        out,
+        SetAttribute(Attribute::Reset),
+        SetForegroundColor(cell.fg.into()),
+        SetBackgroundColor(cell.bg.into())
+    )?;
+    for (modifier, attribute) in ATTRIBUTES {
+        if cell.modifier.contains(modifier) {
+            queue!(out, SetAttribute(attribute))?;
+        }
+    }
+    Ok(())
+}
+
+/// The text of row `y` of `area`, with the byte offset and column at which
+/// each cell's symbol starts.
+fn row_text(buf: &Buffer, area: Rect, y: u16) -> (String, Vec<(usize, u16)>) {
+    let mut text = String::new();
+    let mut columns = Vec::new();
+    let mut x = area.left();
+    while x < area.right() {
+        let symbol = buf.get(x, y).symbol();
+        columns.push((text.len(), x));
+        text.push_str(symbol);
+        // the cells a wide character covers hold nothing of it
+        x += symbol.width().max(1) as u16;
+    }
+    (text, columns)
+}
+
+/// The links in the text of a row.
+fn row_links(text: &str, cwd: &Path) -> Vec<RowLink> {
+    link_regex()
+        .find_iter(text)
+        .filter_map(|found| {
+            let shown = found.as_str().trim_end_matches(['.', ',', ';', ':', ')']);
+            Some(RowLink {
+                start: found.start(),
+                len: shown.len(),
+                target: link_target(shown, cwd)?,
+            })
+        })
+        .collect()
+}
+
+/// Where `shown` should link to: URLs as they are, paths as a `file://`
+/// URL when the file exists.
+fn link_target(shown: &str, cwd: &Path) -> Option<String> {
+    if shown.starts_with("http://") || shown.starts_with("https://") {
+        return Some(shown.to_string());
+    }
+    // `src/main.rs:12:5` opens the file
+    let mut path = shown;
+    for _ in 0..2 {
+        if let Some((head, tail)) = path.rsplit_once(':') {
+            if !tail.is_empty() && tail.bytes().all(|b| b.is_ascii_digit()) {
+                path = head;
+            }
+        }
+    }
+    let path: PathBuf = match path.strip_prefix("~/") {
+        Some(rest) => dirs::home_dir()?.join(rest),
+        None => cwd.join(path),
+    };
+    let path = path.canonicalize().ok()?;
+    Some(format!("file://{}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::style::{Color, Style};
+
+    fn buffer(rows: &[&str], style: Style) -> Buffer {
+        let mut buf = Buffer::empty(Rect::new(0, 0, 40, rows.len() as u16));
+        for (y, row) in rows.iter().enumerate() {
+            buf.set_string(0, y as u16, row, style);
+        }
+        buf
+    }
+
+    #[test]
+    fn links_are_rewritten_in_their_full_style() {
+        let style = Style::default()
+            .fg(Color::Red)
+            .bg(Color::Blue)
+            .add_modifier(Modifier::BOLD | Modifier::REVERSED);
+        let buf = buffer(&["see https://example.com/x."], style);
+        let mut out = Vec::new();
+        Links::default()
+            .write(&mut out, &buf, &[buf.area], Path::new("/"))
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("\x1b]8;;https://example.com/x\x1b\\"));
+        for escape in ["\x1b[1m", "\x1b[7m", "\x1b[38;5;1m", "\x1b[48;5;4m"] {
+            assert_eq!(out.matches(escape).count(), 1, "{escape:?} in {out:?}");
+        }
+        assert!(out.contains("https://example.com/x\x1b]8;;\x1b\\"));
+    }
+
+    #[test]
+    fn unchanged_rows_are_not_checked_again() {
+        let dir = std::env::temp_dir().join(format!("pipetui-test-{}-links", std::process::id()));
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        std::fs::write(dir.join("src/a.rs"), "").unwrap();
+        let mut links = Links::default();
+        let found = |links: &mut Links, rows: &[&str]| {
+            let buf = buffer(rows, Style::default());
+            links.find(&buf, &[buf.area], &dir).len()
+        };
+        assert_eq!(found(&mut links, &["error at src/a.rs:3:1"]), 1);
+        std::fs::remove_file(dir.join("src/a.rs")).unwrap();
+        assert_eq!(found(&mut links, &["error at src/a.rs:3:1"]), 1);
+        assert_eq!(found(&mut links, &["error at src/a.rs:4:1"]), 0);
+        assert_eq!(links.rows.len(), 1, "rows no longer shown are forgotten");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -1,55 +1,238 @@
+mod action;
 mod cli;
+mod columns;
+mod config;
+mod error;
 mod parser;
 mod execution;
+mod fuzzy;
+mod headless;
+mod highlight;
 mod history;
+mod hyperlink;
+mod notes;
 mod persistence;
+mod table;
 mod utility;
+mod workspace;
 
-use anyhow::Result;
-use crossbeam_channel::unbounded;
-use ratatui::backend::CrosstermBackend;
+use std::io::{self, IsTerminal, Read};
+use std::path::PathBuf;
+use std::time::Duration;
+use anyhow::{anyhow, bail, Context, Result};
 
-use execution::{spawn_worker, UiMsg, WorkerMsg};
-use history::App;
+pub use error::Error;
+use workspace::Workspace;
 
+/// Longest the main loop sleeps in one go when nothing is due, as a
+/// safety net for anything that doesn't announce itself.
+const MAX_IDLE_WAIT: Duration = Duration::from_secs(1);
+
+/// Command-line options.
+#[derive(Default)]
+struct Args {
+    /// Run this command without the TUI and exit with its status.
+    exec: Option<String>,
+    /// Wrap width for headless output; overrides `COLUMNS`.
+    width: Option<u16>,
+    /// Page headless stdout with a `-- more --` prompt when it's a
+    /// terminal.
+    page: bool,
+    /// Load this file's contents into the input box on startup.
+    file: Option<PathBuf>,
+    /// Load this command into the input box and run it once on startup.
+    once: Option<String>,
+    /// Run the input loaded from `--file` or stdin once on startup.
+    run: bool,
+}
+
+fn parse_args() -> Result<Args> {
+    let mut args = Args::default();
+    let mut iter = std::env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--exec" => {
+                let cmd = iter
+                    .next()
+                    .ok_or_else(|| anyhow!("--exec needs a command"))?;
+                args.exec = Some(cmd);
+            }
+            "--width" => {
+                let value = iter
+                    .next()
+                    .ok_or_else(|| anyhow!("--width needs a number"))?;
+                let width = value
+                    .parse::<u16>()
+                    .ok()
+                    .filter(|&width| width > 0)
+                    .ok_or_else(|| anyhow!("invalid --width '{value}'"))?;
+                args.width = Some(width);
+            }
+            "--file" => {
+                let path = iter.next().ok_or_else(|| anyhow!("--file needs a path"))?;
+                args.file = Some(PathBuf::from(path));
+            }
+            "--once" => {
+                let cmd = iter
+                    .next()
+                    .ok_or_else(|| anyhow!("--once needs a command"))?;
+                args.once = Some(cmd);
+            }
+            "--run" => args.run = true,
+            "--page" => args.page = true,
+            other => bail!("unknown argument '{other}'"),
+        }
+    }
+    if args.file.is_some() && args.once.is_some() {
+        bail!("--file and --once can't be combined");
+    }
+    if args.run && args.once.is_some() {
+        bail!("--once already runs its command; --run is for --file or piped input");
+    }
+    Ok(args)
+}
 
 fn main() -> Result<()> {
-    // channels
-    let (tx_worker, rx_worker) = unbounded::<WorkerMsg>();
-    let (tx_ui, rx_ui) = unbounded::<UiMsg>();
-    spawn_worker(rx_worker, tx_ui);
+    let args = parse_args()?;
+    utility::init_logging();
+    if let Some(cmd) = &args.exec {
+        let width = args.width.unwrap_or_else(utility::terminal_width);
+        // piped output is for another program: never stop for a prompt
+        let page_height = io::stdout()
+            .is_terminal()
+            .then(|| crossterm::terminal::size().map_or(24, |(_, rows)| rows))
+            .filter(|_| args.page);
+        let status = headless::run(cmd, width, page_height)?;
+        // statuses outside 0..=255 (spawn failures) can't be exit codes
+        std::process::exit(if (0..=255).contains(&status) {
+            status
+        } else {
+            1
+        });
+    }
 
-    let mut terminal = utility::setup_terminal()?;
+    // read before touching the terminal so a bad path is a plain error;
+    // piped stdin (`echo 'ls | wc -l' | pipetui`) is read to the end here,
+    // and the terminal driver then reads keys from the controlling tty
+    let initial_input = match &args.file {
+        Some(path) => {
+            let text = std::fs::read_to_string(path)
+                .with_context(|| format!("cannot read --file '{}'", path.display()))?;
+            Some(text.trim_end_matches(['\n', '\r']).to_string())
+        }
+        None if args.once.is_some() => args.once.clone(),
+        None if !io::stdin().is_terminal() => {
+            let mut text = String::new();
+            io::stdin()
+                .read_to_string(&mut text)
+                .context("cannot read the pipeline from stdin")?;
+            Some(text.trim_end_matches(['\n', '\r']).to_string())
+                .filter(|input| !input.trim().is_empty())
+        }
+        None => None,
+    };
 
-    let mut app = App::new();
+    let mut workspace = Workspace::new();
+    workspace
+        .active_mut()
+        .app
+        .apply_ui_state(persistence::load_state());
+    let has_initial_input = initial_input.is_some();
+    if let Some(input) = initial_input {
+        let app = &mut workspace.active_mut().app;
+        // a script is left for the shell to parse, as written
+        if input.contains('\n') && !app.multiline {
+            app.toggle_multiline();
+        }
+        app.load_input(input);
+    }
+    if let Some(draft) = persistence::load_draft() {
+        workspace.active_mut().app.offer_draft(draft);
+    }
+    let alternate_screen = workspace.active().app.config.alternate_screen;
+
+    let mut terminal = utility::setup_terminal(alternate_screen)?;
+    let mut run_once = args.once.is_some() || (args.run && has_initial_input);
+    let mut dirty = true;
+    let mut links = hyperlink::Links::default();
 
     loop {
-        terminal.draw(|f| cli::render_ui::<CrosstermBackend<std::io::Stdout>>(f, &app))?;
-
-        // check for worker results without blocking UI
-        while let Ok(msg) = rx_ui.try_recv() {
-            match msg {
-                UiMsg::Started(cmd) => app.begin_run(cmd),
-                UiMsg::StdoutChunk(chunk) => app.append_stdout_chunk(chunk),
-                UiMsg::StderrChunk(chunk) => app.append_stderr_chunk(chunk),
-                UiMsg::Finished(res) => app.finish_run(res),
+        // only redraw what changed; a flash frame needs one more to undo it
+        if dirty {
+            let flashed = workspace.sessions.iter().any(|s| s.app.flash.get());
+            let mut output_areas = Vec::new();
+            let frame = terminal.draw(|f| output_areas = cli::render_ui(f, &workspace))?;
+            let config = &workspace.active().app.config;
+            if config.hyperlinks {
+                let cwd = config
+                    .cwd
+                    .clone()
+                    .or_else(|| std::env::current_dir().ok())
+                    .unwrap_or_default();
+                // best effort: a terminal without OSC 8 just shows the text
+                let _ = links.write(&mut io::stdout(), frame.buffer, &output_areas, &cwd);
             }
+            dirty = flashed;
+        }
+
+        // `--once`: a manual run of the prefilled input, after the first frame
+        if std::mem::take(&mut run_once) {
+            let session = workspace.active_mut();
+            cli::run_input(&mut session.app, &session.tx_worker);
+            dirty = true;
         }
 
-        if app.should_auto_run() {
-            let cmd = app.input.clone();
-            if app.prepare_run(&cmd, false) {
-                tx_worker.send(WorkerMsg::Run(cmd)).ok();
+        // apply worker results and start due runs without blocking the UI
+        dirty |= workspace.pump();
+
+        // wait for input until something else is due
+        let timeout = if dirty {
+            Duration::ZERO
+        } else {
+            workspace.idle_timeout(MAX_IDLE_WAIT)
+        };
+        match cli::handle_input(&mut workspace, timeout)? {
+            cli::InputOutcome::Quit => break,
+            cli::InputOutcome::Handled => dirty = true,
+            // a timeout means a tick or deadline came up
+            cli::InputOutcome::Idle => {
+                dirty |= workspace.sessions.iter().any(|s| s.app.is_running())
             }
         }
 
-        // handle input
-        if !cli::handle_input(&mut app, &tx_worker)? {
-            break;
+        let app = &mut workspace.active_mut().app;
+        if std::mem::take(&mut app.clear_screen_request) {
+            terminal.clear()?;
+            dirty = true;
+        }
+        if let Some((viewer, text)) = app.take_view_request() {
+            // hand the terminal to the viewer, then redraw from scratch
+            utility::restore_terminal(alternate_screen)?;
+            let shell = app.config.shell.as_deref();
+            let result = match viewer {
+                history::Viewer::Pager => utility::page_text(&text, shell),
+                history::Viewer::Editor => utility::edit_text(&text, shell),
+            };
+            terminal = utility::setup_terminal(alternate_screen)?;
+            dirty = true;
+            if let Err(e) = result {
+                app.status_line = format!("{e:#}");
+            }
         }
     }
 
+    // stop the workers (killing any running command) before handing the
+    // terminal back
+    let app = workspace.shutdown();
+    persistence::clear_draft();
+    persistence::save_state(&app.ui_state());
+
     // restore terminal
-    utility::restore_terminal()?;
+    utility::restore_terminal(alternate_screen)?;
+    if app.config.print_output_on_exit {
+        for line in &app.output_lines {
+            println!("{line}");
+        }
+    }
     Ok(())
 }
@@ -0,0 +1,148 @@
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+use crate::parser::{column_offset, cursor_column, next_grapheme_boundary, prev_grapheme_boundary};
+use crate::persistence;
+
+/// How long notes are left alone before they are saved.
+pub const NOTES_SAVE_IDLE: Duration = Duration::from_secs(2);
+
+/// Free text kept in `notes.txt` next to the history, edited in a pane of
+/// its own (`Alt+N`) and never run: for what a pipeline does, or TODOs.
+pub struct Notes {
+    pub text: String,
+    /// Byte offset into `text`, always on a grapheme boundary.
+    pub cursor: usize,
+    pub visible: bool,
+    /// Horizontal and vertical scroll of the pane, updated while rendering.
+    pub scroll: Cell<(u16, u16)>,
+    /// When unsaved edits were last made.
+    edited_at: Option<Instant>,
+}
+
+impl Notes {
+    pub fn load() -> Self {
+        let text = persistence::load_notes();
+        Notes {
+            cursor: text.len(),
+            text,
+            visible: false,
+            scroll: Cell::new((0, 0)),
+            edited_at: None,
+        }
+    }
+
+    /// When the unsaved edits are due to be saved.
+    pub fn save_due(&self) -> Option<Instant> {
+        self.edited_at.map(|at| at + NOTES_SAVE_IDLE)
+    }
+
+    /// Save once the notes have been left alone for `NOTES_SAVE_IDLE`, or
+    /// right away with `now`.
+    pub fn save_if_idle(&mut self, now: bool) {
+        let due = self
+            .edited_at
+            .is_some_and(|at| now || at.elapsed() >= NOTES_SAVE_IDLE);
+        if due {
+            persistence::save_notes(&self.text);
+            self.edited_at = None;
+        }
+    }
+
+    /// Take on what another pane saved to `notes.txt`, unless there are
+    /// edits here still to save. The cursor stays put if the text didn't
+    /// change and goes to the end if it did.
+    pub fn reload(&mut self) {
+        if self.edited_at.is_some() {
+            return;
+        }
+        let text = persistence::load_notes();
+        if text != self.text {
+            self.cursor = text.len();
+            self.text = text;
+        }
+    }
+
+    fn edited(&mut self) {
+        self.edited_at = Some(Instant::now());
+    }
+
+    pub fn insert(&mut self, ch: char) {
+        self.text.insert(self.cursor, ch);
+        self.cursor += ch.len_utf8();
+        self.edited();
+    }
+
+    pub fn delete_backward(&mut self) {
+        if self.cursor == 0 {
+            return;
+        }
+        let start = prev_grapheme_boundary(&self.text, self.cursor);
+        self.text.drain(start..self.cursor);
+        self.cursor = start;
+        self.edited();
+    }
+
+    pub fn delete_forward(&mut self) {
+        if self.cursor == self.text.len() {
+            return;
+        }
+        let end = next_grapheme_boundary(&self.text, self.cursor);
+        self.text.drain(self.cursor..end);
+        self.edited();
+    }
+
+    pub fn move_left(&mut self) {
+        self.cursor = prev_grapheme_boundary(&self.text, self.cursor);
+    }
+
+    pub fn move_right(&mut self) {
+        self.cursor = next_grapheme_boundary(&self.text, self.cursor);
+    }
+
+    pub fn move_home(&mut self) {
+        self.cursor = self.line_start(self.cursor);
+    }
+
+    pub fn move_end(&mut self) {
+        self.cursor = self.line_end(self.cursor);
+    }
+
+    /// Move the cursor one line up (`delta` -1) or down (1) at the same
+    /// column, staying put on the first/last line.
+    pub fn move_line(&mut self, delta: isize) {
+        let start = self.line_start(self.cursor);
+        let (_, col) = self.cursor_position();
+        let target = if delta < 0 {
+            if start == 0 {
+                return;
+            }
+            self.line_start(start - 1)
+        } else {
+            let end = self.line_end(self.cursor);
+            if end == self.text.len() {
+                return;
+            }
+            end + 1
+        };
+        let line = &self.text[target..self.line_end(target)];
+        self.cursor = target + column_offset(line, col);
+    }
+
+    /// Row and display column of the cursor.
+    pub fn cursor_position(&self) -> (usize, usize) {
+        let start = self.line_start(self.cursor);
+        let row = self.text[..start].matches('\n').count();
+        let line = &self.text[start..self.cursor];
+        (row, cursor_column(line, line.len()))
+    }
+
+    fn line_start(&self, idx: usize) -> usize {
+        self.text[..idx].rfind('\n').map_or(0, |nl| nl + 1)
+    }
+
+    fn line_end(&self, idx: usize) -> usize {
+        self.text[idx..]
+            .find('\n')
+            .map_or(self.text.len(), |nl| idx + nl)
+    }
+}
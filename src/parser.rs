@@ -1,4 +1,8 @@
+use std::borrow::Cow;
 use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+use crate::error::Result;
+use crate::Error;
 
 pub fn prev_grapheme_boundary(text: &str, cursor: usize) -> usize {
     if cursor == 0 {
@@ -25,3 +29,516 @@ pub fn next_grapheme_boundary(text: &str, cursor: usize) -> usize {
     }
     text.len()
 }
+
+/// Display column of `cursor` in `text`: the summed widths of the grapheme
+/// clusters before it. The renderer draws a cluster at a time, so this is
+/// where the caret belongs; the width of the whole string can differ where
+/// unicode-width joins clusters (Arabic lam-alef counts as one column).
+pub fn cursor_column(text: &str, cursor: usize) -> usize {
+    text[..cursor]
+        .graphemes(true)
+        .map(UnicodeWidthStr::width)
+        .sum()
+}
+
+/// Byte offset in the single line `line` of the last cluster boundary at
+/// or before display column `column`: where the caret lands when moving
+/// to another line at the same column.
+pub fn column_offset(line: &str, column: usize) -> usize {
+    let mut width = 0;
+    for (idx, cluster) in line.grapheme_indices(true) {
+        width += UnicodeWidthStr::width(cluster);
+        if width > column {
+            return idx;
+        }
+    }
+    line.len()
+}
+
+/// Clusters count as word characters by their base character, so a
+/// combining accent stays with the letter it modifies.
+fn is_word_cluster(cluster: &str) -> bool {
+    cluster
+        .chars()
+        .next()
+        .is_some_and(|ch| ch.is_alphanumeric() || ch == '_')
+}
+
+/// Start of the word before `cursor` (readline `backward-word`): skip
+/// separators, then the word itself.
+pub fn prev_word_boundary(text: &str, cursor: usize) -> usize {
+    let mut clusters = text[..cursor].grapheme_indices(true).rev().peekable();
+    while clusters.next_if(|&(_, g)| !is_word_cluster(g)).is_some() {}
+    let mut start = clusters.peek().map_or(0, |&(idx, g)| idx + g.len());
+    while let Some((idx, _)) = clusters.next_if(|&(_, g)| is_word_cluster(g)) {
+        start = idx;
+    }
+    start
+}
+
+/// End of the word after `cursor` (readline `forward-word`).
+pub fn next_word_boundary(text: &str, cursor: usize) -> usize {
+    let after = &text[cursor..];
+    let mut clusters = after.grapheme_indices(true).peekable();
+    while clusters.next_if(|&(_, g)| !is_word_cluster(g)).is_some() {}
+    while clusters.next_if(|&(_, g)| is_word_cluster(g)).is_some() {}
+    cursor + clusters.peek().map_or(after.len(), |&(idx, _)| idx)
+}
+
+/// `line` without a trailing shell comment: a `#` that starts a word
+/// outside quotes, up to the end. `a#b`, `'#'` and `\#` are kept.
+pub fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut word_start = true;
+    let mut chars = line.char_indices();
+    while let Some((idx, ch)) = chars.next() {
+        match (quote, ch) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('"'), '\\') => {
+                chars.next();
+            }
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(ch),
+            (None, '\\') => {
+                chars.next();
+            }
+            (None, '#') if word_start => return line[..idx].trim_end(),
+            _ => {}
+        }
+        word_start = quote.is_none() && (ch.is_whitespace() || matches!(ch, ';' | '|' | '&' | '('));
+    }
+    line
+}
+
+/// Quote `text` so the host shell passes it through as a single word.
+pub fn shell_quote(text: &str) -> String {
+    #[cfg(target_os = "windows")]
+    return format!("\"{}\"", text.replace('"', "\"\""));
+    #[cfg(not(target_os = "windows"))]
+    return format!("'{}'", text.replace('\'', "'\\''"));
+}
+
+/// `text` as a single shell word: unchanged when it only holds characters
+/// no shell treats specially, otherwise quoted with `shell_quote`.
+pub fn quote_word(text: &str) -> Cow<'_, str> {
+    let plain = |ch: char| ch.is_alphanumeric() || "/._-+,:=@%".contains(ch);
+    if !text.is_empty() && text.chars().all(plain) {
+        Cow::Borrowed(text)
+    } else {
+        Cow::Owned(shell_quote(text))
+    }
+}
+
+/// The shell word ending at `cursor`: its start, and its text with quotes
+/// removed and backslash escapes resolved, so a half-typed `"my fi` or
+/// `my\ fi` both give `my fi`.
+pub fn word_before(text: &str, cursor: usize) -> (usize, String) {
+    let mut start = 0;
+    let mut word = String::new();
+    let mut quote = None;
+    let mut chars = text[..cursor].char_indices();
+    while let Some((idx, ch)) = chars.next() {
+        match (quote, ch) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('"'), '\\') | (None, '\\') => {
+                if let Some((_, escaped)) = chars.next() {
+                    word.push(escaped);
+                }
+            }
+            (Some(_), c) => word.push(c),
+            (None, '\'' | '"') => quote = Some(ch),
+            (None, c) if c.is_whitespace() || ";|&<>()".contains(c) => {
+                start = idx + c.len_utf8();
+                word.clear();
+            }
+            (None, c) => word.push(c),
+        }
+    }
+    (start, word)
+}
+
+/// The last shell word of `text` as written, quotes and escapes included,
+/// so `grep -n 'a b' "my file"` gives `"my file"`. Operators separate
+/// words like whitespace does. `None` when there are no words.
+pub fn last_word(text: &str) -> Option<&str> {
+    let mut word = None;
+    let mut start = None;
+    let mut quote = None;
+    let mut chars = text.char_indices();
+    while let Some((idx, ch)) = chars.next() {
+        match (quote, ch) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('"'), '\\') => {
+                chars.next();
+            }
+            (Some(_), _) => {}
+            (None, c) if c.is_whitespace() || ";|&<>()".contains(c) => {
+                if let Some(begin) = start.take() {
+                    word = Some(&text[begin..idx]);
+                }
+            }
+            (None, c) => {
+                start.get_or_insert(idx);
+                match c {
+                    '\'' | '"' => quote = Some(c),
+                    '\\' => {
+                        chars.next();
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    match start {
+        Some(begin) => Some(&text[begin..]),
+        None => word,
+    }
+}
+
+/// The stages of the pipeline in `cmd`, split at unquoted `|` (but not
+/// `||`) and trimmed. A command without pipes is a single stage.
+pub fn pipeline_stages(cmd: &str) -> Vec<&str> {
+    let mut stages = Vec::new();
+    let mut start = 0;
+    let mut quote = None;
+    let mut chars = cmd.char_indices().peekable();
+    while let Some((idx, ch)) = chars.next() {
+        match (quote, ch) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('"') | None, '\\') => {
+                chars.next();
+            }
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(ch),
+            (None, '|') => {
+                if chars.next_if(|&(_, c)| c == '|').is_some() {
+                    continue;
+                }
+                stages.push(cmd[start..idx].trim());
+                start = idx + 1;
+                // `|&` also pipes stderr
+                if chars.next_if(|&(_, c)| c == '&').is_some() {
+                    start += 1;
+                }
+            }
+            (None, _) => {}
+        }
+    }
+    stages.push(cmd[start..].trim());
+    stages
+}
+
+/// Whether `cmd` clearly isn't finished being typed: a quote, `(` or `{`
+/// is still open, or it ends in a pipe (`|`, `|&`), `&&`, `||` or a
+/// line-continuing backslash. Comments don't count. Auto-run waits for
+/// such input to complete.
+pub fn is_incomplete(cmd: &str) -> bool {
+    let cmd = cmd
+        .lines()
+        .map(strip_comment)
+        .collect::<Vec<_>>()
+        .join("\n");
+    let mut quote = None;
+    let mut depth = 0usize;
+    let mut chars = cmd.chars();
+    while let Some(ch) = chars.next() {
+        match (quote, ch) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('"') | None, '\\') => {
+                if chars.next().is_none() {
+                    return true;
+                }
+            }
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(ch),
+            (None, '(' | '{') => depth += 1,
+            (None, ')' | '}') => depth = depth.saturating_sub(1),
+            (None, _) => {}
+        }
+    }
+    if quote.is_some() || depth > 0 {
+        return true;
+    }
+    let end = cmd.trim_end();
+    end.ends_with('|') || end.ends_with("|&") || end.ends_with("&&")
+}
+
+/// Programs that run the command given in their arguments, so what such
+/// a command runs isn't its first word.
+const RUNS_ANOTHER: [&str; 13] = [
+    "!", "time", "sudo", "doas", "env", "command", "builtin", "exec", "eval", "xargs", "nohup",
+    "nice", "timeout",
+];
+
+/// The simple commands in `cmd`, split at unquoted `|`, `||`, `&&`, `;`,
+/// `&` and newlines and trimmed; `None` when it runs or writes more than
+/// they show: a command substitution (`$(...)`, backquotes), a subshell,
+/// group or process substitution (unquoted `(` or `{`, but not `${var}`),
+/// a command run through another (`sudo rm`, `xargs rm`, `! rm`) or an
+/// output redirection other than to `/dev/null` or another descriptor
+/// (`2>&1`).
+pub fn simple_commands(cmd: &str) -> Option<Vec<&str>> {
+    let mut commands = Vec::new();
+    let mut start = 0;
+    let mut quote = None;
+    let mut chars = cmd.char_indices().peekable();
+    while let Some((idx, ch)) = chars.next() {
+        match (quote, ch) {
+            (Some('\''), '\'') => quote = None,
+            (Some('\''), _) => {}
+            (_, '`') => return None,
+            (_, '$') if chars.peek().is_some_and(|&(_, c)| c == '(') => return None,
+            (_, '$') => {
+                chars.next_if(|&(_, c)| c == '{');
+            }
+            (Some('"'), '"') => quote = None,
+            (_, '\\') => {
+                chars.next();
+            }
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(ch),
+            (None, '(' | '{') => return None,
+            (None, '>') => {
+                chars.next_if(|&(_, c)| c == '>');
+                if chars.next_if(|&(_, c)| c == '&').is_none() {
+                    let rest = cmd[idx..].trim_start_matches('>').trim_start();
+                    let target = rest.split(|c: char| c.is_whitespace() || c == ';' || c == '|');
+                    if target.take(1).ne(["/dev/null"]) {
+                        return None;
+                    }
+                }
+            }
+            (None, '|' | '&' | ';' | '\n') => {
+                commands.push(cmd[start..idx].trim());
+                chars.next_if(|&(_, c)| c == ch || (ch == '|' && c == '&'));
+                start = chars.peek().map_or(cmd.len(), |&(next, _)| next);
+            }
+            (None, _) => {}
+        }
+    }
+    commands.push(cmd[start..].trim());
+    commands.retain(|command| !command.is_empty());
+    let program = |command: &str| {
+        let program = stage_program(command);
+        program.rsplit('/').next().unwrap_or(program).to_string()
+    };
+    if commands
+        .iter()
+        .any(|command| RUNS_ANOTHER.contains(&program(command).as_str()))
+    {
+        return None;
+    }
+    Some(commands)
+}
+
+/// Whether `command` starts with the words of `prefix` (`ls`, `git log`),
+/// after any `NAME=value` assignments and comparing the program by its
+/// file name, so `/bin/ls` counts as `ls` but `lsblk` doesn't.
+pub fn command_matches(command: &str, prefix: &str) -> bool {
+    let mut words = command
+        .split_whitespace()
+        .skip_while(|word| is_assignment(word));
+    let mut wanted = prefix.split_whitespace();
+    let program = |word: &str| word.rsplit('/').next().unwrap_or(word).to_string();
+    match (words.next(), wanted.next()) {
+        (Some(first), Some(want)) if program(first) == program(want) => {}
+        _ => return false,
+    }
+    wanted.all(|want| words.next() == Some(want))
+}
+
+/// The program a pipeline stage runs: its first word after any leading
+/// `NAME=value` assignments.
+pub fn stage_program(stage: &str) -> &str {
+    let mut words = stage.split_whitespace();
+    let first = words.next().unwrap_or_default();
+    std::iter::once(first)
+        .chain(words)
+        .find(|word| !is_assignment(word))
+        .unwrap_or(first)
+}
+
+fn is_assignment(word: &str) -> bool {
+    word.split_once('=').is_some_and(|(name, _)| {
+        !name.is_empty()
+            && !name.starts_with(|c: char| c.is_ascii_digit())
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    })
+}
+
+/// Split `input` into argv words the way a POSIX shell would for a simple
+/// command: whitespace separates words, single quotes are literal, double
+/// quotes allow backslash escapes. Unquoted shell operators are rejected
+/// since there is no shell to interpret them.
+pub fn split_argv(input: &str) -> Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    let mut in_word = false;
+    let mut chars = input.chars();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\'' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => current.push(c),
+                        None => return Err(Error::Argv("unterminated single quote".into())),
+                    }
+                }
+            }
+            '"' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => current.push(c),
+                            Some(c) => {
+                                current.push('\\');
+                                current.push(c);
+                            }
+                            None => return Err(Error::Argv("unterminated double quote".into())),
+                        },
+                        Some(c) => current.push(c),
+                        None => return Err(Error::Argv("unterminated double quote".into())),
+                    }
+                }
+            }
+            '\\' => {
+                in_word = true;
+                match chars.next() {
+                    Some(c) => current.push(c),
+                    None => return Err(Error::Argv("trailing backslash".into())),
+                }
+            }
+            '|' | '&' | ';' | '<' | '>' | '(' | ')' | '`' => {
+                return Err(Error::Argv(format!(
+                    "'{ch}' needs shell mode (direct mode runs a single program)"
+                )))
+            }
+            c if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut current));
+                    in_word = false;
+                }
+            }
+            c => {
+                in_word = true;
+                current.push(c);
+            }
+        }
+    }
+    if in_word {
+        words.push(current);
+    }
+    if words.is_empty() {
+        return Err(Error::Argv("empty command".into()));
+    }
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::{buffer::Buffer, layout::Rect, widgets::Paragraph, widgets::Widget};
+
+    /// Column at which the terminal draws the `x` following `text`.
+    fn rendered_column(text: &str) -> usize {
+        let area = Rect::new(0, 0, 20, 1);
+        let mut buf = Buffer::empty(area);
+        Paragraph::new(format!("{text}x")).render(area, &mut buf);
+        (0..area.width)
+            .find(|&x| buf.get(x, 0).symbol() == "x")
+            .expect("x is drawn") as usize
+    }
+
+    #[test]
+    fn simple_commands_split_at_operators() {
+        assert_eq!(
+            simple_commands("ls -l | grep x && echo 'a|b' ; make 2>&1 >/dev/null"),
+            Some(vec![
+                "ls -l",
+                "grep x",
+                "echo 'a|b'",
+                "make 2>&1 >/dev/null"
+            ])
+        );
+        assert_eq!(
+            simple_commands("echo \"${HOME}\" ${PATH}"),
+            Some(vec!["echo \"${HOME}\" ${PATH}"])
+        );
+        assert_eq!(
+            simple_commands("echo '(' \"{\" \\("),
+            Some(vec!["echo '(' \"{\" \\("])
+        );
+    }
+
+    #[test]
+    fn hidden_commands_are_not_simple() {
+        for cmd in [
+            "echo $(rm x)",
+            "echo `rm x`",
+            "(rm x)",
+            "ls; (cd / && rm x)",
+            "{ rm x; }",
+            "diff <(rm x) y",
+            "tee >(rm x)",
+            "ls > out.txt",
+            "! rm x",
+            "time rm x",
+            "sudo rm x",
+            "env FOO=1 rm x",
+            "command rm x",
+            "exec rm x",
+            "ls | xargs rm",
+            "FOO=1 /usr/bin/sudo rm x",
+        ] {
+            assert_eq!(simple_commands(cmd), None, "{cmd:?}");
+        }
+    }
+
+    #[test]
+    fn caret_steps_over_a_zwj_sequence_as_one_cluster() {
+        let family = "👨\u{200d}👩\u{200d}👧";
+        let text = format!("a{family}b");
+        let after = next_grapheme_boundary(&text, 1);
+        assert_eq!(after, 1 + family.len());
+        assert_eq!(prev_grapheme_boundary(&text, after), 1);
+        assert_eq!(cursor_column(&text, after), 3);
+        assert_eq!(cursor_column(family, family.len()), rendered_column(family));
+    }
+
+    #[test]
+    fn combining_mark_stays_with_its_letter() {
+        let text = "cafe\u{301} bar";
+        let accent = text.find('\u{301}').unwrap();
+        assert_eq!(next_grapheme_boundary(text, 3), accent + 2);
+        assert_eq!(cursor_column(text, accent + 2), 4);
+        assert_eq!(prev_word_boundary(text, accent + 2), 0);
+        assert_eq!(next_word_boundary(text, 0), accent + 2);
+        assert_eq!(rendered_column("cafe\u{301}"), 4);
+    }
+
+    #[test]
+    fn wide_characters_take_two_columns() {
+        let text = "日本語";
+        assert_eq!(cursor_column(text, 3), 2);
+        assert_eq!(cursor_column(text, text.len()), 6);
+        assert_eq!(rendered_column(text), 6);
+        // column 3 is the right half of 本: the caret goes to its start
+        assert_eq!(column_offset(text, 3), 3);
+        assert_eq!(column_offset(text, 4), 6);
+    }
+
+    #[test]
+    fn caret_follows_the_renderer_not_the_string_width() {
+        // unicode-width joins lam-alef into one column, but they are two
+        // clusters and are drawn a cell each
+        let lam_alef = "\u{644}\u{627}";
+        assert_eq!(UnicodeWidthStr::width(lam_alef), 1);
+        assert_eq!(cursor_column(lam_alef, lam_alef.len()), 2);
+        assert_eq!(rendered_column(lam_alef), 2);
+    }
+}
@@ -1,32 +1,305 @@
+use std::collections::HashSet;
 use std::fs;
 use std::path::{Path, PathBuf};
-use anyhow::Result;
-use crate::history::App;
+use serde::{Deserialize, Serialize};
+use crate::config::{Config, ProjectRc};
+use crate::error::Result;
+use crate::execution::ExecMode;
+use crate::history::{App, Pane};
+use crate::Error;
 
 pub const HISTORY_LIMIT: usize = 500;
 
+/// Overrides both the data and the config directory when set, for tests,
+/// sandboxes and portable installs.
+pub const DATA_DIR_VAR: &str = "PIPETUI_DATA_DIR";
+
+/// `$PIPETUI_DATA_DIR` if set and non-empty.
+fn data_dir_override() -> Option<PathBuf> {
+    std::env::var_os(DATA_DIR_VAR)
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+}
+
+/// Directory for history and other state, created on first use.
+pub fn data_dir() -> Result<PathBuf> {
+    let proj = match data_dir_override() {
+        Some(dir) => dir,
+        None => dirs::cache_dir()
+            .or_else(dirs::data_dir)
+            .ok_or(Error::NoDir("cache or data"))?
+            .join("pipetui"),
+    };
+    fs::create_dir_all(&proj).map_err(Error::io(&proj))?;
+    Ok(proj)
+}
+
 pub fn history_file() -> Result<PathBuf> {
-    let proj = dirs::cache_dir()
-        .or_else(|| dirs::data_dir())
-        .ok_or_else(|| anyhow::anyhow!("no cache or data dir"))?
+    Ok(data_dir()?.join("history.json"))
+}
+
+pub fn log_file() -> Result<PathBuf> {
+    Ok(data_dir()?.join("pipetui.log"))
+}
+
+/// Input saved while idle, so a crash or a closed terminal doesn't lose
+/// a pipeline being composed. Removed on a clean exit.
+pub fn draft_file() -> Result<PathBuf> {
+    Ok(data_dir()?.join("draft.txt"))
+}
+
+pub fn notes_file() -> Result<PathBuf> {
+    Ok(data_dir()?.join("notes.txt"))
+}
+
+/// The saved notes, empty when there are none.
+pub fn load_notes() -> String {
+    notes_file()
+        .and_then(|path| fs::read_to_string(&path).map_err(Error::io(path)))
+        .unwrap_or_default()
+}
+
+pub fn save_notes(text: &str) {
+    if let Ok(path) = notes_file() {
+        let _ = fs::write(path, text);
+    }
+}
+
+pub fn load_draft() -> Option<String> {
+    let text = fs::read_to_string(draft_file().ok()?).ok()?;
+    (!text.is_empty()).then_some(text)
+}
+
+pub fn save_draft(text: &str) {
+    if let Ok(path) = draft_file() {
+        let _ = if text.is_empty() {
+            fs::remove_file(path)
+        } else {
+            fs::write(path, text)
+        };
+    }
+}
+
+pub fn clear_draft() {
+    if let Ok(path) = draft_file() {
+        let _ = fs::remove_file(path);
+    }
+}
+
+/// View preferences remembered between launches in `state.json`; saved
+/// on a clean exit.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UiState {
+    pub exec_mode: Option<ExecMode>,
+    pub zoom: Option<Pane>,
+}
+
+pub fn state_file() -> Result<PathBuf> {
+    Ok(data_dir()?.join("state.json"))
+}
+
+/// Saved preferences; missing or unreadable state just means defaults.
+pub fn load_state() -> UiState {
+    state_file()
+        .ok()
+        .and_then(|path| fs::File::open(path).ok())
+        .and_then(|file| serde_json::from_reader(file).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_state(state: &UiState) {
+    if let Ok(file) = state_file().and_then(|path| fs::File::create(&path).map_err(Error::io(path)))
+    {
+        let _ = serde_json::to_writer_pretty(file, state);
+    }
+}
+
+pub fn config_file() -> Result<PathBuf> {
+    if data_dir_override().is_some() {
+        return Ok(data_dir()?.join("config.json"));
+    }
+    let dir = dirs::config_dir()
+        .ok_or(Error::NoDir("config"))?
         .join("pipetui");
-    fs::create_dir_all(&proj)?;
-    Ok(proj.join("history.json"))
+    Ok(dir.join("config.json"))
+}
+
+pub fn load_config(path: &Path) -> Result<Config> {
+    if !path.exists() {
+        return Ok(Config::default());
+    }
+    let file = fs::File::open(path).map_err(Error::io(path))?;
+    serde_json::from_reader(file).map_err(Error::parse(path))
+}
+
+pub const PROJECT_RC: &str = ".pipetuirc";
+
+/// Nearest `.pipetuirc` in `start` or its ancestors.
+pub fn find_project_rc(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .map(|dir| dir.join(PROJECT_RC))
+        .find(|path| path.is_file())
+}
+
+pub fn load_project_rc(path: &Path) -> Result<ProjectRc> {
+    let file = fs::File::open(path).map_err(Error::io(path))?;
+    serde_json::from_reader(file).map_err(Error::parse(path))
 }
 
 pub fn load_history(path: &Path) -> Result<Vec<String>> {
     if !path.exists() {
         return Ok(Vec::new());
     }
-    let file = fs::File::open(path)?;
-    let hist: Vec<String> = serde_json::from_reader(file)?;
-    Ok(hist)
+    let file = fs::File::open(path).map_err(Error::io(path))?;
+    serde_json::from_reader(file).map_err(Error::parse(path))
+}
+
+/// Remove the saved history file and its never-ran marks, if any.
+pub fn clear_history(app: &App) -> Result<()> {
+    let Some(path) = &app.history_path else {
+        return Ok(());
+    };
+    for path in [path.clone(), never_ran_file(path)] {
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(Error::io(path)(e)),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Next to the history file: the commands in it whose latest run couldn't
+/// be started, so they are still marked as never run after a restart.
+fn never_ran_file(history: &Path) -> PathBuf {
+    history.with_extension("never-ran.json")
+}
+
+/// The commands of the history at `history` marked as never run; none
+/// when the file is missing or unreadable.
+pub fn load_never_ran(history: &Path) -> HashSet<String> {
+    fs::File::open(never_ran_file(history))
+        .ok()
+        .and_then(|file| serde_json::from_reader(file).ok())
+        .unwrap_or_default()
+}
+
+/// Mark `cmd` as never run (or as run after all) in the file next to the
+/// history at `history`, re-read first like the history, and return the
+/// marks. Commands no longer in the history are dropped.
+pub fn mark_never_ran(history: &Path, cmd: &str, never_ran: bool) -> Result<HashSet<String>> {
+    let mut marks = load_never_ran(history);
+    if never_ran {
+        marks.insert(cmd.to_string());
+    } else {
+        marks.remove(cmd);
+    }
+    let entries: HashSet<String> = load_history(history)
+        .unwrap_or_default()
+        .into_iter()
+        .collect();
+    marks.retain(|cmd| entries.contains(cmd));
+    let mut sorted: Vec<&String> = marks.iter().collect();
+    sorted.sort();
+    let path = never_ran_file(history);
+    let json = serde_json::to_string_pretty(&sorted).map_err(Error::parse(&path))?;
+    fs::write(&path, json).map_err(Error::io(&path))?;
+    Ok(marks)
 }
 
-pub fn save_history(app: &App) {
-    if let Some(path) = &app.history_path {
-        if let Ok(file) = fs::File::create(path) {
-            let _ = serde_json::to_writer_pretty(file, &app.history);
+/// `saved`, the history file as other instances may have left it, with
+/// `additions` (the entries added here since the last save) after it as
+/// the newest. As when typing, an entry repeating the one before it is
+/// dropped, and the oldest beyond `HISTORY_LIMIT` go.
+pub fn merge_history(mut saved: Vec<String>, additions: &[String]) -> Vec<String> {
+    for entry in additions {
+        if saved.last() != Some(entry) {
+            saved.push(entry.clone());
         }
     }
+    let excess = saved.len().saturating_sub(HISTORY_LIMIT);
+    saved.drain(..excess);
+    saved
+}
+
+/// Merge `additions` into the history file at `path` and return the
+/// merged history. The file is re-read right before writing, so entries
+/// saved meanwhile by another instance survive, and replaced by renaming
+/// a temporary file over it, so a crash mid-write can't truncate it. An
+/// unreadable file is replaced, as before merging existed.
+pub fn save_history(path: &Path, additions: &[String]) -> Result<Vec<String>> {
+    let saved = match load_history(path) {
+        Ok(saved) => saved,
+        Err(Error::Parse { .. }) => Vec::new(),
+        Err(e) => return Err(e),
+    };
+    let merged = merge_history(saved, additions);
+    if additions.is_empty() {
+        return Ok(merged);
+    }
+    let json = serde_json::to_string_pretty(&merged).map_err(Error::parse(path))?;
+    let tmp = path.with_extension(format!("json.{}.tmp", std::process::id()));
+    fs::write(&tmp, json).map_err(Error::io(&tmp))?;
+    if let Err(e) = fs::rename(&tmp, path) {
+        let _ = fs::remove_file(&tmp);
+        return Err(Error::io(path)(e));
+    }
+    Ok(merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(list: &[&str]) -> Vec<String> {
+        list.iter().map(|entry| entry.to_string()).collect()
+    }
+
+    #[test]
+    fn additions_go_after_what_others_saved() {
+        // saved by another instance since this one loaded "a"
+        let saved = entries(&["a", "other 1", "other 2"]);
+        let merged = merge_history(saved, &entries(&["mine 1", "mine 2"]));
+        assert_eq!(merged, ["a", "other 1", "other 2", "mine 1", "mine 2"]);
+    }
+
+    #[test]
+    fn consecutive_duplicates_are_dropped() {
+        let merged = merge_history(entries(&["ls", "make"]), &entries(&["make", "ls", "ls"]));
+        assert_eq!(merged, ["ls", "make", "ls"]);
+    }
+
+    #[test]
+    fn oldest_entries_beyond_the_limit_go() {
+        let saved: Vec<String> = (0..HISTORY_LIMIT).map(|n| format!("echo {n}")).collect();
+        let merged = merge_history(saved, &entries(&["new 1", "new 2"]));
+        assert_eq!(merged.len(), HISTORY_LIMIT);
+        assert_eq!(merged[0], "echo 2");
+        assert_eq!(merged[HISTORY_LIMIT - 2..], ["new 1", "new 2"]);
+    }
+
+    #[test]
+    fn saving_twice_keeps_both_and_the_file_in_step() {
+        let path =
+            std::env::temp_dir().join(format!("pipetui-test-{}-save.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+        assert_eq!(save_history(&path, &entries(&["one"])).unwrap(), ["one"]);
+        let merged = save_history(&path, &entries(&["two", "two"])).unwrap();
+        assert_eq!(merged, ["one", "two"]);
+        assert_eq!(load_history(&path).unwrap(), merged);
+        // nothing to add: the file is read but left alone
+        assert_eq!(save_history(&path, &[]).unwrap(), merged);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn unreadable_history_is_replaced() {
+        let path =
+            std::env::temp_dir().join(format!("pipetui-test-{}-bad.json", std::process::id()));
+        fs::write(&path, "not json").unwrap();
+        assert_eq!(save_history(&path, &entries(&["ls"])).unwrap(), ["ls"]);
+        assert_eq!(load_history(&path).unwrap(), ["ls"]);
+        let _ = fs::remove_file(&path);
+    }
 }
@@ -0,0 +1,108 @@
+use unicode_width::UnicodeWidthStr;
+
+/// Lines looked at when detecting a table; longer output stays text.
+pub const TABLE_SCAN_LIMIT: usize = 10_000;
+
+/// Output that looks like whitespace-separated columns (`ls -l`, `df`,
+/// `ps`), split into cells for the table view.
+#[derive(Debug, Default)]
+pub struct Tabular {
+    /// Names of the columns, when the first line holds them.
+    pub header: Option<Vec<String>>,
+    /// Each row with the index of the line it came from.
+    pub rows: Vec<(usize, Vec<String>)>,
+    /// Display width of each column.
+    pub widths: Vec<u16>,
+    /// Columns whose cells are all numbers (sizes and percentages
+    /// included), aligned right.
+    pub numeric: Vec<bool>,
+}
+
+/// Split `lines` into columns if they look tabular: most of them have the
+/// same number of whitespace-separated fields and nearly all at least
+/// that many, the extra fields of the rest going to the last column
+/// (file names with spaces, `df`'s "Mounted on"). Blank lines are skipped
+/// and the few short ones (`ls -l`'s "total") fill what cells they can.
+pub fn detect(lines: &[String]) -> Option<Tabular> {
+    if lines.len() > TABLE_SCAN_LIMIT {
+        return None;
+    }
+    let lines: Vec<(usize, &str)> = lines
+        .iter()
+        .map(|line| line.trim())
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty())
+        .collect();
+    let counts: Vec<usize> = lines
+        .iter()
+        .map(|(_, line)| line.split_whitespace().count())
+        .collect();
+    // the most common field count, the wider one on a tie
+    let mut tally = std::collections::BTreeMap::new();
+    for &count in &counts {
+        *tally.entry(count).or_insert(0usize) += 1;
+    }
+    let (&columns, &exact) = tally.iter().max_by_key(|&(count, n)| (n, count))?;
+    let fitting = counts.iter().filter(|&&count| count >= columns).count();
+    if columns < 2 || exact < 2 || exact * 5 < lines.len() * 3 || fitting * 10 < lines.len() * 9 {
+        return None;
+    }
+    let mut rows: Vec<(usize, Vec<String>)> = lines
+        .iter()
+        .map(|&(idx, line)| (idx, split_fields(line, columns)))
+        .collect();
+    let mut numeric = vec![false; columns];
+    for (col, numeric) in numeric.iter_mut().enumerate() {
+        let mut cells = rows.iter().skip(1).filter_map(|(_, cells)| cells.get(col));
+        *numeric = cells.clone().next().is_some() && cells.all(|cell| is_number(cell));
+    }
+    // a first row of words above numbers names the columns
+    let header = match rows.first() {
+        Some((_, first))
+            if numeric.iter().any(|&n| n)
+                && first
+                    .iter()
+                    .zip(&numeric)
+                    .all(|(cell, &numeric)| !numeric || !is_number(cell)) =>
+        {
+            Some(rows.remove(0).1)
+        }
+        _ => None,
+    };
+    let mut widths = vec![0u16; columns];
+    for cells in header.iter().chain(rows.iter().map(|(_, cells)| cells)) {
+        for (width, cell) in widths.iter_mut().zip(cells) {
+            *width = (*width).max(cell.width().min(u16::MAX as usize) as u16);
+        }
+    }
+    Some(Tabular {
+        header,
+        rows,
+        widths,
+        numeric,
+    })
+}
+
+/// The whitespace-separated fields of `line`, the last of `columns`
+/// keeping the rest of the line as it is.
+fn split_fields(line: &str, columns: usize) -> Vec<String> {
+    let mut cells = Vec::new();
+    let mut rest = line;
+    while !rest.is_empty() {
+        if cells.len() + 1 == columns {
+            cells.push(rest.to_string());
+            break;
+        }
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        cells.push(rest[..end].to_string());
+        rest = rest[end..].trim_start();
+    }
+    cells
+}
+
+/// `42`, `-1.5`, `87%`, `1.2G`.
+fn is_number(cell: &str) -> bool {
+    cell.trim_end_matches(['%', 'B', 'K', 'M', 'G', 'T', 'P', 'k'])
+        .parse::<f64>()
+        .is_ok()
+}
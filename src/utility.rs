@@ -1,31 +1,504 @@
-use std::io;
-use anyhow::Result;
+use std::borrow::Cow;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use base64::prelude::{Engine as _, BASE64_STANDARD};
 use crossterm::{execute, terminal};
 use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
 use ratatui::backend::CrosstermBackend;
-use ratatui::Terminal;
+use ratatui::{Terminal, TerminalOptions, Viewport};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+use crate::config::ControlChars;
+use crate::error::Result;
+use crate::execution;
+use crate::parser::shell_quote;
+use crate::Error;
 
-pub fn setup_terminal() -> Result<Terminal<CrosstermBackend<io::Stdout>>> {
+/// Without the alternate screen the UI is drawn inline in the normal
+/// screen, so whatever it showed stays in the scrollback after exit.
+pub fn setup_terminal(alternate_screen: bool) -> Result<Terminal<CrosstermBackend<io::Stdout>>> {
+    try_setup_terminal(alternate_screen).map_err(Error::Terminal)
+}
+
+fn try_setup_terminal(
+    alternate_screen: bool,
+) -> io::Result<Terminal<CrosstermBackend<io::Stdout>>> {
     enable_raw_mode()?;
     let mut stdout = io::stdout();
-    execute!(
-        stdout,
-        terminal::EnterAlternateScreen,
-        crossterm::cursor::Hide
-    )?;
+    if alternate_screen {
+        execute!(stdout, terminal::EnterAlternateScreen)?;
+    }
+    execute!(stdout, crossterm::cursor::Hide)?;
     let backend = CrosstermBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
+    let mut terminal = if alternate_screen {
+        Terminal::new(backend)?
+    } else {
+        let (_, rows) = terminal::size()?;
+        Terminal::with_options(
+            backend,
+            TerminalOptions {
+                viewport: Viewport::Inline(rows),
+            },
+        )?
+    };
     terminal.clear()?;
     Ok(terminal)
 }
 
-pub fn restore_terminal() -> Result<()> {
+pub fn restore_terminal(alternate_screen: bool) -> Result<()> {
+    try_restore_terminal(alternate_screen).map_err(Error::Terminal)
+}
+
+fn try_restore_terminal(alternate_screen: bool) -> io::Result<()> {
     disable_raw_mode()?;
     let mut stdout = io::stdout();
-    execute!(
-        stdout,
-        crossterm::cursor::Show,
-        terminal::LeaveAlternateScreen
-    )?;
+    execute!(stdout, crossterm::cursor::Show)?;
+    if alternate_screen {
+        execute!(stdout, terminal::LeaveAlternateScreen)?;
+    } else {
+        // leave the last frame intact and put the shell prompt below it
+        let (_, rows) = terminal::size()?;
+        execute!(stdout, crossterm::cursor::MoveTo(0, rows.saturating_sub(1)))?;
+        writeln!(stdout)?;
+    }
+    Ok(())
+}
+
+/// Pipe `text` into `$PAGER` (else `less -R`) run through `shell`, and
+/// wait for it. The caller hands the terminal over first.
+pub fn page_text(text: &str, shell: Option<&str>) -> Result<()> {
+    let pager = std::env::var("PAGER")
+        .ok()
+        .filter(|pager| !pager.trim().is_empty())
+        .unwrap_or_else(|| "less -R".into());
+    let (shell, flag) = execution::shell_invocation(shell);
+    let mut child = Command::new(&shell)
+        .args([flag, &pager])
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|source| Error::ViewerStart {
+            kind: "pager",
+            program: pager.clone(),
+            source,
+        })?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // a pager quit early closes the pipe; that's not an error
+        let _ = stdin.write_all(text.as_bytes());
+    }
+    let status = child.wait().map_err(|source| Error::ViewerStart {
+        kind: "pager",
+        program: pager.clone(),
+        source,
+    })?;
+    if !status.success() {
+        return Err(Error::ViewerFailed {
+            kind: "pager",
+            program: pager,
+            status,
+        });
+    }
     Ok(())
 }
+
+/// Open a temporary file holding `text` in `$VISUAL`, `$EDITOR` or `vi`,
+/// run through `shell`, and wait for it. Edits are discarded.
+pub fn edit_text(text: &str, shell: Option<&str>) -> Result<()> {
+    let editor = ["VISUAL", "EDITOR"]
+        .into_iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| "vi".into());
+    let (path, mut file) = create_temp_file("pipetui-output")?;
+    let written = file.write_all(text.as_bytes());
+    drop(file);
+    if let Err(err) = written {
+        let _ = std::fs::remove_file(&path);
+        return Err(Error::io(&path)(err));
+    }
+    let (shell, flag) = execution::shell_invocation(shell);
+    let cmd = format!("{editor} {}", shell_quote(&path.to_string_lossy()));
+    let mut command = Command::new(&shell);
+    command.args([flag, &cmd]);
+    // stdin may have been a pipe read at launch; the editor needs the tty
+    #[cfg(unix)]
+    if !io::IsTerminal::is_terminal(&io::stdin()) {
+        if let Ok(tty) = std::fs::File::open("/dev/tty") {
+            command.stdin(tty);
+        }
+    }
+    let status = command.status();
+    let _ = std::fs::remove_file(&path);
+    let status = status.map_err(|source| Error::ViewerStart {
+        kind: "editor",
+        program: editor.clone(),
+        source,
+    })?;
+    if !status.success() {
+        return Err(Error::ViewerFailed {
+            kind: "editor",
+            program: editor,
+            status,
+        });
+    }
+    Ok(())
+}
+
+/// Create a new file in the temp dir, readable by the user only. The name
+/// is never one that already exists, so a file or symlink planted there by
+/// someone else is left alone instead of written through.
+fn create_temp_file(prefix: &str) -> Result<(std::path::PathBuf, std::fs::File)> {
+    let dir = std::env::temp_dir();
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.subsec_nanos());
+    let mut attempt = 0u32;
+    loop {
+        let path = dir.join(format!(
+            "{prefix}-{}-{nanos}-{attempt}.txt",
+            std::process::id()
+        ));
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        match options.open(&path) {
+            Ok(file) => return Ok((path, file)),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists && attempt < 100 => attempt += 1,
+            Err(err) => return Err(Error::io(&path)(err)),
+        }
+    }
+}
+
+/// Send `tracing` output to `pipetui.log` in the data dir when
+/// `PIPETUI_LOG` is set (to a filter such as `debug` or
+/// `pipetui=trace`). Without it nothing is recorded. Never writes to the
+/// terminal, which belongs to the TUI.
+pub fn init_logging() {
+    let Ok(filter) = std::env::var("PIPETUI_LOG") else {
+        return;
+    };
+    let Ok(path) = crate::persistence::log_file() else {
+        return;
+    };
+    let Ok(file) = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+    else {
+        return;
+    };
+    let _ = tracing_subscriber::fmt()
+        .with_env_filter(tracing_subscriber::EnvFilter::new(filter))
+        .with_writer(std::sync::Mutex::new(file))
+        .with_ansi(false)
+        .try_init();
+}
+
+pub fn ring_bell() {
+    let mut stdout = io::stdout();
+    let _ = stdout.write_all(b"\x07");
+    let _ = stdout.flush();
+}
+
+/// Put `text` on the system clipboard through the terminal (OSC 52), which
+/// also works over SSH. Terminals without OSC 52 support ignore it.
+pub fn copy_to_clipboard(text: &str) {
+    let mut stdout = io::stdout();
+    let encoded = BASE64_STANDARD.encode(text);
+    let _ = write!(stdout, "\x1b]52;c;{encoded}\x07");
+    let _ = stdout.flush();
+}
+
+/// `text` with ANSI escape sequences (CSI such as colors, OSC such as
+/// titles, charset switches and other short escapes) removed.
+pub fn strip_ansi(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if ch != '\x1b' {
+            out.push(ch);
+            continue;
+        }
+        match chars.next() {
+            // CSI: parameters and intermediates up to a final byte in @..~
+            Some('[') => {
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            // OSC: up to BEL or ST (ESC \)
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\x07' {
+                        break;
+                    }
+                    if c == '\x1b' && chars.next_if_eq(&'\\').is_some() {
+                        break;
+                    }
+                }
+            }
+            // nF escapes such as `ESC ( B`: intermediates, then one final byte
+            Some(' '..='/') => {
+                while chars.next_if(|c| (' '..='/').contains(c)).is_some() {}
+                chars.next();
+            }
+            _ => {}
+        }
+    }
+    out
+}
+
+/// `line` with C0 control characters (except tab) and DEL drawn visibly
+/// per `mode`. Borrowed unchanged when there is nothing to replace.
+pub fn show_control_chars(line: &str, mode: ControlChars) -> Cow<'_, str> {
+    let is_control = |ch: char| ch.is_ascii_control() && ch != '\t';
+    if mode == ControlChars::Raw || !line.contains(is_control) {
+        return Cow::Borrowed(line);
+    }
+    let mut out = String::with_capacity(line.len() + 8);
+    for ch in line.chars() {
+        if !is_control(ch) {
+            out.push(ch);
+            continue;
+        }
+        let code = ch as u32;
+        match mode {
+            ControlChars::Caret => {
+                out.push('^');
+                out.push(char::from_u32(code ^ 0x40).unwrap_or('?'));
+            }
+            _ => out.push(
+                char::from_u32(if code == 0x7f { 0x2421 } else { 0x2400 + code }).unwrap_or(ch),
+            ),
+        }
+    }
+    Cow::Owned(out)
+}
+
+/// `text` made safe to paste where invisible bytes confuse: ANSI escapes
+/// removed and other control characters except tabs and newlines in caret
+/// notation, so a stray `\r` shows as `^M`.
+pub fn sanitize(text: &str) -> String {
+    strip_ansi(text)
+        .split('\n')
+        .map(|line| show_control_chars(line, ControlChars::Caret))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// `text` as a Markdown code block, fenced with more backticks than any
+/// run of them inside it.
+pub fn markdown_fence(text: &str) -> String {
+    let longest = text.split(|ch| ch != '`').map(str::len).max().unwrap_or(0);
+    let fence = "`".repeat(longest.max(2) + 1);
+    format!("{fence}\n{text}\n{fence}\n")
+}
+
+/// Replace each tab in `line` with spaces up to the next multiple of
+/// `tab_width` columns, counting display width. `0` keeps tabs as they are.
+pub fn expand_tabs(line: &str, tab_width: usize) -> String {
+    if tab_width == 0 || !line.contains('\t') {
+        return line.to_string();
+    }
+    let mut out = String::with_capacity(line.len() + tab_width);
+    let mut col = 0;
+    for ch in line.chars() {
+        if ch == '\t' {
+            let spaces = tab_width - col % tab_width;
+            out.push_str(&" ".repeat(spaces));
+            col += spaces;
+        } else {
+            out.push(ch);
+            col += ch.width().unwrap_or(0);
+        }
+    }
+    out
+}
+
+/// `cat | grep | sort` from the programs of a pipeline, fit into `width`
+/// columns: stages in the middle collapse into `…` first, keeping the
+/// ends, and only then is the text cut short.
+pub fn breadcrumb(programs: &[&str], width: usize) -> String {
+    let fits = |text: &str| UnicodeWidthStr::width(text) <= width;
+    let full = programs.join(" | ");
+    if fits(&full) {
+        return full;
+    }
+    for keep in (2..programs.len()).rev() {
+        let head = keep / 2;
+        let parts: Vec<&str> = programs[..head]
+            .iter()
+            .copied()
+            .chain(Some("…"))
+            .chain(programs[programs.len() - (keep - head)..].iter().copied())
+            .collect();
+        let text = parts.join(" | ");
+        if fits(&text) {
+            return text;
+        }
+    }
+    let mut text = String::new();
+    let mut used = 0;
+    for ch in full.chars() {
+        let w = ch.width().unwrap_or(0);
+        if used + w + 1 > width {
+            break;
+        }
+        used += w;
+        text.push(ch);
+    }
+    if width > 0 {
+        text.push('…');
+    }
+    text
+}
+
+/// `n` with thousands separators: `1,234,567`.
+pub fn format_count(n: usize) -> String {
+    let digits = n.to_string();
+    let mut out = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, ch) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(ch);
+    }
+    out
+}
+
+/// Byte size in binary units for the status line: `512 B`, `1.2 KB`,
+/// `340.0 MB`.
+pub fn format_bytes(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+/// Compact duration for the status line: `850ms`, `2.41s`, `3m07s`.
+pub fn format_duration(duration: Duration) -> String {
+    let ms = duration.as_millis();
+    if ms < 1000 {
+        format!("{ms}ms")
+    } else if ms < 60_000 {
+        format!("{:.2}s", duration.as_secs_f64())
+    } else {
+        let secs = duration.as_secs();
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    }
+}
+
+/// Interval as typed by a user: `2s`, `0.5s`.
+pub fn format_interval(interval: Duration) -> String {
+    let secs = interval.as_secs_f64();
+    if secs.fract() == 0.0 {
+        format!("{secs:.0}s")
+    } else {
+        format!("{secs}s")
+    }
+}
+
+/// Wall-clock time of `at` as `HH:MM:SS`: local time on Unix, UTC
+/// elsewhere.
+pub fn format_clock(at: SystemTime) -> String {
+    let secs = at.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    #[cfg(unix)]
+    {
+        let time = secs as libc::time_t;
+        // SAFETY: `tm` is plain data, and `localtime_r` only writes to it
+        let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+        if !unsafe { libc::localtime_r(&time, &mut tm) }.is_null() {
+            return format!("{:02}:{:02}:{:02}", tm.tm_hour, tm.tm_min, tm.tm_sec);
+        }
+    }
+    let day = secs % 86_400;
+    format!("{:02}:{:02}:{:02}", day / 3600, day / 60 % 60, day % 60)
+}
+
+/// Fallback when neither `--width`, `COLUMNS` nor the terminal say otherwise.
+pub const DEFAULT_WIDTH: u16 = 80;
+
+/// Width for wrapping outside the TUI: `COLUMNS` if set, else the
+/// detected terminal width, else `DEFAULT_WIDTH`.
+pub fn terminal_width() -> u16 {
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|cols| cols.trim().parse::<u16>().ok())
+        .filter(|&cols| cols > 0)
+        .or_else(|| terminal::size().ok().map(|(cols, _)| cols))
+        .filter(|&cols| cols > 0)
+        .unwrap_or(DEFAULT_WIDTH)
+}
+
+/// Split `line` into rows of at most `width` display columns.
+pub fn wrap_line(line: &str, width: usize) -> Vec<&str> {
+    if width == 0 || line.is_empty() {
+        return vec![line];
+    }
+    let mut rows = Vec::new();
+    let mut row_start = 0;
+    let mut row_width = 0;
+    for (idx, ch) in line.char_indices() {
+        let ch_width = UnicodeWidthChar::width(ch).unwrap_or(0);
+        if row_width + ch_width > width && idx > row_start {
+            rows.push(&line[row_start..idx]);
+            row_start = idx;
+            row_width = 0;
+        }
+        row_width += ch_width;
+    }
+    rows.push(&line[row_start..]);
+    rows
+}
+
+/// Rows `line` occupies when wrapped at `width` columns.
+pub fn wrapped_rows(line: &str, width: usize) -> usize {
+    if width == 0 {
+        return 1;
+    }
+    UnicodeWidthStr::width(line).div_ceil(width).max(1)
+}
+
+/// Index of the first of `lines` to show so the tail fills `height` rows
+/// when wrapped at `width` columns. At least the last line is shown.
+pub fn tail_start(lines: &[&str], height: usize, width: usize) -> usize {
+    let mut rows = 0;
+    let mut start = lines.len();
+    while start > 0 {
+        rows += wrapped_rows(lines[start - 1], width);
+        if rows > height && start < lines.len() {
+            break;
+        }
+        start -= 1;
+    }
+    start
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn temp_files_are_new_and_private() {
+        let (first, _) = create_temp_file("pipetui-test").unwrap();
+        let (second, _) = create_temp_file("pipetui-test").unwrap();
+        assert_ne!(first, second);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&first).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        let _ = std::fs::remove_file(first);
+        let _ = std::fs::remove_file(second);
+    }
+}
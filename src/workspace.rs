@@ -0,0 +1,216 @@
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use crate::execution::{spawn_worker, UiMsg, WorkerMsg};
+use crate::history::App;
+
+/// Upper bound on side-by-side panes.
+pub const MAX_SESSIONS: usize = 2;
+
+/// One pipeline editor: its `App` state plus a dedicated worker.
+pub struct Session {
+    pub app: App,
+    pub tx_worker: Sender<WorkerMsg>,
+    rx_ui: Receiver<UiMsg>,
+    worker: JoinHandle<()>,
+}
+
+impl Session {
+    pub fn new() -> Self {
+        let (tx_worker, rx_worker) = unbounded::<WorkerMsg>();
+        let (tx_ui, rx_ui) = unbounded::<UiMsg>();
+        let worker = spawn_worker(rx_worker, tx_ui);
+        Self {
+            app: App::new(),
+            tx_worker,
+            rx_ui,
+            worker,
+        }
+    }
+
+    /// Apply worker results without blocking, then start any due run.
+    /// Returns whether anything happened that changes the screen.
+    pub fn pump(&mut self) -> bool {
+        let app = &mut self.app;
+        let mut changed = false;
+        while let Ok(msg) = self.rx_ui.try_recv() {
+            changed = true;
+            match msg {
+                UiMsg::Started(cmd) => {
+                    tracing::debug!(cmd = %cmd, "ui: started");
+                    app.begin_run(cmd)
+                }
+                UiMsg::Spawned(pid) => app.child_pid = Some(pid),
+                UiMsg::ScratchDir(dir) => app.scratch_dir = Some(dir),
+                UiMsg::StdoutChunk(chunk) => {
+                    tracing::trace!(bytes = chunk.len(), "ui: stdout chunk");
+                    app.append_stdout_chunk(chunk)
+                }
+                UiMsg::StderrChunk(chunk) => {
+                    tracing::trace!(bytes = chunk.len(), "ui: stderr chunk");
+                    app.append_stderr_chunk(chunk)
+                }
+                UiMsg::Finished(res) => {
+                    tracing::debug!(cmd = %res.cmd, status = %res.status, "ui: finished");
+                    app.finish_run(res)
+                }
+            }
+        }
+
+        let due = if let Some(cmd) = app.take_queued_run() {
+            tracing::debug!(cmd = %cmd, "dispatching queued run");
+            Some(cmd)
+        } else if let Some(cmd) = app.auto_run_cmd() {
+            Some(cmd)
+        } else if let Some(cmd) = app.take_watch_run() {
+            tracing::debug!(cmd = %cmd, "watch run");
+            Some(cmd)
+        } else {
+            None
+        };
+        if let Some(cmd) = due {
+            changed = true;
+            if app.prepare_run(&cmd, false) {
+                self.tx_worker.send(WorkerMsg::Run(app.run_spec(&cmd))).ok();
+            }
+        }
+        changed
+    }
+
+    /// Stop the worker, killing any running command, and wait for it.
+    pub fn shutdown(self) -> App {
+        self.tx_worker.send(WorkerMsg::Shutdown).ok();
+        let _ = self.worker.join();
+        let mut app = self.app;
+        app.notes.save_if_idle(true);
+        app
+    }
+}
+
+/// All open sessions, shown side by side, with one holding keyboard focus.
+pub struct Workspace {
+    pub sessions: Vec<Session>,
+    pub active: usize,
+}
+
+impl Workspace {
+    pub fn new() -> Self {
+        Self {
+            sessions: vec![Session::new()],
+            active: 0,
+        }
+    }
+
+    pub fn active(&self) -> &Session {
+        &self.sessions[self.active]
+    }
+
+    pub fn active_mut(&mut self) -> &mut Session {
+        &mut self.sessions[self.active]
+    }
+
+    /// Pump every session; returns whether any of them changed.
+    pub fn pump(&mut self) -> bool {
+        let mut changed = false;
+        for session in &mut self.sessions {
+            changed |= session.pump();
+        }
+        // one draft file: it follows the pane being typed in
+        self.active_mut().app.save_draft_if_idle();
+        for session in &mut self.sessions {
+            session.app.notes.save_if_idle(false);
+        }
+        changed
+    }
+
+    /// How long the main loop may wait for input before some session
+    /// needs it, at most `max`.
+    pub fn idle_timeout(&self, max: Duration) -> Duration {
+        self.sessions
+            .iter()
+            .filter_map(|session| session.app.next_wakeup())
+            .min()
+            .map_or(max, |at| {
+                at.saturating_duration_since(Instant::now()).min(max)
+            })
+    }
+
+    /// Open a second pane, or close the focused one if already split.
+    pub fn toggle_split(&mut self) {
+        // the new pane loads the notes, the remaining one reloads them
+        self.active_mut().app.notes.save_if_idle(true);
+        if self.sessions.len() < MAX_SESSIONS {
+            self.sessions.push(Session::new());
+            self.active = self.sessions.len() - 1;
+        } else {
+            let closed = self.sessions.remove(self.active);
+            closed.shutdown();
+            self.active = self.active.min(self.sessions.len() - 1);
+            self.refresh_active();
+        }
+    }
+
+    /// Cycle focus through the panes of the focused session, then on to
+    /// the next session's input.
+    pub fn focus_next(&mut self) {
+        if self.active_mut().app.focus_next() {
+            return;
+        }
+        self.active_mut().app.notes.save_if_idle(true);
+        self.active = (self.active + 1) % self.sessions.len();
+        self.refresh_active();
+    }
+
+    /// Each pane saves its runs to the history file and its notes to the
+    /// notes file, and only the focused one is edited: as focus arrives,
+    /// pick up what the other saved, so neither overwrites the other.
+    fn refresh_active(&mut self) {
+        let app = &mut self.active_mut().app;
+        app.refresh_history();
+        app.notes.reload();
+    }
+
+    /// Shut every session down; returns the state of the focused one.
+    pub fn shutdown(mut self) -> App {
+        let focused = self.sessions.remove(self.active);
+        for session in self.sessions {
+            session.shutdown();
+        }
+        focused.shutdown()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::Focus;
+
+    /// Move focus on until it reaches the other session.
+    fn focus_other(workspace: &mut Workspace) {
+        let from = workspace.active;
+        while workspace.active == from {
+            workspace.focus_next();
+        }
+        assert_eq!(workspace.active_mut().app.focus, Focus::Input);
+    }
+
+    #[test]
+    fn split_panes_share_the_notes() {
+        crate::history::tests::app();
+        let mut workspace = Workspace::new();
+        workspace.active_mut().app.notes.text.clear();
+        workspace.active_mut().app.notes.cursor = 0;
+        workspace.active_mut().app.notes.insert('a');
+        workspace.toggle_split();
+        assert_eq!(workspace.active().app.notes.text, "a");
+        workspace.active_mut().app.notes.insert('b');
+        focus_other(&mut workspace);
+        assert_eq!(workspace.active().app.notes.text, "ab");
+        workspace.active_mut().app.notes.insert('c');
+        focus_other(&mut workspace);
+        assert_eq!(workspace.active().app.notes.text, "abc");
+        workspace.toggle_split();
+        assert_eq!(workspace.active().app.notes.text, "abc");
+        assert_eq!(workspace.shutdown().notes.text, "abc");
+    }
+}